@@ -0,0 +1,149 @@
+use std::sync::mpsc;
+
+use serde::Deserialize;
+
+use super::File;
+use crate::{cancel, config::Config, path::RemotePath};
+
+/// How many files are asked for in each page, which is the most that a single transaction of
+/// `b2_list_file_names` is billed for
+const PAGE_SIZE: &str = "1000";
+
+/// A page of the listing, along with the listing's copy of the config if it authorised again
+/// while fetching it
+type Page = (anyhow::Result<Vec<File>>, Option<Box<Config>>);
+
+/// Every file under a prefix, following the pages of `b2_list_file_names` (or
+/// `b2_list_file_versions`) as it is iterated.  The next page is fetched in the background while
+/// the current one is being processed, so slow work on each file doesn't leave the network idle.
+///
+/// The fetching works on a copy of the config, so if it has to authorise again the new token is
+/// sent back with the page and must be given to the caller's config with [`Listing::update_auth`].
+pub struct Listing {
+    page: std::vec::IntoIter<File>,
+    pages: mpsc::Receiver<Page>,
+    /// The copy of the config after it last authorised again, if that hasn't been taken yet
+    auth: Option<Box<Config>>,
+}
+
+impl Listing {
+    /// The latest version of every file under `path`
+    pub fn files(cfg: &mut Config, path: &RemotePath) -> anyhow::Result<Self> {
+        Self::new(cfg, path, false, None)
+    }
+
+    /// Every version of every file under `path`, including hidden files and unfinished large
+    /// files, ordered by name and then newest first
+    pub fn versions(cfg: &mut Config, path: &RemotePath) -> anyhow::Result<Self> {
+        Self::new(cfg, path, true, None)
+    }
+
+    /// Every version of every file under `path` from the file at `path` itself on, so that its
+    /// versions come first and the listing can be stopped at the first other name
+    pub fn versions_from(cfg: &mut Config, path: &RemotePath) -> anyhow::Result<Self> {
+        Self::new(cfg, path, true, Some((path.key.clone(), None)))
+    }
+
+    fn new(
+        cfg: &mut Config,
+        path: &RemotePath,
+        versions: bool,
+        mut start: Option<(String, Option<String>)>,
+    ) -> anyhow::Result<Self> {
+        let bucket_id = cfg.require_bucket_id(&path.bucket)?;
+        let prefix = path.key.clone();
+        let mut cfg = cfg.clone();
+
+        // Holding one page in the channel is what makes the fetching run a page ahead
+        let (tx, rx) = mpsc::sync_channel(1);
+        std::thread::spawn(move || {
+            let mut token = cfg.auth_token.clone();
+            loop {
+                let page = next_page(&mut cfg, &bucket_id, &prefix, versions, &mut start);
+                let last = page.is_err() || start.is_none();
+                let auth = (cfg.auth_token != token).then(|| {
+                    token = cfg.auth_token.clone();
+                    Box::new(cfg.clone())
+                });
+                // The listing was dropped, so nobody wants the rest
+                if tx.send((page, auth)).is_err() || last {
+                    break;
+                }
+            }
+        });
+
+        Ok(Self {
+            page: Vec::new().into_iter(),
+            pages: rx,
+            auth: None,
+        })
+    }
+
+    /// Give `cfg` the auth token that the listing got if it had to authorise again, so that it
+    /// isn't thrown away with the listing's copy of the config
+    pub fn update_auth(&mut self, cfg: &mut Config) {
+        if let Some(auth) = self.auth.take() {
+            cfg.adopt_auth(&auth);
+        }
+    }
+}
+
+impl Iterator for Listing {
+    type Item = anyhow::Result<File>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(file) = self.page.next() {
+                return Some(Ok(file));
+            }
+            let (page, auth) = self.pages.recv().ok()?;
+            if auth.is_some() {
+                self.auth = auth;
+            }
+            match page {
+                Ok(page) => self.page = page.into_iter(),
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+}
+
+/// Fetch the page of files that starts at `start`, replacing it with where the next page starts,
+/// or `None` if this was the last one
+fn next_page(
+    cfg: &mut Config,
+    bucket_id: &str,
+    prefix: &str,
+    versions: bool,
+    start: &mut Option<(String, Option<String>)>,
+) -> anyhow::Result<Vec<File>> {
+    cancel::check()?;
+
+    let mut query = vec![
+        ("bucketId", bucket_id),
+        ("prefix", prefix),
+        ("maxFileCount", PAGE_SIZE),
+    ];
+    if let Some((name, id)) = start {
+        query.push(("startFileName", name));
+        if let Some(id) = id {
+            query.push(("startFileId", id));
+        }
+    }
+
+    let api_name = if versions {
+        "b2_list_file_versions"
+    } else {
+        "b2_list_file_names"
+    };
+    let res = cfg.list_page(api_name, &query)?;
+
+    let page = Deserialize::deserialize(res["files"].clone())?;
+    *start = match (res["nextFileName"].as_str(), res["nextFileId"].as_str()) {
+        (Some(name), id) if !versions || id.is_some() => {
+            Some((name.to_string(), id.map(String::from)))
+        }
+        _ => None,
+    };
+    Ok(page)
+}
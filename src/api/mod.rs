@@ -1,40 +1,168 @@
-use chrono::{serde::ts_milliseconds, Utc};
-use serde::Deserialize;
+// These types mirror the API responses, so the fields that nothing reads are kept for
+// deserializing and debugging with a targeted `#[allow(dead_code)]`
+
+use chrono::{serde::ts_milliseconds, DateTime, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+
+mod list;
+#[cfg(test)]
+mod tests;
+
+pub use list::Listing;
+
+/// An enum of the strings that the API sends for something, with an `Unknown` variant holding
+/// any that were added since this was written, so that new features on b2's side don't break
+/// deserializing everything else
+macro_rules! api_enum {
+    ($(#[$meta:meta])* $name:ident { $($(#[$vmeta:meta])* $variant:ident = $value:literal,)* }) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+        #[serde(from = "String", into = "String")]
+        pub enum $name {
+            $($(#[$vmeta])* $variant,)*
+            Unknown(String),
+        }
+
+        impl $name {
+            /// The string that the API uses for this
+            pub fn as_str(&self) -> &str {
+                match self {
+                    $(Self::$variant => $value,)*
+                    Self::Unknown(s) => s,
+                }
+            }
+        }
+
+        impl From<String> for $name {
+            fn from(s: String) -> Self {
+                match s.as_str() {
+                    $($value => Self::$variant,)*
+                    _ => Self::Unknown(s),
+                }
+            }
+        }
+
+        impl From<$name> for String {
+            fn from(value: $name) -> Self {
+                match value {
+                    $name::Unknown(s) => s,
+                    known => known.as_str().to_string(),
+                }
+            }
+        }
+
+        impl std::fmt::Display for $name {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                f.pad(self.as_str())
+            }
+        }
+    };
+}
+
+api_enum!(
+    /// Something that an application key is allowed to do
+    Capability {
+        ListKeys = "listKeys",
+        WriteKeys = "writeKeys",
+        DeleteKeys = "deleteKeys",
+        ListBuckets = "listBuckets",
+        ListAllBucketNames = "listAllBucketNames",
+        ReadBuckets = "readBuckets",
+        WriteBuckets = "writeBuckets",
+        DeleteBuckets = "deleteBuckets",
+        ReadBucketRetentions = "readBucketRetentions",
+        WriteBucketRetentions = "writeBucketRetentions",
+        ReadBucketEncryption = "readBucketEncryption",
+        WriteBucketEncryption = "writeBucketEncryption",
+        ReadBucketReplications = "readBucketReplications",
+        WriteBucketReplications = "writeBucketReplications",
+        ReadBucketNotifications = "readBucketNotifications",
+        WriteBucketNotifications = "writeBucketNotifications",
+        ListFiles = "listFiles",
+        ReadFiles = "readFiles",
+        ShareFiles = "shareFiles",
+        WriteFiles = "writeFiles",
+        DeleteFiles = "deleteFiles",
+        ReadFileLegalHolds = "readFileLegalHolds",
+        WriteFileLegalHolds = "writeFileLegalHolds",
+        ReadFileRetentions = "readFileRetentions",
+        WriteFileRetentions = "writeFileRetentions",
+        BypassGovernance = "bypassGovernance",
+    }
+);
+
+api_enum!(
+    /// Who can download the files in a bucket
+    BucketType {
+        AllPublic = "allPublic",
+        AllPrivate = "allPrivate",
+        /// A bucket holding snapshots made in the web UI
+        Snapshot = "snapshot",
+        Shared = "shared",
+        Restricted = "restricted",
+    }
+);
+
+api_enum!(
+    /// What a file version is
+    Action {
+        /// An unfinished large file
+        Start = "start",
+        Upload = "upload",
+        /// A marker hiding the versions before it
+        Hide = "hide",
+        /// A virtual folder, when listing with a delimiter
+        Folder = "folder",
+    }
+);
+
+api_enum!(
+    /// How a file is encrypted at rest
+    SseMode {
+        /// With keys managed by b2
+        SseB2 = "SSE-B2",
+        /// With a key given by the client for each request
+        SseC = "SSE-C",
+    }
+);
 
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct AuthResponse {
     pub account_id: String,
     pub api_info: ApiInfo,
+    #[allow(dead_code)]
     pub application_key_expiration_timestamp: Option<String>,
     pub authorization_token: String,
 }
 
-// Array [
-//      String("readBucketReplications"),
-//      String("shareFiles"),
-//      String("readFileLegalHolds"),
-//      String("listKeys"),
-//      String("writeKeys"),
-//      String("writeBucketRetentions"),
-//      String("readFiles"),
-//      String("deleteKeys"),
-//      String("readBucketRetentions"),
-//      String("writeFileLegalHolds"),
-//      String("writeFileRetentions"),
-//      String("deleteFiles"),
-//      String("readBucketEncryption"),
-//      String("readBuckets"),
-//      String("readFileRetentions"),
-//      String("writeBuckets"),
-//      String("deleteBuckets"),
-//      String("writeBucketEncryption"),
-//      String("writeBucketReplications"),
-//      String("bypassGovernance"),
-//      String("listBuckets"),
-//      String("listFiles"),
-//      String("writeFiles"),
-//  ],
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Key {
+    #[allow(dead_code)]
+    pub account_id: String,
+    pub application_key_id: String,
+    /// The bucket that the key is restricted to, if any
+    pub bucket_id: Option<String>,
+    pub capabilities: Vec<Capability>,
+    pub expiration_timestamp: Option<i64>,
+    pub key_name: String,
+    pub name_prefix: Option<String>,
+    #[allow(dead_code)]
+    #[serde(default)]
+    pub options: Vec<String>,
+}
+
+impl Key {
+    pub fn expires(&self) -> Option<DateTime<Utc>> {
+        self.expiration_timestamp
+            .and_then(|ms| Utc.timestamp_millis_opt(ms).single())
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.expires().is_some_and(|e| e <= Utc::now())
+    }
+}
 
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -47,30 +175,39 @@ pub struct ApiInfo {
 pub struct StorageApi {
     pub absolute_minimum_part_size: u64,
     pub api_url: String,
+    #[allow(dead_code)]
     pub bucket_id: Option<String>,
+    #[allow(dead_code)]
     pub bucket_name: Option<String>,
-    pub capabilities: Vec<String>,
+    #[allow(dead_code)]
+    pub capabilities: Vec<Capability>,
     pub download_url: String,
+    #[allow(dead_code)]
     pub info_type: String,
+    #[allow(dead_code)]
     pub name_prefix: Option<String>,
     pub recommended_part_size: u64,
+    #[allow(dead_code)]
     pub s3_api_url: String,
 }
 
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Bucket {
+    #[allow(dead_code)]
     pub account_id: String,
     pub bucket_id: String,
     pub bucket_info: serde_json::Value,
     pub bucket_name: String,
-    pub bucket_type: String,                // TODO enum
+    pub bucket_type: BucketType,
     pub cors_rules: Vec<serde_json::Value>, // TODO
     pub default_server_side_encryption: GenericConfig,
     pub file_lock_configuration: GenericConfig,
     pub lifecycle_rules: Vec<serde_json::Value>, // TODO
     pub options: Vec<String>,
+    #[allow(dead_code)]
     pub replication_configuration: GenericConfig,
+    #[allow(dead_code)]
     pub revision: u64,
 }
 
@@ -81,40 +218,60 @@ pub struct GenericConfig {
     pub value: serde_json::Value,
 }
 
-#[derive(Debug, Deserialize)]
-#[serde(rename_all = "camelCase")]
-pub enum Action {
-    Start,
-    Upload,
-    Hide,
-    Folder,
-}
-
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct File {
+    #[allow(dead_code)]
     pub account_id: String,
     pub action: Action,
     pub bucket_id: String,
     pub content_length: u64,
+    #[allow(dead_code)]
     pub content_md5: Option<String>,
     pub content_sha1: String,
     pub content_type: String,
     pub file_id: String,
     pub file_info: serde_json::Value,
     pub file_name: String,
-    pub file_retention: GenericConfig,
-    pub legal_hold: GenericConfig,
+    pub file_retention: Option<GenericConfig>,
+    pub legal_hold: Option<GenericConfig>,
+    #[allow(dead_code)]
     pub server_side_encryption: ServerSideEncryption,
     #[serde(with = "ts_milliseconds")]
     pub upload_timestamp: chrono::DateTime<Utc>,
 }
 
+impl File {
+    /// The modification time of the source file, as set by `X-Bz-Info-src_last_modified_millis`,
+    /// falling back to the upload timestamp if it is missing or invalid
+    pub fn last_modified(&self) -> DateTime<Utc> {
+        self.file_info["src_last_modified_millis"]
+            .as_str()
+            .and_then(|s| s.parse().ok())
+            .and_then(|ms| Utc.timestamp_millis_opt(ms).single())
+            .unwrap_or(self.upload_timestamp)
+    }
+}
+
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ServerSideEncryption {
+    #[allow(dead_code)]
     pub algorithm: Option<String>,
-    pub mode: Option<String>,
+    #[allow(dead_code)]
+    pub mode: Option<SseMode>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NotificationRule {
+    pub name: String,
+    pub event_types: Vec<String>,
+    pub is_enabled: bool,
+    pub is_suspended: bool,
+    pub object_name_prefix: String,
+    pub suspension_reason: Option<String>,
+    pub target_configuration: serde_json::Value,
 }
 
 #[derive(Debug, Deserialize)]
@@ -122,5 +279,26 @@ pub struct ServerSideEncryption {
 pub struct ApiError {
     pub code: String,
     pub message: String,
+    #[allow(dead_code)]
     pub status: u16,
+    /// The url of the request which caused this error
+    #[serde(skip)]
+    pub url: String,
+}
+
+impl std::fmt::Display for ApiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "`{}`: {} - {}", self.url, self.code, self.message)
+    }
+}
+
+impl std::error::Error for ApiError {}
+
+impl ApiError {
+    /// Whether this error was caused by a file's retention settings or legal hold
+    pub fn is_file_lock(&self) -> bool {
+        let message = self.message.to_lowercase();
+        self.code == "access_denied"
+            && (message.contains("retention") || message.contains("legal hold"))
+    }
 }
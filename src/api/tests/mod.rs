@@ -0,0 +1,144 @@
+//! Responses captured from the API (with the ids changed), checked against the models so that a
+//! change on b2's side shows up here rather than as a failed command
+
+use chrono::{TimeZone, Utc};
+use serde::de::DeserializeOwned;
+
+use super::{Action, ApiError, AuthResponse, Bucket, BucketType, Capability, File, SseMode};
+
+/// Deserialize `field` of a fixture, or the whole thing if it is `None`
+fn parse<T: DeserializeOwned>(fixture: &str, field: Option<&str>) -> T {
+    let mut json: serde_json::Value = serde_json::from_str(fixture).unwrap();
+    if let Some(field) = field {
+        json = json[field].take();
+    }
+    serde_json::from_value(json).unwrap()
+}
+
+#[test]
+fn auth() {
+    let auth: AuthResponse = parse(include_str!("auth.json"), None);
+    let storage = auth.api_info.storage_api;
+    assert_eq!(storage.api_url, "https://api001.backblazeb2.com");
+    assert_eq!(storage.recommended_part_size, 100_000_000);
+    assert_eq!(storage.absolute_minimum_part_size, 5_000_000);
+    assert!(storage.bucket_id.is_none());
+    assert!(storage.capabilities.contains(&Capability::WriteFiles));
+    assert!(auth.application_key_expiration_timestamp.is_none());
+}
+
+#[test]
+fn list_buckets() {
+    let buckets: Vec<Bucket> = parse(include_str!("list_buckets.json"), Some("buckets"));
+    assert_eq!(buckets.len(), 2);
+    assert_eq!(buckets[0].bucket_name, "photos");
+    assert_eq!(buckets[0].bucket_type, BucketType::AllPrivate);
+    assert_eq!(buckets[1].bucket_type, BucketType::AllPublic);
+    assert_eq!(buckets[1].revision, 7);
+    assert_eq!(buckets[1].cors_rules.len(), 1);
+    assert!(
+        !buckets[1]
+            .file_lock_configuration
+            .is_client_authorized_to_read
+    );
+}
+
+#[test]
+fn list_file_names() {
+    let fixture = include_str!("list_file_names.json");
+    let files: Vec<File> = parse(fixture, Some("files"));
+    assert_eq!(files.len(), 2);
+    assert!(files.iter().all(|f| matches!(f.action, Action::Upload)));
+
+    let hello = &files[0];
+    assert_eq!(hello.file_name, "notes/hello.txt");
+    assert_eq!(hello.content_length, 7);
+    assert_eq!(
+        hello.upload_timestamp,
+        Utc.timestamp_millis_opt(1709294400000).unwrap()
+    );
+    assert_eq!(
+        hello.last_modified(),
+        Utc.timestamp_millis_opt(1709290800000).unwrap()
+    );
+
+    // Large files have no sha1 of their own, and a bad modification time falls back to the
+    // upload time
+    let video = &files[1];
+    assert_eq!(video.content_sha1, "none");
+    assert!(video.content_md5.is_none());
+    assert_eq!(video.server_side_encryption.mode, Some(SseMode::SseB2));
+    assert_eq!(video.last_modified(), video.upload_timestamp);
+
+    let json: serde_json::Value = serde_json::from_str(fixture).unwrap();
+    assert_eq!(json["nextFileName"], "videos/holiday.mp4\0");
+}
+
+#[test]
+fn list_file_versions() {
+    let files: Vec<File> = parse(include_str!("list_file_versions.json"), Some("files"));
+    assert!(matches!(files[0].action, Action::Hide));
+    assert!(matches!(files[1].action, Action::Upload));
+    assert!(matches!(files[2].action, Action::Start));
+
+    // An empty object is sent for the encryption of some versions
+    assert!(files[0].server_side_encryption.algorithm.is_none());
+
+    assert!(crate::retention::lock_reason(&files[0]).is_none());
+    assert!(crate::retention::lock_reason(&files[1]).is_some());
+}
+
+#[test]
+fn unknown_values() {
+    let mut json: serde_json::Value =
+        serde_json::from_str(include_str!("upload_file.json")).unwrap();
+    json["action"] = "archive".into();
+    json["serverSideEncryption"]["mode"] = "SSE-Q".into();
+    let file: File = serde_json::from_value(json).unwrap();
+    assert_eq!(file.action, Action::Unknown("archive".into()));
+    assert_eq!(
+        file.server_side_encryption.mode,
+        Some(SseMode::Unknown("SSE-Q".into()))
+    );
+
+    let mut json: serde_json::Value =
+        serde_json::from_str(include_str!("list_buckets.json")).unwrap();
+    json["buckets"][0]["bucketType"] = "allSecret".into();
+    let buckets: Vec<Bucket> = serde_json::from_value(json["buckets"].take()).unwrap();
+    assert_eq!(buckets[0].bucket_type.as_str(), "allSecret");
+
+    // Unknown values are sent back as they came, e.g. when copying a key's capabilities
+    let caps: Vec<Capability> =
+        serde_json::from_str(r#"["readFiles", "readTimeMachine"]"#).unwrap();
+    assert_eq!(caps[0], Capability::ReadFiles);
+    assert_eq!(
+        serde_json::to_string(&caps).unwrap(),
+        r#"["readFiles","readTimeMachine"]"#
+    );
+}
+
+#[test]
+fn upload_file() {
+    let file: File = parse(include_str!("upload_file.json"), None);
+    assert_eq!(file.file_name, "typing_test.txt");
+    assert_eq!(
+        file.content_sha1,
+        "bae5ed658ab3546aee12f23f36392f35dba1ebdd"
+    );
+    assert_eq!(
+        crate::expire::expires(&file),
+        Utc.timestamp_millis_opt(1712500000000).single()
+    );
+}
+
+#[test]
+fn errors() {
+    let error: ApiError = parse(include_str!("error_bad_request.json"), None);
+    assert_eq!(error.status, 400);
+    assert_eq!(error.code, "bad_request");
+    assert!(!error.is_file_lock());
+
+    let error: ApiError = parse(include_str!("error_file_lock.json"), None);
+    assert_eq!(error.status, 401);
+    assert!(error.is_file_lock());
+}
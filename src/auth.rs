@@ -0,0 +1,163 @@
+use std::{fs, path::Path, process::Command};
+
+use anyhow::{bail, Context};
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+
+/// The keyring service under which the application key is stored
+const KEYRING_SERVICE: &str = "b2";
+const KEYRING_USER: &str = "application-key";
+
+/// An application key, used to get an auth token from b2
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Credentials {
+    pub key_id: String,
+    pub key: String,
+}
+
+/// Somewhere that [`Credentials`] can be found
+pub trait CredentialProvider {
+    /// A short description of where the credentials come from, for messages
+    fn name(&self) -> String;
+
+    /// Get the credentials, or `None` if this provider doesn't have any
+    fn credentials(&self) -> anyhow::Result<Option<Credentials>>;
+}
+
+/// The `B2_APPLICATION_KEY_ID` and `B2_APPLICATION_KEY` environment variables, which are the same
+/// ones used by the official CLI
+pub struct Env;
+
+impl CredentialProvider for Env {
+    fn name(&self) -> String {
+        "the environment".into()
+    }
+
+    fn credentials(&self) -> anyhow::Result<Option<Credentials>> {
+        match (
+            std::env::var("B2_APPLICATION_KEY_ID"),
+            std::env::var("B2_APPLICATION_KEY"),
+        ) {
+            (Ok(key_id), Ok(key)) => Ok(Some(Credentials { key_id, key })),
+            _ => Ok(None),
+        }
+    }
+}
+
+/// A command whose output is the credentials as JSON, e.g. `{"key_id": "...", "key": "..."}`
+pub struct Process<'a>(pub &'a str);
+
+impl CredentialProvider for Process<'_> {
+    fn name(&self) -> String {
+        format!("`{}`", self.0)
+    }
+
+    fn credentials(&self) -> anyhow::Result<Option<Credentials>> {
+        let out = Command::new("sh").arg("-c").arg(self.0).output()?;
+        if !out.status.success() {
+            bail!(
+                "{} failed ({}): {}",
+                self.name(),
+                out.status,
+                String::from_utf8_lossy(&out.stderr).trim()
+            );
+        }
+
+        let creds = serde_json::from_slice(&out.stdout)
+            .with_context(|| format!("{} did not print valid credentials", self.name()))?;
+        Ok(Some(creds))
+    }
+}
+
+/// A TOML file with `key_id` and `key`, e.g. a mounted secret
+pub struct SecretsFile<'a>(pub &'a Path);
+
+impl CredentialProvider for SecretsFile<'_> {
+    fn name(&self) -> String {
+        self.0.display().to_string()
+    }
+
+    fn credentials(&self) -> anyhow::Result<Option<Credentials>> {
+        let content = fs::read_to_string(self.0)
+            .with_context(|| format!("Unable to read {}", self.name()))?;
+        Ok(Some(toml::from_str(&content)?))
+    }
+}
+
+/// The OS keyring (the Secret Service on Linux, Keychain on macOS, Credential Manager on
+/// Windows)
+pub struct Keyring;
+
+impl Keyring {
+    fn entry() -> anyhow::Result<keyring::Entry> {
+        Ok(keyring::Entry::new(KEYRING_SERVICE, KEYRING_USER)?)
+    }
+
+    pub fn store(creds: &Credentials) -> anyhow::Result<()> {
+        Self::entry()?.set_password(&serde_json::to_string(creds)?)?;
+        Ok(())
+    }
+}
+
+impl CredentialProvider for Keyring {
+    fn name(&self) -> String {
+        "the keyring".into()
+    }
+
+    fn credentials(&self) -> anyhow::Result<Option<Credentials>> {
+        match Self::entry()?.get_password() {
+            Ok(creds) => Ok(Some(serde_json::from_str(&creds)?)),
+            Err(keyring::Error::NoEntry) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+/// The `key_id` and `key` saved in the config file by `b2 authorise`
+pub struct ConfigFile<'a>(pub &'a Config);
+
+impl CredentialProvider for ConfigFile<'_> {
+    fn name(&self) -> String {
+        "the config file".into()
+    }
+
+    fn credentials(&self) -> anyhow::Result<Option<Credentials>> {
+        if self.0.key_id.is_empty() || self.0.key.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(Credentials {
+            key_id: self.0.key_id.clone(),
+            key: self.0.key.clone(),
+        }))
+    }
+}
+
+/// Find the credentials to use, checking each provider in order:
+///
+/// 1. the environment
+/// 2. the `credential_process` command from the config
+/// 3. the `credentials_file` from the config
+/// 4. the keyring, if `keyring = true` in the config
+/// 5. the key saved in the config
+pub fn resolve(cfg: &Config) -> anyhow::Result<Option<Credentials>> {
+    let mut providers: Vec<Box<dyn CredentialProvider + '_>> = vec![Box::new(Env)];
+    if let Some(ref cmd) = cfg.credential_process {
+        providers.push(Box::new(Process(cmd)));
+    }
+    if let Some(ref file) = cfg.credentials_file {
+        providers.push(Box::new(SecretsFile(file)));
+    }
+    if cfg.keyring {
+        providers.push(Box::new(Keyring));
+    }
+    providers.push(Box::new(ConfigFile(cfg)));
+
+    for provider in providers {
+        if let Some(creds) = provider.credentials()? {
+            return Ok(Some(creds));
+        }
+    }
+
+    Ok(None)
+}
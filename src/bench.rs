@@ -0,0 +1,179 @@
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use anyhow::bail;
+use colored::Colorize;
+use humanize_bytes::humanize_bytes_decimal;
+
+use crate::{cancel, config::Config, http, path::RemotePath, transfer};
+
+/// The largest file that can be uploaded without using parts
+const MAX_SIZE: u64 = 5_000_000_000;
+
+/// How much synthetic data is sent, and how
+pub struct Options {
+    /// The size of each file
+    pub size: u64,
+    /// How many files are uploaded and then downloaded
+    pub count: usize,
+    /// How many files are transferred at once
+    pub concurrency: usize,
+}
+
+/// The timings of one direction of the benchmark
+struct Report {
+    elapsed: Duration,
+    latencies: Vec<Duration>,
+}
+
+impl Report {
+    fn print(&mut self, label: &str, bytes: u64) {
+        self.latencies.sort();
+        let percentile = |p: usize| {
+            let i = (self.latencies.len() * p).div_ceil(100).saturating_sub(1);
+            format!("{:.2}s", self.latencies[i].as_secs_f64())
+        };
+        let speed = (bytes as f64 / self.elapsed.as_secs_f64()) as u64;
+
+        println!(
+            "{:<10}{:>12}/s   p50 {}   p90 {}   p99 {}",
+            label.blue(),
+            humanize_bytes_decimal!(speed),
+            percentile(50),
+            percentile(90),
+            percentile(99)
+        );
+    }
+}
+
+/// Upload and then download `opts.count` files of random data into `bucket`, printing the
+/// throughput and the latency of each request, and then delete them again
+pub fn bench(cfg: &mut Config, bucket: &RemotePath, opts: &Options) -> anyhow::Result<()> {
+    if opts.size > MAX_SIZE {
+        bail!(
+            "Files can be at most {} without using parts",
+            humanize_bytes_decimal!(MAX_SIZE)
+        );
+    }
+    if opts.count == 0 || opts.concurrency == 0 {
+        bail!("The count and concurrency must be at least 1");
+    }
+
+    let prefix = bucket.join(&format!(
+        "b2-bench-{}/",
+        chrono::Utc::now().timestamp_millis()
+    ));
+    let data = random_bytes(opts.size as usize);
+
+    println!(
+        "Sending {} files of {} to {}, {} at a time",
+        opts.count,
+        humanize_bytes_decimal!(opts.size),
+        prefix,
+        opts.concurrency
+    );
+
+    cfg.confirm_auth()?;
+    cfg.require_bucket_id(&bucket.bucket)?;
+
+    let uploaded = Mutex::new(Vec::with_capacity(opts.count));
+    let result = run(cfg, opts, |cfg, i| {
+        // Like `b2 upload`, this gets a new upload url for each file
+        let file = transfer::upload_bytes(
+            cfg,
+            &prefix.join(&i.to_string()),
+            &data,
+            "application/octet-stream",
+        )?;
+        uploaded.lock().unwrap().push(file);
+        Ok(())
+    })
+    .and_then(|mut upload| {
+        let files = uploaded.lock().unwrap();
+        let mut download = run(cfg, opts, |cfg, i| {
+            download(cfg, &RemotePath::new(&bucket.bucket, &files[i].file_name))
+        })?;
+
+        let bytes = opts.size * opts.count as u64;
+        upload.print("Upload", bytes);
+        download.print("Download", bytes);
+        Ok(())
+    });
+
+    // Clean up even if the benchmark failed part way through
+    for file in uploaded.into_inner().unwrap() {
+        cfg.delete_file_version(&file)?;
+    }
+
+    result
+}
+
+/// Call `f` for each of `0..opts.count` from `opts.concurrency` threads, timing each call
+fn run<F>(cfg: &Config, opts: &Options, f: F) -> anyhow::Result<Report>
+where
+    F: Fn(&mut Config, usize) -> anyhow::Result<()> + Sync,
+{
+    let next = AtomicUsize::new(0);
+    let latencies = Mutex::new(Vec::with_capacity(opts.count));
+
+    let start = Instant::now();
+    std::thread::scope(|s| {
+        let workers: Vec<_> = (0..std::cmp::min(opts.concurrency, opts.count))
+            .map(|_| {
+                s.spawn(|| -> anyhow::Result<()> {
+                    let mut cfg = cfg.clone();
+                    loop {
+                        cancel::check()?;
+                        let i = next.fetch_add(1, Ordering::Relaxed);
+                        if i >= opts.count {
+                            break Ok(());
+                        }
+
+                        let start = Instant::now();
+                        f(&mut cfg, i)?;
+                        latencies.lock().unwrap().push(start.elapsed());
+                    }
+                })
+            })
+            .collect();
+        workers.into_iter().try_for_each(|w| w.join().unwrap())
+    })?;
+
+    Ok(Report {
+        elapsed: start.elapsed(),
+        latencies: latencies.into_inner().unwrap(),
+    })
+}
+
+/// Download `file`, throwing away its contents
+fn download(cfg: &mut Config, file: &RemotePath) -> anyhow::Result<()> {
+    let url = file.url(&cfg.download_url);
+    let mut res = cfg.send_request_res(|cfg| {
+        Ok(http::client()
+            .get(&url)
+            .header("Authorization", &cfg.auth_token)
+            .send()?)
+    })?;
+    std::io::copy(&mut res, &mut std::io::sink())?;
+    Ok(())
+}
+
+/// `len` bytes of noise, so that nothing along the way can compress it
+fn random_bytes(len: usize) -> Vec<u8> {
+    // xorshift64, which is plenty random enough for this
+    let mut state = chrono::Utc::now().timestamp_nanos_opt().unwrap_or(1) as u64 | 1;
+    let mut out = Vec::with_capacity(len + 8);
+    while out.len() < len {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        out.extend_from_slice(&state.to_le_bytes());
+    }
+    out.truncate(len);
+    out
+}
@@ -0,0 +1,206 @@
+use std::{fs, path::Path};
+
+use anyhow::{bail, Context};
+use colored::Colorize;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    api::{ApiError, Bucket, BucketType},
+    config::Config,
+};
+
+/// The settings of a bucket which can be exported to a file and applied to another bucket
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Settings {
+    pub bucket_type: BucketType,
+    #[serde(default)]
+    pub bucket_info: serde_json::Value,
+    #[serde(default)]
+    pub cors_rules: Vec<serde_json::Value>,
+    #[serde(default)]
+    pub lifecycle_rules: Vec<serde_json::Value>,
+    /// e.g. `{"mode": "SSE-B2", "algorithm": "AES256"}`
+    #[serde(default)]
+    pub default_server_side_encryption: Option<serde_json::Value>,
+    /// e.g. `{"isFileLockEnabled": true, "defaultRetention": {...}}`
+    #[serde(default)]
+    pub file_lock_configuration: Option<serde_json::Value>,
+}
+
+impl Settings {
+    fn lock_enabled(&self) -> bool {
+        self.file_lock_configuration
+            .as_ref()
+            .is_some_and(|l| l["isFileLockEnabled"].as_bool() == Some(true))
+    }
+
+    fn default_retention(&self) -> Option<&serde_json::Value> {
+        self.file_lock_configuration
+            .as_ref()
+            .map(|l| &l["defaultRetention"])
+            .filter(|r| !r["mode"].is_null())
+    }
+}
+
+/// Check `name` against b2's rules for bucket names, so that a broken rule is explained rather
+/// than the api's generic bad request
+pub fn validate_name(name: &str) -> anyhow::Result<()> {
+    if !(6..=63).contains(&name.len()) {
+        bail!(
+            "Bucket names must be between 6 and 63 characters long, `{}` is {}",
+            name,
+            name.len()
+        );
+    }
+    if let Some(c) = name
+        .chars()
+        .find(|c| !c.is_ascii_alphanumeric() && *c != '-')
+    {
+        bail!(
+            "Bucket names may only contain letters, numbers and `-`, `{}` contains `{}`",
+            name,
+            c
+        );
+    }
+    if name.to_lowercase().starts_with("b2-") {
+        bail!("Bucket names may not start with `b2-`, which is reserved");
+    }
+    Ok(())
+}
+
+/// Explain an error from `b2_create_bucket` for `name`
+pub fn explain_create_error(err: anyhow::Error, name: &str) -> anyhow::Error {
+    match err.downcast_ref::<ApiError>() {
+        Some(e) if e.code == "duplicate_bucket_name" => anyhow::anyhow!(
+            "The bucket name `{}` is already taken, bucket names are shared between every b2 \
+             account so it may belong to someone else",
+            name
+        ),
+        Some(e) if e.code == "too_many_buckets" => anyhow::anyhow!(
+            "This account already has as many buckets as it's allowed: {}",
+            e.message
+        ),
+        _ => err,
+    }
+}
+
+/// Get the bucket called `name` from the api
+pub fn get(cfg: &mut Config, name: &str) -> anyhow::Result<Option<Bucket>> {
+    let account_id = cfg.account_id.clone();
+    let res = cfg.list(
+        "b2_list_buckets",
+        &[("accountId", &account_id), ("bucketName", name)],
+    )?;
+    let buckets: Vec<Bucket> = Deserialize::deserialize(res["buckets"].clone())?;
+    Ok(buckets.into_iter().next())
+}
+
+/// Write the settings of `bucket` to `file` as JSON, or to stdout if there's no file
+pub fn export(cfg: &mut Config, bucket: &str, file: Option<&Path>) -> anyhow::Result<()> {
+    let Some(bucket) = get(cfg, bucket)? else {
+        bail!("Bucket `{}` does not exist", bucket);
+    };
+
+    // Settings that the key can't read are left out, rather than exported as if they were unset
+    let readable = |c: crate::api::GenericConfig, name| {
+        if !c.is_client_authorized_to_read {
+            eprintln!(
+                "{}",
+                format!("Not exporting {}, the application key can't read it", name).yellow()
+            );
+        }
+        c.is_client_authorized_to_read.then_some(c.value)
+    };
+
+    let settings = Settings {
+        bucket_type: bucket.bucket_type,
+        bucket_info: bucket.bucket_info,
+        cors_rules: bucket.cors_rules,
+        lifecycle_rules: bucket.lifecycle_rules,
+        default_server_side_encryption: readable(
+            bucket.default_server_side_encryption,
+            "the default encryption",
+        ),
+        file_lock_configuration: readable(bucket.file_lock_configuration, "the file lock"),
+    };
+
+    let json = serde_json::to_string_pretty(&settings)?;
+    match file {
+        Some(file) => fs::write(file, json + "\n")?,
+        None => println!("{}", json),
+    }
+    Ok(())
+}
+
+/// Apply the settings in `file` to `bucket`, creating it if it doesn't exist yet
+pub fn import(cfg: &mut Config, bucket: &str, file: &Path) -> anyhow::Result<()> {
+    let settings: Settings = serde_json::from_str(&fs::read_to_string(file)?)
+        .with_context(|| format!("{} is not a valid settings file", file.display()))?;
+
+    let existing = get(cfg, bucket)?;
+
+    let mut body = serde_json::json!({
+        "accountId": cfg.account_id,
+        "bucketType": settings.bucket_type,
+        "bucketInfo": settings.bucket_info,
+        "corsRules": settings.cors_rules,
+        "lifecycleRules": settings.lifecycle_rules,
+    });
+    if let Some(ref encryption) = settings.default_server_side_encryption {
+        body["defaultServerSideEncryption"] = encryption.clone();
+    }
+
+    match existing {
+        Some(ref existing) => {
+            let lock_enabled =
+                existing.file_lock_configuration.value["isFileLockEnabled"].as_bool() == Some(true);
+            if lock_enabled && !settings.lock_enabled() {
+                eprintln!(
+                    "{}",
+                    "The file lock can't be disabled once it is enabled, leaving it on".yellow()
+                );
+            }
+
+            body["bucketId"] = existing.bucket_id.clone().into();
+            if settings.lock_enabled() && !lock_enabled {
+                body["fileLockEnabled"] = true.into();
+            }
+            if let Some(retention) = settings.default_retention() {
+                body["defaultRetention"] = retention.clone();
+            }
+
+            let _: serde_json::Value =
+                cfg.send_request_de(|cfg| Ok(cfg.post("b2_update_bucket")?.json(&body).send()?))?;
+            println!("{}", format!("Updated {}", bucket).green());
+        }
+        None => {
+            validate_name(bucket)?;
+            body["bucketName"] = bucket.into();
+            body["fileLockEnabled"] = settings.lock_enabled().into();
+
+            let created: Bucket = cfg
+                .send_request_de(|cfg| Ok(cfg.post("b2_create_bucket")?.json(&body).send()?))
+                .map_err(|e| explain_create_error(e, bucket))?;
+
+            // The default retention can only be set once the bucket exists
+            if let Some(retention) = settings.default_retention() {
+                let _: serde_json::Value = cfg.send_request_de(|cfg| {
+                    Ok(cfg
+                        .post("b2_update_bucket")?
+                        .json(&serde_json::json!({
+                            "accountId": cfg.account_id,
+                            "bucketId": created.bucket_id,
+                            "defaultRetention": retention,
+                        }))
+                        .send()?)
+                })?;
+            }
+
+            cfg.get_buckets()?;
+            println!("{}", format!("Created {}", bucket).green());
+        }
+    }
+
+    Ok(())
+}
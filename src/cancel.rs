@@ -0,0 +1,78 @@
+use std::{
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+
+use anyhow::bail;
+use colored::Colorize;
+
+/// Set once the command should stop, checked by transfers and long running loops so that they
+/// can stop at a point where they're able to clean up after themselves
+static CANCELLED: AtomicBool = AtomicBool::new(false);
+
+/// Stop on Ctrl-C, or after `deadline`.  A second Ctrl-C exits straight away, without cleaning
+/// up.
+pub fn install(deadline: Option<Duration>) -> anyhow::Result<()> {
+    ctrlc::set_handler(|| {
+        if CANCELLED.swap(true, Ordering::SeqCst) {
+            std::process::exit(130);
+        }
+        eprintln!(
+            "{}",
+            "Stopping, press Ctrl-C again to stop immediately".yellow()
+        );
+    })?;
+
+    if let Some(deadline) = deadline {
+        std::thread::spawn(move || {
+            std::thread::sleep(deadline);
+            eprintln!(
+                "{}",
+                format!("Stopping after {}s", deadline.as_secs()).yellow()
+            );
+            CANCELLED.store(true, Ordering::SeqCst);
+        });
+    }
+    Ok(())
+}
+
+/// Whether the command has been asked to stop
+pub fn is_cancelled() -> bool {
+    CANCELLED.load(Ordering::SeqCst)
+}
+
+/// Fail if the command has been asked to stop
+pub fn check() -> anyhow::Result<()> {
+    if is_cancelled() {
+        bail!("Cancelled");
+    }
+    Ok(())
+}
+
+/// Sleep for `duration`, waking up early to fail if the command is asked to stop
+pub fn sleep(duration: Duration) -> anyhow::Result<()> {
+    let end = std::time::Instant::now() + duration;
+    loop {
+        check()?;
+        let left = end.saturating_duration_since(std::time::Instant::now());
+        if left.is_zero() {
+            return Ok(());
+        }
+        std::thread::sleep(std::cmp::min(left, Duration::from_millis(100)));
+    }
+}
+
+/// Parse a `--deadline`, which is a number of seconds or a number followed by s, m or h
+pub fn parse_deadline(s: &str) -> anyhow::Result<Duration> {
+    let end = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let Ok(n) = s[..end].parse::<u64>() else {
+        bail!("`{}` does not start with a number", s);
+    };
+    let unit = match &s[end..] {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        unit => bail!("Unknown time unit `{}`, expected one of s, m or h", unit),
+    };
+    Ok(Duration::from_secs(n * unit))
+}
@@ -0,0 +1,70 @@
+use anyhow::bail;
+use chrono::{DateTime, NaiveDate, Utc};
+use colored::Colorize;
+use humanize_bytes::humanize_bytes_decimal;
+
+use crate::{
+    api::{Action, File},
+    cli::OutputFormat,
+    config::Config,
+    files::TimeStyle,
+    path::RemotePath,
+    table::Table,
+};
+
+/// Parse the start of a `changes` query, either as a date (midnight UTC) or an RFC 3339 time
+pub fn parse_since(s: &str) -> anyhow::Result<DateTime<Utc>> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(s) {
+        return Ok(time.to_utc());
+    }
+    match NaiveDate::parse_from_str(s, "%Y-%m-%d") {
+        Ok(date) => Ok(date.and_time(Default::default()).and_utc()),
+        Err(_) => bail!("`{}` is not a date (2024-01-31) or an RFC 3339 time", s),
+    }
+}
+
+/// The uploads, hides and large file starts under `path` since `since`, oldest first
+///
+/// b2 doesn't keep any record of deleted versions, so those can't be seen.
+pub fn changes(
+    cfg: &mut Config,
+    path: &RemotePath,
+    since: DateTime<Utc>,
+) -> anyhow::Result<Vec<File>> {
+    let mut files = cfg.list_file_versions(path)?;
+    files.retain(|f| f.upload_timestamp >= since && !matches!(f.action, Action::Folder));
+    files.sort_by_key(|f| f.upload_timestamp);
+    Ok(files)
+}
+
+pub fn print(files: Vec<File>, output: OutputFormat, time_style: &TimeStyle) -> anyhow::Result<()> {
+    if output != OutputFormat::Table {
+        let mut table = Table::new(vec!["time", "action", "name", "size", "id"]);
+        for file in files {
+            table.row(vec![
+                file.upload_timestamp.to_rfc3339(),
+                file.action.to_string(),
+                file.file_name,
+                file.content_length.to_string(),
+                file.file_id,
+            ]);
+        }
+        return table.print(output);
+    }
+
+    for file in files {
+        let action = match file.action {
+            Action::Upload => "upload".green(),
+            Action::Hide => "hide  ".red(),
+            ref a => format!("{:<6}", a).yellow(),
+        };
+        println!(
+            "{}   {}   {:>10}   {}",
+            time_style.format(file.upload_timestamp),
+            action,
+            humanize_bytes_decimal!(file.content_length),
+            file.file_name
+        );
+    }
+    Ok(())
+}
@@ -1,10 +1,47 @@
-use std::path::PathBuf;
+use std::{path::PathBuf, time::Duration};
 
+use chrono::{DateTime, Utc};
 use clap::{Parser, Subcommand};
+use regex::Regex;
+
+use crate::{
+    cancel::parse_deadline,
+    changes::parse_since,
+    files::TimeStyle,
+    find::{parse_age, parse_size, AgeFilter, SizeFilter},
+    path::RemotePath,
+    report,
+    text::Encoding,
+    transfer::{ChecksumAlgorithm, Compression},
+};
 
 #[derive(Parser, Debug)]
-#[command(version, about)]
+#[command(version, about, disable_help_subcommand = true)]
 pub struct Cli {
+    /// Use the config of this profile (`<name>.toml` next to the default config) rather than
+    /// the default one, e.g. `b2 --profile work authorise`
+    #[arg(long, global = true, value_name = "name")]
+    pub profile: Option<String>,
+    /// Keep memory use low, at the cost of speed: large files are sent in small parts one at a
+    /// time over a single connection, uploads aren't hashed in parallel and listings aren't
+    /// cached in memory, e.g. for routers, NAS devices and small containers
+    #[arg(long, global = true)]
+    pub low_memory: bool,
+    /// Never draw progress bars, which are already left out when stdout isn't a terminal
+    #[arg(long, global = true)]
+    pub no_progress: bool,
+    /// Answer listings (`ls`, `tree`, `find`, `report`...) from the ones cached on disk by
+    /// `list_cache_ttl`, however old, without touching the network
+    #[arg(long, global = true)]
+    pub offline: bool,
+    /// Stop the command after this long (seconds, or a number followed by s, m or h), cleaning
+    /// up like Ctrl-C does
+    #[arg(long, global = true, value_name = "duration", value_parser = parse_deadline)]
+    pub deadline: Option<Duration>,
+    /// Add this to the end of the `User-Agent` of every request, instead of
+    /// `http.user_agent_suffix` from the config
+    #[arg(long, global = true, value_name = "suffix")]
+    pub user_agent_suffix: Option<String>,
     #[command(subcommand)]
     pub command: Command,
 }
@@ -18,19 +55,59 @@ pub struct BucketType {
     pub public: bool,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum RetentionMode {
+    Governance,
+    Compliance,
+    None,
+}
+
+/// How listings are printed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum OutputFormat {
+    /// The normal, human-readable output
+    Table,
+    Json,
+    Csv,
+    Tsv,
+}
+
 #[derive(Subcommand, Debug)]
 pub enum Command {
+    /// Send an authenticated request to any endpoint of the b2 api and print the response, for
+    /// anything that doesn't have a command yet
+    Api {
+        /// Query parameters to send
+        #[arg(short, long, value_name = "key=value", value_parser = parse_key_value)]
+        query: Vec<(String, String)>,
+        /// A JSON file to POST as the body of the request, otherwise a GET request is sent
+        #[arg(short, long, value_name = "file.json")]
+        body: Option<PathBuf>,
+        /// The name of the endpoint, e.g. `b2_list_keys`
+        #[arg(value_name = "endpoint")]
+        endpoint: String,
+    },
     /// Authorise your b2 account
     #[command(alias = "authorize")]
-    Authorise,
+    Authorise {
+        /// Store the application key in the OS keyring rather than the config file
+        #[arg(long)]
+        keyring: bool,
+        /// Never store the application key, only the auth token, which expires after a day
+        #[arg(long, conflicts_with = "keyring")]
+        ephemeral: bool,
+    },
     /// Get the url to share a file in a public bucket
     Share {
-        /// The bucket from which to download the file
+        /// The bucket from which to download the file, or `b2://bucket/file`
         #[arg(value_name = "bucket")]
-        bucket: String,
+        bucket: RemotePath,
         /// The path from which to download the file
         #[arg(value_name = "file")]
-        file: PathBuf,
+        file: Option<String>,
+        /// Also print the url as a QR code, to open it on a phone
+        #[arg(long)]
+        qr: bool,
     },
     CreateBucket {
         #[arg(value_name = "name")]
@@ -38,37 +115,148 @@ pub enum Command {
         #[clap(flatten)]
         visibility: BucketType,
     },
+    /// Manage the settings of a bucket
+    Bucket {
+        #[command(subcommand)]
+        command: BucketCommand,
+    },
     // TODO: CancelAllUnfinishedLargeFiles {},
     // TODO: CancelLargeFile {},
     // TODO: ClearAccount {},
     // TODO: CopyFileById {},
+    /// Copy a file, on the server within one account, or by streaming it from one account
+    /// into another with `--from-profile`/`--to-profile`
+    Cp {
+        /// The profile whose account `src` is in, otherwise `--profile` or the default
+        #[arg(long, value_name = "name")]
+        from_profile: Option<String>,
+        /// The profile whose account `dest` is in, otherwise `--profile` or the default
+        #[arg(long, value_name = "name")]
+        to_profile: Option<String>,
+        /// The file to copy, as `b2://bucket/file`
+        #[arg(value_name = "src")]
+        src: RemotePath,
+        /// Where to copy it to, as `b2://bucket/file`, or a directory to keep its name
+        #[arg(value_name = "dest")]
+        dest: RemotePath,
+    },
     // TODO: CreateBucket {},
     // TODO: CreateKey {},
     // TODO: DeleteBucket {},
     // TODO: DeleteFileVersion {},
     // TODO: DeleteKey {},
     /// Download a file from a bucket
+    #[command(alias = "get")]
     Download {
         /// The file into which the contents will be downloaded -- If not specified, it will download into the current directory using the file name on b2
         #[arg(short = 'O', long, value_name = "file")]
         output: Option<PathBuf>,
-        /// The bucket from which to download the file
+        /// Verify the downloaded file against a checksum stored when it was uploaded
+        #[arg(long, value_enum, value_name = "algorithm")]
+        verify: Vec<ChecksumAlgorithm>,
+        /// Download from a public bucket without any credentials
+        #[arg(long)]
+        anonymous: bool,
+        /// Save files served with a `Content-Encoding` (e.g. gzipped website assets, or uploads
+        /// with `--compress`) as they are stored, rather than decompressing them
+        #[arg(long)]
+        no_decompress: bool,
+        /// Raise a desktop notification when the download finishes or fails, if it took at least this
+        /// many seconds (30 when just `--notify` is given)
+        #[arg(
+            long,
+            value_name = "seconds",
+            num_args = 0..=1,
+            require_equals = true,
+            default_missing_value = "30"
+        )]
+        notify: Option<u64>,
+        /// Download every file listed in this file (or stdin for `-`), one per line or separated
+        /// by NULs, relative to `bucket`. Each is saved at the same path under the `-O`
+        /// directory, or the current one.
+        #[arg(long, value_name = "list", conflicts_with = "file")]
+        files_from: Option<PathBuf>,
+        /// Pick the files to download from a list of those under `bucket`, moving through its
+        /// directories and typing to narrow them down. Each is saved like with `--files-from`.
+        #[arg(long, conflicts_with_all = ["file", "files_from"])]
+        pick: bool,
+        /// The bucket from which to download the file, or `b2://bucket/file`
         #[arg(value_name = "bucket")]
-        bucket: String,
+        bucket: RemotePath,
         /// The path from which to download the file
         #[arg(value_name = "file")]
-        file: PathBuf,
+        file: Option<String>,
     },
     Cat {
         /// Force the file to be printed even if it is not text
         #[arg(short, long)]
         force: bool,
-        /// The bucket from which to download the file
+        /// Print the file as-is, without syntax highlighting or a pager
+        #[arg(short, long)]
+        plain: bool,
+        /// The encoding of the file, which is otherwise detected from its BOM or contents
+        #[arg(short, long, value_enum, value_name = "encoding")]
+        encoding: Option<Encoding>,
+        /// Print the raw bytes of the file, whatever they are
+        #[arg(long, conflicts_with_all = ["hex", "encoding"])]
+        bytes: bool,
+        /// Print a hex dump of the file
+        #[arg(long, conflicts_with = "encoding")]
+        hex: bool,
+        /// Print files served with a `Content-Encoding` (e.g. gzipped website assets, or uploads
+        /// with `--compress`) as they are stored, rather than decompressing them
+        #[arg(long)]
+        no_decompress: bool,
+        /// Pick the file to print from a list of those under `bucket`, moving through its
+        /// directories and typing to narrow them down
+        #[arg(long, conflicts_with = "file")]
+        pick: bool,
+        /// The bucket from which to download the file, or `b2://bucket/file`
         #[arg(value_name = "bucket")]
-        bucket: String,
+        bucket: RemotePath,
         /// The path from which to download the file
         #[arg(value_name = "file")]
+        file: Option<String>,
+    },
+    /// Publish a file for download: upload it with a `.sha256` sidecar (and a `.sig` with
+    /// `--sign`), then print the url of each
+    Release {
+        /// Sign the file with this command, with `{}` replaced by its path, ending with `;`.
+        /// What it prints is uploaded as `<file>.sig`, e.g. `--sign gpg --detach-sign -o - {} ;`
+        #[arg(
+            long,
+            value_name = "command",
+            num_args = 1..,
+            value_terminator = ";",
+            allow_hyphen_values = true
+        )]
+        sign: Option<Vec<String>>,
+        /// The file to publish
+        #[arg(value_name = "file")]
         file: PathBuf,
+        /// The bucket to publish into, or `b2://bucket/prefix`
+        #[arg(value_name = "bucket")]
+        bucket: RemotePath,
+        /// The directory within the bucket to publish into
+        #[arg(value_name = "prefix")]
+        prefix: Option<String>,
+    },
+    /// Upload the contents of a url to b2 without saving it locally first
+    Fetch {
+        /// Manually override the Content Type of the file rather than using the one sent by the
+        /// server
+        #[arg(short, long, value_name = "content-type")]
+        content_type: Option<String>,
+        /// The HTTP(S) url to fetch
+        #[arg(value_name = "url")]
+        url: String,
+        /// The bucket into which the file should be uploaded, or `b2://bucket/dest`
+        #[arg(value_name = "bucket")]
+        bucket: RemotePath,
+        /// The destination file path relative to the root of the bucket -- If not specified, the
+        /// last segment of the url is used
+        #[arg(value_name = "dest")]
+        dest: Option<String>,
     },
     // TODO: GetAccountInfo {},
     // TODO: GetBucket {},
@@ -76,22 +264,189 @@ pub enum Command {
     // TODO: GetDownloadAuth {},
     // TODO: GetDownloadUrlWithAuth {},
     // TODO: HideFile {},
+    /// Find the files in a bucket which match some predicates, like `find`
+    Find {
+        /// Only find files whose name (not including the directory) matches this glob
+        #[arg(long, value_name = "glob")]
+        name: Option<String>,
+        /// Only find files of this size, `+N` for more than and `-N` for less than, with an
+        /// optional unit of c, k, M, G or T
+        #[arg(long, value_name = "size", allow_hyphen_values = true)]
+        size: Option<SizeFilter>,
+        /// Only find files modified this long ago, `+N` for more than and `-N` for less than,
+        /// with an optional unit of s, m, h, d (the default) or w
+        #[arg(long, value_name = "age", allow_hyphen_values = true)]
+        mtime: Option<AgeFilter>,
+        /// Delete the files that are found
+        #[arg(long, conflicts_with = "exec")]
+        delete: bool,
+        /// Delete without asking for confirmation
+        #[arg(short, long, requires = "delete")]
+        yes: bool,
+        /// Copy each file into this bucket, under `<time>/<bucket>/`, before deleting it so that
+        /// it can be recovered
+        #[arg(long, value_name = "bucket", requires = "delete")]
+        trash_bucket: Option<String>,
+        /// Run a command for each file, with `{}` replaced by its `b2://` path, ending with `;`
+        #[arg(
+            long,
+            value_name = "command",
+            num_args = 1..,
+            value_terminator = ";",
+            allow_hyphen_values = true
+        )]
+        exec: Option<Vec<String>>,
+        /// The bucket to search, or `b2://bucket/prefix`
+        #[arg(value_name = "bucket")]
+        bucket: RemotePath,
+        /// Only search files starting with this prefix
+        #[arg(value_name = "prefix")]
+        prefix: Option<String>,
+    },
+    /// Search the lines of the files in a bucket for a regex, printing them as `file:line:match`
+    Grep {
+        /// Only search files whose name (relative to the prefix) matches this glob, e.g. `*.log`
+        #[arg(short, long, value_name = "glob")]
+        glob: Option<String>,
+        /// Only search files whose Content Type starts with this, e.g. `text/`
+        #[arg(short, long, value_name = "content-type")]
+        content_type: Option<String>,
+        /// The regex to search for
+        #[arg(value_name = "pattern")]
+        pattern: Regex,
+        /// The bucket to search, or `b2://bucket/prefix`
+        #[arg(value_name = "bucket")]
+        bucket: RemotePath,
+        /// Only search files starting with this prefix
+        #[arg(value_name = "prefix")]
+        prefix: Option<String>,
+    },
+    /// Print the first lines of a file without downloading all of it
+    Head {
+        /// The number of lines to print
+        #[arg(short = 'n', long, default_value_t = 10)]
+        lines: usize,
+        /// The bucket which contains the file, or `b2://bucket/file`
+        #[arg(value_name = "bucket")]
+        bucket: RemotePath,
+        /// The path of the file
+        #[arg(value_name = "file")]
+        file: Option<String>,
+    },
+    /// Show the help for a command, or a longer guide on one of the help topics
+    Help {
+        /// The command or topic, all topics are listed if this is not given
+        #[arg(value_name = "topic")]
+        topic: Option<String>,
+    },
     /// List the buckets (also force-updates the bucket cache)
-    ListBuckets,
-    // TODO: ListKeys {},
+    ListBuckets {
+        /// List information about the bucket such as its type and options
+        #[arg(short, long)]
+        long: bool,
+        /// Only list buckets which have this option enabled, e.g. `s3`
+        #[arg(short, long, value_name = "option")]
+        option: Option<String>,
+        /// How to print the buckets, every format other than `table` includes all information
+        #[arg(long, value_enum, default_value_t = OutputFormat::Table)]
+        output: OutputFormat,
+    },
+    /// Manage the event notification rules of a bucket
+    Notifications {
+        #[command(subcommand)]
+        command: NotificationsCommand,
+    },
+    /// Content-addressed backups, where the contents of each file are stored once under
+    /// `blobs/<sha1>` and each snapshot is a manifest under `snapshots/`
+    Snapshots {
+        #[command(subcommand)]
+        command: SnapshotsCommand,
+    },
+    /// Delete the blobs of a snapshot repository that are no longer part of any snapshot
+    Gc {
+        /// Delete without asking first
+        #[arg(short, long)]
+        yes: bool,
+        /// The bucket which contains the repository, or `b2://bucket/prefix`
+        #[arg(value_name = "bucket")]
+        bucket: RemotePath,
+        /// The prefix of the repository within the bucket
+        #[arg(value_name = "prefix")]
+        prefix: Option<String>,
+    },
+    /// Delete the files that were uploaded with `--delete-after` and whose time is up
+    Expire {
+        /// Only list the files that would be deleted
+        #[arg(long)]
+        dry_run: bool,
+        /// Delete without asking first
+        #[arg(short, long)]
+        yes: bool,
+        /// The bucket to look in, or `b2://bucket/prefix`
+        #[arg(value_name = "bucket")]
+        bucket: RemotePath,
+        /// Only look at files starting with this prefix
+        #[arg(value_name = "prefix")]
+        prefix: Option<String>,
+    },
+    /// Manage the tags of a file, which are stored in its file info
+    Tag {
+        #[command(subcommand)]
+        command: TagCommand,
+    },
+    /// Manage the application key that the CLI uses
+    Key {
+        #[command(subcommand)]
+        command: KeyCommand,
+    },
+    /// List the application keys in the account
+    ListKeys {
+        /// Only list keys whose name starts with this
+        #[arg(short, long, value_name = "prefix")]
+        prefix: Option<String>,
+        /// Only list keys which are restricted to this bucket
+        #[arg(short, long, value_name = "bucket")]
+        bucket: Option<String>,
+        /// Also list keys which have expired
+        #[arg(long)]
+        show_expired: bool,
+    },
     // TODO: ListParts {},
     // TODO: ListUnfinishedLargeFiles {},
     /// Show files in a specific bucket
+    #[command(alias = "list")]
     Ls {
         /// List information about the file such as date uploaded and file size
         #[arg(short, long)]
         long: bool,
+        /// Print the number of files and their total size after the listing, which fetches
+        /// every file under the prefix rather than just the first page
+        #[arg(short, long, conflicts_with_all = ["print0", "output"])]
+        summary: bool,
         /// List all files, including their full path
         #[arg(short, long)]
         all: bool,
-        /// The bucket from which to list the file
+        /// Separate the names with NUL bytes instead of newlines, for use with `xargs -0`
+        #[arg(short = '0', long, conflicts_with = "long")]
+        print0: bool,
+        /// Only show files with this tag
+        #[arg(short, long, value_name = "tag")]
+        tag: Option<String>,
+        /// How to print the files, every format other than `table` lists all files with all of
+        /// their information
+        #[arg(long, value_enum, default_value_t = OutputFormat::Table, conflicts_with = "print0")]
+        output: OutputFormat,
+        /// Show a file in a public bucket without any credentials, b2 doesn't allow listing
+        /// without authorising so this only works for a single file
+        #[arg(long, conflicts_with = "tag")]
+        anonymous: bool,
+        /// How to show times with `--long`: `short`, `iso`, `full`, `relative` (e.g. `3 days ago`)
+        /// or a strftime format like `+%Y-%m-%d %H:%M`
+        #[arg(long, value_name = "style", default_value = "short")]
+        time_style: TimeStyle,
+        /// The bucket from which to list the file, or `b2://bucket/prefix`
         #[arg(value_name = "bucket")]
-        bucket: String,
+        bucket: RemotePath,
         /// The prefix of files to search
         #[arg(value_name = "search")]
         search: Option<String>,
@@ -101,44 +456,315 @@ pub enum Command {
         /// List information about the file such as date uploaded and file size
         #[arg(short, long)]
         long: bool,
-        /// The bucket from which to list the file
+        /// Print the number of files and their total size after each directory and the listing
+        #[arg(short, long)]
+        summary: bool,
+        /// Only show this many levels, with the number of files and total size of each
+        /// directory that isn't expanded
+        #[arg(short, long, value_name = "n", value_parser = clap::value_parser!(u64).range(1..))]
+        depth: Option<u64>,
+        /// How to show times with `--long`: `short`, `iso`, `full`, `relative` (e.g. `3 days ago`)
+        /// or a strftime format like `+%Y-%m-%d %H:%M`
+        #[arg(long, value_name = "style", default_value = "short")]
+        time_style: TimeStyle,
+        /// The bucket from which to list the file, or `b2://bucket/prefix`
         #[arg(value_name = "bucket")]
-        bucket: String,
+        bucket: RemotePath,
         /// The prefix of files to search
         #[arg(value_name = "search")]
         search: Option<String>,
     },
-    // TODO: Rm {},
+    /// Delete the latest version of a file, which reveals the version before it if there is one
+    #[command(alias = "del")]
+    Rm {
+        /// Delete every version of the file, rather than just the latest one
+        #[arg(long, conflicts_with = "file_id")]
+        all_versions: bool,
+        /// Delete every version, or every listed file, without asking first
+        #[arg(short, long)]
+        yes: bool,
+        /// The id of the file version to delete, e.g. an older one shown by `changes`, instead of
+        /// the latest version at a path
+        #[arg(long, value_name = "id", conflicts_with_all = ["bucket", "file"])]
+        file_id: Option<String>,
+        /// Delete every file listed in this file (or stdin for `-`), one per line or separated
+        /// by NULs, relative to `bucket`
+        #[arg(long, value_name = "list", conflicts_with_all = ["file", "file_id"])]
+        files_from: Option<PathBuf>,
+        /// Copy the latest version of each file into this bucket, under `<time>/<bucket>/`,
+        /// before deleting it so that it can be recovered
+        #[arg(
+            long,
+            value_name = "bucket",
+            conflicts_with_all = ["file_id", "all_versions"]
+        )]
+        trash_bucket: Option<String>,
+        /// The bucket which contains the file, or `b2://bucket/file`
+        #[arg(value_name = "bucket", required_unless_present = "file_id")]
+        bucket: Option<RemotePath>,
+        /// The path of the file
+        #[arg(value_name = "file")]
+        file: Option<String>,
+    },
     // TODO: GetUrl {},
     // TODO: Sync {},
     // TODO: UpdateBucket {},
     /// Upload a file to b2, if `dest` is not specified, then it will take the name of the file
     /// that is uploaded.
+    #[command(alias = "put")]
     Upload {
         /// Upload the file using the "parts" api
         /// Note: this is automatically enabled if the file that is being uploaded is more than 1GiB
         #[arg(short, long)]
         parts: bool,
+        /// When uploading in parts over a file that was also uploaded in parts with `--delta`,
+        /// copy the parts that haven't changed instead of uploading them again. This reads the
+        /// file an extra time to hash its parts first.
+        #[arg(long)]
+        delta: bool,
+        /// Read back the size and sha1 of each file after uploading it and check that they match
+        /// what was read from it, or the sha1 of each part of a large file
+        #[arg(long)]
+        verify_after_upload: bool,
         /// Manually override the Content Type of the file rather than trying to guess from the
         /// file extension
         #[arg(short, long, value_name = "content-type")]
         content_type: Option<String>,
+        /// Compute an extra checksum and store it in the file info, so that it can be verified
+        /// when downloading (b2 always stores the sha1)
+        #[arg(long, value_enum, value_name = "algorithm")]
+        checksum: Vec<ChecksumAlgorithm>,
+        /// Compress files before uploading them, they are decompressed again when downloaded
+        /// (`auto` skips files that are already compressed, like images, video and archives)
+        #[arg(long, value_enum, value_name = "codec", default_value_t = Compression::None)]
+        compress: Compression,
         /// Upload directories recursively
         #[arg(short, long)]
         recursive: bool,
+        /// When uploading recursively, record empty directories by uploading a `.bzEmpty`
+        /// placeholder into them
+        #[arg(long, requires = "recursive")]
+        empty_dirs: bool,
+        /// When uploading recursively, skip files whose names aren't valid UTF-8 rather than
+        /// replacing the invalid parts of the name
+        #[arg(long, requires = "recursive")]
+        skip_invalid_names: bool,
+        /// When uploading recursively, remove this many leading components from each local
+        /// path before joining it onto `dest`, like `tar` (files with no components left are
+        /// skipped)
+        #[arg(long, value_name = "n", default_value_t = 0, requires = "recursive")]
+        strip_components: usize,
+        /// When uploading recursively, replace the start of each key (after stripping) that
+        /// matches `from` with `to`, e.g. `--rewrite src/=assets/`. The first that matches is
+        /// used.
+        #[arg(long, value_name = "from=to", value_parser = parse_key_value, requires = "recursive")]
+        rewrite: Vec<(String, String)>,
+        /// When uploading recursively, include hidden files and directories (those starting
+        /// with `.`)
+        #[arg(long, requires = "recursive", overrides_with = "no_hidden")]
+        hidden: bool,
+        /// When uploading recursively, skip hidden files and directories (the default)
+        #[arg(long, requires = "recursive")]
+        no_hidden: bool,
+        /// When uploading recursively, include files matched by `.gitignore`, `.ignore` and
+        /// `.b2ignore` files rather than skipping them
+        #[arg(long, requires = "recursive")]
+        no_ignore: bool,
+        /// Carry on with an interrupted recursive upload, skipping the files that it finished
+        /// (as long as they haven't changed since)
+        #[arg(long, requires = "recursive", conflicts_with = "restart")]
+        resume: bool,
+        /// Upload everything again, forgetting about an interrupted recursive upload
+        #[arg(long, requires = "recursive")]
+        restart: bool,
+        /// Upload with the content type, cache control and custom file info in this JSON file,
+        /// e.g. `{"contentType": "text/html", "cacheControl": "max-age=3600", "info": {"k": "v"}}`.
+        /// When uploading recursively, a `.b2meta.json` in a directory applies to the files
+        /// under it on top of this.
+        #[arg(long, value_name = "file")]
+        attach_metadata_from_json: Option<PathBuf>,
+        /// Don't upload a file if one with the same name already exists, exiting with code 3
+        /// once everything else has been uploaded
+        #[arg(long)]
+        if_none_match: bool,
+        /// Mark each file to be deleted this long after it is uploaded (e.g. `30d`, with a unit
+        /// of s, m, h, d or w), which is done by `b2 expire`
+        #[arg(long, value_name = "age", value_parser = parse_age)]
+        delete_after: Option<chrono::Duration>,
+        /// Delete each local file once it has been uploaded and its size and sha1 (or the sha1 of
+        /// each part of a large file) have been read back and checked
+        #[arg(long)]
+        delete_after_upload: bool,
+        /// Move each local file into this directory once it has been uploaded and checked,
+        /// keeping its path relative to the uploaded directory
+        #[arg(long, value_name = "dir", conflicts_with = "delete_after_upload")]
+        move_to: Option<PathBuf>,
+        /// Upload only the files listed in this file (or stdin for `-`), one per line or
+        /// separated by NULs, relative to `file`, which must then be a directory. They are
+        /// uploaded as if with `-r`, but ignore files and hidden files aren't skipped.
+        #[arg(long, value_name = "list")]
+        files_from: Option<PathBuf>,
+        /// When uploading recursively, try the files that failed again this many times once
+        /// the rest are done
+        #[arg(long, value_name = "n", default_value_t = 2)]
+        retries: u32,
+        /// Where to record the files that still failed after retrying, for `b2 retry`
+        #[arg(long, value_name = "file", default_value = "b2-retry.json")]
+        retry_file: PathBuf,
+        /// The files to upload when run again by `b2 retry`
+        #[arg(skip)]
+        retry_files: Option<Vec<PathBuf>>,
+        /// Raise a desktop notification when the upload finishes or fails, if it took at least this
+        /// many seconds (30 when just `--notify` is given)
+        #[arg(
+            long,
+            value_name = "seconds",
+            num_args = 0..=1,
+            require_equals = true,
+            default_missing_value = "30"
+        )]
+        notify: Option<u64>,
         /// The path to the file to upload
         #[arg(value_name = "file")]
         file: PathBuf,
-        /// The bucket into which the file should be uploaded
+        /// The bucket into which the file should be uploaded, or `b2://bucket/dest`
         #[arg(value_name = "bucket")]
-        bucket: String,
+        bucket: RemotePath,
         /// The destination file path relative to the root of the bucket
         #[arg(value_name = "dest")]
-        dest: Option<PathBuf>,
+        dest: Option<String>,
+    },
+    /// Upload the files that a recursive upload couldn't, using the file it recorded them in
+    Retry {
+        /// The retry file, `b2-retry.json` unless `--retry-file` was given
+        #[arg(value_name = "file")]
+        file: PathBuf,
     },
     // TODO: UploadUnboundStream {},
     // TODO: UpdateFileLegalHold {},
-    // TODO: UpdateFileRetention {},
+    /// Print the last lines of a file without downloading all of it
+    Tail {
+        /// The number of lines to print
+        #[arg(short = 'n', long, default_value_t = 10)]
+        lines: usize,
+        /// The bucket which contains the file, or `b2://bucket/file`
+        #[arg(value_name = "bucket")]
+        bucket: RemotePath,
+        /// The path of the file
+        #[arg(value_name = "file")]
+        file: Option<String>,
+    },
+    /// Compare a local file with a file in b2 byte by byte, like `cmp`, only downloading as
+    /// far as the first difference. Exits with 1 if they differ
+    Cmp {
+        /// The local file
+        #[arg(value_name = "local")]
+        local: PathBuf,
+        /// The bucket which contains the file, or `b2://bucket/file`
+        #[arg(value_name = "bucket")]
+        bucket: RemotePath,
+        /// The path of the file
+        #[arg(value_name = "file")]
+        file: Option<String>,
+    },
+    /// Summarise every bucket: its files, the largest ones, unfinished large files, lifecycle
+    /// rules and whether it is public, e.g. for a periodic audit
+    Report {
+        /// How to print the report
+        #[arg(long, value_enum, default_value_t = report::Format::Markdown)]
+        output: report::Format,
+    },
+    /// Compare the files in two buckets (or prefixes) by name, size and sha1, e.g. to check on
+    /// replication. Exits with 1 if there are any differences
+    VerifyMirror {
+        /// How to print the differences
+        #[arg(long, value_enum, default_value_t = OutputFormat::Table)]
+        output: OutputFormat,
+        /// The source, as `bucket` or `b2://bucket/prefix`
+        #[arg(value_name = "src")]
+        src: RemotePath,
+        /// The mirror, as `bucket` or `b2://bucket/prefix`
+        #[arg(value_name = "dst")]
+        dst: RemotePath,
+    },
+    /// List the files uploaded or hidden since a time, by scanning every version (b2 keeps no
+    /// record of deleted versions, so those can't be listed)
+    Changes {
+        /// Only list changes at or after this date (`2024-01-31`, midnight UTC) or RFC 3339 time
+        #[arg(long, value_name = "time", value_parser = parse_since)]
+        since: DateTime<Utc>,
+        /// How to print the changes, every format other than `table` includes the file ids
+        #[arg(long, value_enum, default_value_t = OutputFormat::Table)]
+        output: OutputFormat,
+        /// How to show times: `short`, `iso`, `full`, `relative` (e.g. `3 days ago`)
+        /// or a strftime format like `+%Y-%m-%d %H:%M`
+        #[arg(long, value_name = "style", default_value = "full")]
+        time_style: TimeStyle,
+        /// The bucket to look for changes in, or `b2://bucket/prefix`
+        #[arg(value_name = "bucket")]
+        bucket: RemotePath,
+        /// Only look for changes under this prefix
+        #[arg(value_name = "prefix")]
+        prefix: Option<String>,
+    },
+    /// Measure the speed of uploads and downloads by sending random data to a bucket, which is
+    /// deleted afterwards
+    Bench {
+        /// The size of each file, e.g. `10M` (10 MiB)
+        #[arg(short, long, value_name = "size", default_value = "10M", value_parser = parse_size)]
+        size: u64,
+        /// How many files to upload and download
+        #[arg(short, long, value_name = "count", default_value_t = 16)]
+        count: usize,
+        /// How many files to transfer at once
+        #[arg(short = 'j', long, value_name = "threads", default_value_t = 4)]
+        concurrency: usize,
+        /// The bucket to send the files to, or `b2://bucket/prefix`
+        #[arg(value_name = "bucket")]
+        bucket: RemotePath,
+    },
+    /// Wait for a file to be uploaded, e.g. for an artifact from another job in CI
+    Wait {
+        /// Wait for a new version of the file if it already exists
+        #[arg(short, long)]
+        new: bool,
+        /// How many seconds to wait between checks
+        #[arg(short, long, value_name = "seconds", default_value_t = 5)]
+        interval: u64,
+        /// How many seconds to wait before giving up
+        #[arg(short, long, value_name = "seconds", default_value_t = 300)]
+        timeout: u64,
+        /// The bucket which will contain the file, or `b2://bucket/file`
+        #[arg(value_name = "bucket")]
+        bucket: RemotePath,
+        /// The path of the file
+        #[arg(value_name = "file")]
+        file: Option<String>,
+    },
+    /// Change the retention settings of a file in a bucket with file lock enabled
+    UpdateFileRetention {
+        /// The retention mode to apply, `none` removes the retention
+        #[arg(long, value_enum)]
+        mode: RetentionMode,
+        /// When the retention expires, e.g. `2030-01-01T00:00:00Z` (required unless the mode is
+        /// `none`)
+        #[arg(long, value_name = "date")]
+        retain_until: Option<DateTime<Utc>>,
+        /// Allow removing or shortening governance mode retention (requires the
+        /// `bypassGovernance` capability)
+        #[arg(long)]
+        bypass_governance: bool,
+        /// The id of the file version to change, e.g. an older one shown by `changes`, instead of
+        /// the latest version at a path
+        #[arg(long, value_name = "id", conflicts_with_all = ["bucket", "file"])]
+        file_id: Option<String>,
+        /// The bucket which contains the file, or `b2://bucket/file`
+        #[arg(value_name = "bucket", required_unless_present = "file_id")]
+        bucket: Option<RemotePath>,
+        /// The path of the file
+        #[arg(value_name = "file")]
+        file: Option<String>,
+    },
     // TODO: ReplicationSetup {},
     // TODO: ReplicationDelete {},
     // TODO: ReplicationPause {},
@@ -148,3 +774,238 @@ pub enum Command {
     // TODO: License {},
     // TODO: InstallAutocomplete {},
 }
+
+#[derive(Subcommand, Debug)]
+pub enum NotificationsCommand {
+    /// List the notification rules of a bucket
+    Ls {
+        /// The bucket whose rules should be listed
+        #[arg(value_name = "bucket")]
+        bucket: String,
+    },
+    /// Trigger a notification rule by uploading and then deleting a probe file that matches it,
+    /// and report whether B2 suspended the rule because its webhook failed
+    Test {
+        /// The bucket which has the rule
+        #[arg(value_name = "bucket")]
+        bucket: String,
+        /// The name of the rule to test
+        #[arg(value_name = "rule")]
+        rule: String,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum BucketCommand {
+    /// Save the type, info, CORS rules, lifecycle rules, encryption and file lock settings of a
+    /// bucket as JSON
+    Export {
+        /// The bucket whose settings are exported
+        #[arg(value_name = "bucket")]
+        bucket: String,
+        /// The file to save the settings to, otherwise they are printed
+        #[arg(value_name = "file")]
+        file: Option<PathBuf>,
+    },
+    /// Apply settings saved by `export` to a bucket, creating it if it doesn't exist
+    Import {
+        /// The bucket to apply the settings to
+        #[arg(value_name = "bucket")]
+        bucket: String,
+        /// The file containing the settings
+        #[arg(value_name = "file")]
+        file: PathBuf,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum KeyCommand {
+    /// Create a new key with the same capabilities and restrictions as the current one, switch
+    /// to it, and then delete the current key
+    Rotate,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum SnapshotsCommand {
+    /// Take a snapshot of a directory, uploading only the files whose contents aren't already
+    /// in the repository
+    Create {
+        /// The directory to take a snapshot of
+        #[arg(value_name = "dir")]
+        dir: PathBuf,
+        /// The bucket which contains the repository, or `b2://bucket/prefix`
+        #[arg(value_name = "bucket")]
+        bucket: RemotePath,
+        /// The prefix of the repository within the bucket
+        #[arg(value_name = "prefix")]
+        prefix: Option<String>,
+    },
+    /// Delete the snapshots that aren't kept by the `--keep-*` rules, and the blobs which only
+    /// they use, e.g. `--keep-daily 7 --keep-weekly 4 --keep-monthly 12`
+    Prune {
+        /// Keep this many of the newest snapshots
+        #[arg(long, value_name = "n", default_value_t = 0)]
+        keep_last: usize,
+        /// Keep the newest snapshot in each of the last `n` hours that have snapshots
+        #[arg(long, value_name = "n", default_value_t = 0)]
+        keep_hourly: usize,
+        /// Keep the newest snapshot in each of the last `n` days that have snapshots
+        #[arg(long, value_name = "n", default_value_t = 0)]
+        keep_daily: usize,
+        /// Keep the newest snapshot in each of the last `n` weeks that have snapshots
+        #[arg(long, value_name = "n", default_value_t = 0)]
+        keep_weekly: usize,
+        /// Keep the newest snapshot in each of the last `n` months that have snapshots
+        #[arg(long, value_name = "n", default_value_t = 0)]
+        keep_monthly: usize,
+        /// Keep the newest snapshot in each of the last `n` years that have snapshots
+        #[arg(long, value_name = "n", default_value_t = 0)]
+        keep_yearly: usize,
+        /// Only show what would be removed
+        #[arg(short = 'n', long)]
+        dry_run: bool,
+        /// Delete without asking first
+        #[arg(short, long)]
+        yes: bool,
+        /// The bucket which contains the repository, or `b2://bucket/prefix`
+        #[arg(value_name = "bucket")]
+        bucket: RemotePath,
+        /// The prefix of the repository within the bucket
+        #[arg(value_name = "prefix")]
+        prefix: Option<String>,
+    },
+    /// List the snapshots in a repository
+    Ls {
+        /// The bucket which contains the repository, or `b2://bucket/prefix`
+        #[arg(value_name = "bucket")]
+        bucket: RemotePath,
+        /// The prefix of the repository within the bucket
+        #[arg(value_name = "prefix")]
+        prefix: Option<String>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum TagCommand {
+    /// Add tags to a file
+    Add {
+        /// The tags to add
+        #[arg(short, long = "tag", value_name = "tag", required = true)]
+        tags: Vec<String>,
+        /// The bucket which contains the file, or `b2://bucket/file`
+        #[arg(value_name = "bucket")]
+        bucket: RemotePath,
+        /// The path of the file
+        #[arg(value_name = "file")]
+        file: Option<String>,
+    },
+    /// Remove tags from a file
+    Remove {
+        /// The tags to remove
+        #[arg(short, long = "tag", value_name = "tag", required = true)]
+        tags: Vec<String>,
+        /// The bucket which contains the file, or `b2://bucket/file`
+        #[arg(value_name = "bucket")]
+        bucket: RemotePath,
+        /// The path of the file
+        #[arg(value_name = "file")]
+        file: Option<String>,
+    },
+    /// List the tags of a file
+    List {
+        /// The bucket which contains the file, or `b2://bucket/file`
+        #[arg(value_name = "bucket")]
+        bucket: RemotePath,
+        /// The path of the file
+        #[arg(value_name = "file")]
+        file: Option<String>,
+    },
+}
+
+fn parse_key_value(s: &str) -> anyhow::Result<(String, String)> {
+    let Some((key, value)) = s.split_once('=') else {
+        anyhow::bail!("expected `key=value`");
+    };
+    Ok((key.to_string(), value.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use clap::{CommandFactory, Parser};
+
+    use super::{Cli, Command};
+
+    #[test]
+    fn definition() {
+        Cli::command().debug_assert();
+    }
+
+    /// `wait` has its own `--timeout`, which mustn't clash with the global `--deadline`
+    #[test]
+    fn wait_timeout_and_deadline() {
+        let cli = Cli::try_parse_from([
+            "b2",
+            "--deadline",
+            "2m",
+            "wait",
+            "-t",
+            "5",
+            "bucket",
+            "file",
+        ])
+        .unwrap();
+        assert_eq!(cli.deadline, Some(Duration::from_secs(120)));
+        let Command::Wait { timeout, .. } = cli.command else {
+            panic!("not a wait: {:?}", cli.command);
+        };
+        assert_eq!(timeout, 5);
+
+        let cli = Cli::try_parse_from(["b2", "wait", "bucket/file", "--deadline", "10"]).unwrap();
+        assert_eq!(cli.deadline, Some(Duration::from_secs(10)));
+        let Command::Wait { timeout, .. } = cli.command else {
+            panic!("not a wait: {:?}", cli.command);
+        };
+        assert_eq!(timeout, 300);
+    }
+
+    #[test]
+    fn rm_files_from_and_trash() {
+        let cli = Cli::try_parse_from([
+            "b2",
+            "del",
+            "--files-from",
+            "-",
+            "--trash-bucket",
+            "trash",
+            "photos",
+        ])
+        .unwrap();
+        let Command::Rm {
+            files_from,
+            trash_bucket,
+            file,
+            ..
+        } = cli.command
+        else {
+            panic!("not an rm: {:?}", cli.command);
+        };
+        assert_eq!(files_from.as_deref(), Some(std::path::Path::new("-")));
+        assert_eq!(trash_bucket.as_deref(), Some("trash"));
+        assert_eq!(file, None);
+
+        // Older versions can't be copied into the trash, so it only works for the latest one
+        assert!(Cli::try_parse_from([
+            "b2",
+            "rm",
+            "--all-versions",
+            "--trash-bucket",
+            "t",
+            "b",
+            "f"
+        ])
+        .is_err());
+        assert!(Cli::try_parse_from(["b2", "rm", "--files-from", "-", "b", "f"]).is_err());
+    }
+}
@@ -0,0 +1,66 @@
+use std::{fs, io::Read, path::Path};
+
+use colored::Colorize;
+
+use crate::{config::Config, path::RemotePath, peek};
+
+/// How much of each file is compared at a time, which is all that is downloaded past the first
+/// difference
+const CHUNK_SIZE: u64 = 4 * 1024 * 1024;
+
+/// Compare `local` with `remote` byte by byte, like `cmp`, downloading the remote file a chunk at
+/// a time so that files which differ early aren't downloaded in full.  Returns whether they are
+/// the same.
+pub fn cmp(cfg: &mut Config, local: &Path, remote: &RemotePath) -> anyhow::Result<bool> {
+    let local_size = fs::metadata(local)?.len();
+    let remote_size = peek::file_size(cfg, remote)?;
+    if local_size != remote_size {
+        println!(
+            "{}",
+            format!(
+                "Sizes differ: {} is {} bytes, {} is {} bytes",
+                local.display(),
+                local_size,
+                remote,
+                remote_size
+            )
+            .yellow()
+        );
+    }
+
+    let mut file = fs::File::open(local)?;
+    let len = std::cmp::min(local_size, remote_size);
+    let mut buf = vec![0; std::cmp::min(CHUNK_SIZE, len) as usize];
+    let mut offset = 0;
+    while offset < len {
+        let end = std::cmp::min(offset + CHUNK_SIZE, len);
+        let theirs = peek::get_range(cfg, remote, offset, end)?;
+        let ours = &mut buf[..(end - offset) as usize];
+        file.read_exact(ours)?;
+
+        if let Some(i) = ours.iter().zip(&theirs).position(|(a, b)| a != b) {
+            // Like `cmp`, bytes are counted from 1
+            println!(
+                "{} {} differ: byte {}",
+                local.display(),
+                remote,
+                offset + i as u64 + 1
+            );
+            return Ok(false);
+        }
+        offset = end;
+    }
+
+    if local_size != remote_size {
+        let shorter = if local_size < remote_size {
+            local.display().to_string()
+        } else {
+            remote.to_string()
+        };
+        println!("EOF on {} after byte {}", shorter, len);
+        return Ok(false);
+    }
+
+    println!("{}", "The files are the same".green());
+    Ok(true)
+}
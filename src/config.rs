@@ -2,19 +2,59 @@ use std::{
     collections::HashMap,
     fs,
     io::{BufRead, Write},
-    path::PathBuf,
+    os::unix::fs::{DirBuilderExt, OpenOptionsExt},
+    path::{Path, PathBuf},
 };
 
+use ::reqwest::{
+    header::{ACCEPT, RETRY_AFTER},
+    StatusCode,
+};
 use anyhow::bail;
+use chrono::{DateTime, NaiveTime, TimeDelta, Utc};
 use colored::Colorize;
 use reqwest::blocking as reqwest;
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
-use crate::api;
+use crate::{api, auth, cancel, http, path::RemotePath, transfer};
 
 const AUTHORISE_URL: &str = "https://api.backblazeb2.com/b2api/v3/b2_authorize_account";
 
-#[derive(Debug, Serialize, Deserialize, Default)]
+/// How many times a request is sent again after b2 answers with 429 or 503
+const OVERLOAD_RETRIES: u32 = 5;
+
+/// The longest that a request waits before being sent again after b2 answers with 429 or 503,
+/// whatever its `Retry-After` says
+const MAX_OVERLOAD_WAIT: u64 = 60;
+
+/// How long an auth token is valid for, minus a minute so that it isn't used right as it expires
+const AUTH_TOKEN_LIFETIME: TimeDelta = TimeDelta::seconds(24 * 60 * 60 - 60);
+
+/// Apis that don't change what the `b2_list_*` apis return, or that change files, whose callers
+/// forget the lists of just that bucket once they succeed with [`Config::forget_lists`]
+const KEEPS_LISTS: &[&str] = &[
+    "b2_get_file_info",
+    "b2_get_download_authorization",
+    "b2_get_bucket_notification_rules",
+    "b2_get_upload_url",
+    "b2_get_upload_part_url",
+    "b2_copy_part",
+    "b2_start_large_file",
+    "b2_finish_large_file",
+    "b2_cancel_large_file",
+    "b2_copy_file",
+    "b2_delete_file_version",
+    "b2_update_file_retention",
+];
+
+/// The scope of cached listings that aren't of a single bucket, e.g. `b2_list_buckets`
+const ACCOUNT_SCOPE: &str = "account";
+
+/// The smallest part b2 accepts, used when the config was saved before the account's
+/// `absolute_minimum_part_size` was
+const MIN_PART_SIZE: u64 = 5_000_000;
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
 #[serde(default)]
 pub struct Config {
     pub key_id: String,
@@ -22,43 +62,176 @@ pub struct Config {
     pub api_url: String,
     pub download_url: String,
     pub auth_token: String,
+    /// When the auth token stops being valid
+    pub auth_token_expires: Option<DateTime<Utc>>,
     pub account_id: String,
     // Bucket Name : Bucket Id
     pub buckets: HashMap<String, String>,
     pub recommended_part_size: u64,
+    /// The smallest part that b2 accepts for this account, other than the last part of a file
+    pub absolute_minimum_part_size: u64,
+    // Alias : Bucket Name/Prefix
+    pub aliases: HashMap<String, String>,
+    pub bandwidth: Vec<BandwidthWindow>,
+    /// A command which prints the credentials to use as JSON
+    pub credential_process: Option<String>,
+    /// A TOML file containing the credentials to use
+    pub credentials_file: Option<PathBuf>,
+    /// Store the key in the OS keyring instead of this file
+    pub keyring: bool,
+    /// Never store the key anywhere, only the auth token
+    pub ephemeral: bool,
+    pub http: HttpOptions,
+    /// Switch a simple upload over to parts when it's estimated to take longer than this many
+    /// seconds, `0` never switches (defaults to 300)
+    pub auto_parts_after: Option<u64>,
+    /// Keep the responses of list calls on disk for this many seconds, so that running several
+    /// commands over the same files doesn't list them again each time, `0` only keeps them for
+    /// the current command
+    pub list_cache_ttl: u64,
+    // Extension : Content Type, used before guessing the type from the extension
+    pub content_types: HashMap<String, String>,
+    /// Set by `--low-memory`, keep buffers small rather than fast
+    #[serde(skip)]
+    pub low_memory: bool,
+    /// Set by `--offline`, answer lists from the disk cache and make no requests
+    #[serde(skip)]
+    pub offline: bool,
+    /// The responses of list calls made since the last change, see [`Config::list`]
+    #[serde(skip)]
+    list_cache: HashMap<String, serde_json::Value>,
+    /// Where the config was loaded from, and so where it is saved to
+    #[serde(skip)]
+    path: PathBuf,
+    /// The config as it was when it was loaded, used to tell which fields have been changed since
+    /// then when saving
+    #[serde(skip)]
+    loaded: Option<Box<Config>>,
+}
+
+/// A daily window of time during which transfers are limited to a maximum speed
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BandwidthWindow {
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+    /// The maximum number of bytes per second, `0` pauses transfers for the whole window
+    pub limit: u64,
+}
+
+impl BandwidthWindow {
+    /// Whether `time` falls within this window, taking windows that wrap around midnight into
+    /// account
+    pub fn contains(&self, time: NaiveTime) -> bool {
+        if self.start <= self.end {
+            self.start <= time && time < self.end
+        } else {
+            self.start <= time || time < self.end
+        }
+    }
+}
+
+/// Options for the http client that is shared by all requests, see [`crate::http`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HttpOptions {
+    /// Speak HTTP/2 from the start instead of HTTP/1.1
+    pub http2: bool,
+    /// Grow the HTTP/2 flow control window to fit the measured bandwidth-delay product, rather
+    /// than using a fixed size that limits throughput on fast links with high latency
+    pub http2_adaptive_window: bool,
+    /// Seconds between TCP keepalive probes on idle connections, `0` disables them
+    pub tcp_keepalive: u64,
+    /// Seconds that an idle connection is kept open to be reused, `0` keeps them forever
+    pub pool_idle_timeout: u64,
+    /// The most idle connections to keep open to each host, which should be at least the number
+    /// of parts uploaded at once
+    pub pool_max_idle_per_host: usize,
+    /// Added to the end of the `User-Agent`, e.g. to tell apart the traffic of different
+    /// machines behind a proxy
+    pub user_agent_suffix: Option<String>,
+}
+
+impl Default for HttpOptions {
+    fn default() -> Self {
+        Self {
+            http2: false,
+            http2_adaptive_window: true,
+            tcp_keepalive: 60,
+            pool_idle_timeout: 90,
+            pool_max_idle_per_host: 16,
+            user_agent_suffix: None,
+        }
+    }
 }
 
 impl Config {
     pub fn load(file: Option<PathBuf>) -> anyhow::Result<Self> {
-        let file = if let Some(file) = file {
-            file
-        } else {
-            let Some(dir) = directories::ProjectDirs::from("com", "funnyboyroks", "b2") else {
-                bail!("No config dir available");
-            };
-            let mut cfg = dir.config_dir().to_path_buf();
-            fs::create_dir_all(&cfg)?;
-            cfg.push("config.toml");
-            cfg
+        let file = match file {
+            Some(file) => file,
+            None => default_path()?,
         };
-        if file.exists() {
-            let content = fs::read_to_string(file)?;
-            Ok(toml::from_str(&content)?)
-        } else {
-            Ok(Default::default())
+        let mut cfg = read(&file)?;
+        cfg.path = file;
+        // A config written by an older version, or only partly written, can have a token but no
+        // part size, so get a new token (and with it the part size) before it is used
+        if !cfg.auth_token.is_empty() && cfg.recommended_part_size == 0 {
+            cfg.auth_token.clear();
         }
+        cfg.loaded = Some(Box::new(cfg.clone()));
+        Ok(cfg)
     }
 
+    /// Save the config, keeping any changes that another invocation has saved since this one was
+    /// loaded in the fields that this one hasn't changed
+    ///
+    /// The config is locked while this happens, and written to a temporary file first so that it
+    /// is never seen half-written.
     pub fn save(&self) -> anyhow::Result<()> {
-        let Some(dir) = directories::ProjectDirs::from("com", "funnyboyroks", "b2") else {
-            bail!("No config dir available");
-        };
-        let mut cfg = dir.config_dir().to_path_buf();
-        fs::create_dir_all(&cfg)?;
-        cfg.push("config.toml");
+        let lock = fs::File::create(self.path.with_extension("lock"))?;
+        lock.lock()?;
+
+        let mut disk = read(&self.path)?;
+        if let Some(ref loaded) = self.loaded {
+            macro_rules! merge {
+                ($($field:ident),*) => {
+                    $(
+                        if self.$field != loaded.$field {
+                            disk.$field = self.$field.clone();
+                        }
+                    )*
+                };
+            }
+            merge!(
+                key_id,
+                key,
+                api_url,
+                download_url,
+                auth_token,
+                auth_token_expires,
+                account_id,
+                buckets,
+                recommended_part_size,
+                absolute_minimum_part_size,
+                aliases,
+                bandwidth,
+                credential_process,
+                credentials_file,
+                keyring,
+                ephemeral,
+                http,
+                auto_parts_after,
+                list_cache_ttl,
+                content_types
+            );
+        } else {
+            disk = self.clone();
+        }
 
-        fs::write(cfg, toml::to_string_pretty(self)?)?;
+        let tmp = self.path.with_extension("toml.tmp");
+        fs::write(&tmp, toml::to_string_pretty(&disk)?)?;
+        fs::rename(tmp, &self.path)?;
 
+        lock.unlock()?;
         Ok(())
     }
 
@@ -79,17 +252,34 @@ impl Config {
         let key = key.trim();
         println!("{}", key.red());
 
-        self.authorise(key_id, key)?;
+        let creds = auth::Credentials {
+            key_id: key_id.to_string(),
+            key: key.to_string(),
+        };
+        self.authorise(&creds)?;
+
+        if self.ephemeral {
+            self.key_id.clear();
+            self.key.clear();
+        } else if self.keyring {
+            auth::Keyring::store(&creds)?;
+            self.key_id.clear();
+            self.key.clear();
+        } else {
+            self.key_id = creds.key_id;
+            self.key = creds.key;
+        }
 
         println!("{}", "Authorised!".green());
 
         Ok(())
     }
 
-    pub fn authorise(&mut self, key_id: &str, key: &str) -> anyhow::Result<()> {
-        let client = reqwest::Client::new()
+    /// Get an auth token using `creds`, without saving them
+    pub fn authorise(&mut self, creds: &auth::Credentials) -> anyhow::Result<()> {
+        let client = http::client()
             .get(AUTHORISE_URL)
-            .header("Authorization", get_auth(key_id, key))
+            .header("Authorization", get_auth(&creds.key_id, &creds.key))
             .send()?;
 
         if client.status() != 200 {
@@ -99,13 +289,13 @@ impl Config {
 
         let json: api::AuthResponse = client.json()?;
 
-        self.key_id = key_id.to_string();
-        self.key = key.to_string();
         self.api_url = json.api_info.storage_api.api_url.clone();
         self.download_url = json.api_info.storage_api.download_url.clone();
         self.auth_token = json.authorization_token.clone();
+        self.auth_token_expires = Some(Utc::now() + AUTH_TOKEN_LIFETIME);
         self.account_id = json.account_id.clone();
         self.recommended_part_size = json.api_info.storage_api.recommended_part_size;
+        self.absolute_minimum_part_size = json.api_info.storage_api.absolute_minimum_part_size;
 
         Ok(())
     }
@@ -123,22 +313,46 @@ impl Config {
         F: FnMut(&mut Config) -> anyhow::Result<reqwest::Response>,
     {
         let mut loops = 5;
+        let mut overloaded = 0;
         loop {
-            let res = req(self)?;
+            let res = {
+                let _permit = http::limiter().acquire();
+                req(self)?
+            };
 
             if loops == 0 {
                 bail!("Unable to authorise with Backblaze.");
             }
 
-            if res.status() == 200 {
+            let status = res.status();
+            let busy = status == StatusCode::TOO_MANY_REQUESTS
+                || status == StatusCode::SERVICE_UNAVAILABLE;
+            if busy && overloaded < OVERLOAD_RETRIES {
+                http::limiter().overloaded();
+                // b2 says how long to wait with 429, otherwise back off exponentially
+                let wait = res
+                    .headers()
+                    .get(RETRY_AFTER)
+                    .and_then(|h| h.to_str().ok())
+                    .and_then(|h| h.parse().ok())
+                    .map_or(1 << overloaded, |secs: u64| secs);
+                cancel::sleep(std::time::Duration::from_secs(wait.min(MAX_OVERLOAD_WAIT)))?;
+                overloaded += 1;
+                continue;
+            }
+
+            // Range requests are answered with 206 Partial Content
+            if status.is_success() {
+                http::limiter().success();
                 break Ok(res);
             } else {
                 let url = res.url().clone();
-                let error: api::ApiError = res.json()?;
+                let mut error: api::ApiError = res.json()?;
                 if error.code == "expired_auth_token" {
                     self.reauth()?;
                 } else {
-                    bail!("`{}`: {} - {}", url, error.code, error.message);
+                    error.url = url.to_string();
+                    return Err(error.into());
                 }
             }
 
@@ -146,87 +360,503 @@ impl Config {
         }
     }
 
+    /// Get a new auth token, saving it straight away rather than when the command finishes so
+    /// that it isn't lost if the command is killed, or a second Ctrl-C stops it without cleaning
+    /// up
     pub fn reauth(&mut self) -> anyhow::Result<()> {
-        self.confirm_auth()?;
-
-        let client = reqwest::Client::new()
-            .get(AUTHORISE_URL)
-            .header("Authorization", get_auth(&self.key_id, &self.key))
-            .send()?;
-
-        if client.status() != 200 {
-            let error: api::ApiError = client.json()?;
-            bail!("{} - {}", error.code, error.message);
+        match auth::resolve(self)? {
+            Some(creds) => self.authorise(&creds)?,
+            None => self.auth_from_stdin()?,
         }
+        self.save()?;
 
-        let json: api::AuthResponse = client.json()?;
-
-        self.api_url = json.api_info.storage_api.api_url.clone();
-        self.download_url = json.api_info.storage_api.download_url.clone();
-        self.auth_token = json.authorization_token.clone();
-        self.account_id = json.account_id.clone();
-        self.recommended_part_size = json.api_info.storage_api.recommended_part_size;
-
+        // Everything that was changed has now been saved, so only what changes from here on needs
+        // saving again
+        self.loaded = None;
+        self.loaded = Some(Box::new(self.clone()));
         Ok(())
     }
 
+    /// Take the credentials and auth token from `other`, a copy of this config which had to
+    /// authorise again, e.g. on another thread.  It has already saved them.
+    pub fn adopt_auth(&mut self, other: &Config) {
+        self.key_id.clone_from(&other.key_id);
+        self.key.clone_from(&other.key);
+        self.api_url.clone_from(&other.api_url);
+        self.download_url.clone_from(&other.download_url);
+        self.auth_token.clone_from(&other.auth_token);
+        self.auth_token_expires = other.auth_token_expires;
+        self.account_id.clone_from(&other.account_id);
+        self.recommended_part_size = other.recommended_part_size;
+        self.absolute_minimum_part_size = other.absolute_minimum_part_size;
+    }
+
+    /// Make sure that there is an auth token, asking for credentials if none can be found
     pub fn confirm_auth(&mut self) -> anyhow::Result<()> {
-        if self.key.is_empty() || self.key_id.is_empty() {
-            self.auth_from_stdin()?;
+        let expired = self.auth_token_expires.is_some_and(|e| e <= Utc::now());
+        if (self.auth_token.is_empty() || expired) && !self.offline {
+            self.reauth()?;
         }
         Ok(())
     }
 
+    /// The smallest part that b2 accepts
+    pub fn min_part_size(&self) -> u64 {
+        if self.absolute_minimum_part_size == 0 {
+            MIN_PART_SIZE
+        } else {
+            self.absolute_minimum_part_size
+        }
+    }
+
+    /// The size of the parts to upload large files in, which is the size recommended for the
+    /// account but never less than the smallest that b2 accepts
+    pub fn part_size(&mut self) -> anyhow::Result<u64> {
+        if self.recommended_part_size == 0 {
+            self.confirm_auth()?;
+        }
+        Ok(std::cmp::max(
+            self.recommended_part_size,
+            self.min_part_size(),
+        ))
+    }
+
     pub fn api_url(&mut self, api_name: &str) -> anyhow::Result<String> {
+        if self.offline {
+            bail!(
+                "This needs to call `{}`, which can't be done with --offline",
+                api_name
+            );
+        }
+        // Anything else might change what any of the lists would return
+        if !api_name.starts_with("b2_list_") && !KEEPS_LISTS.contains(&api_name) {
+            self.forget_lists(None);
+        }
         self.confirm_auth()?;
         Ok(format!("{}/b2api/v3/{}", self.api_url, api_name))
     }
 
     /// Get a [`RequestBuilder`] for GET with the "Authorization" header set
     pub fn get(&mut self, api_name: &str) -> anyhow::Result<reqwest::RequestBuilder> {
-        Ok(reqwest::Client::new()
+        Ok(http::client()
             .get(self.api_url(api_name)?)
-            .header("Authorization", &self.auth_token))
+            .header("Authorization", &self.auth_token)
+            .header(ACCEPT, "application/json"))
     }
 
     /// Get a [`RequestBuilder`] for POST with the "Authorization" header set
     pub fn post(&mut self, api_name: &str) -> anyhow::Result<reqwest::RequestBuilder> {
-        Ok(reqwest::Client::new()
+        Ok(http::client()
             .post(self.api_url(api_name)?)
-            .header("Authorization", &self.auth_token))
+            .header("Authorization", &self.auth_token)
+            .header(ACCEPT, "application/json"))
     }
 
-    /// Get the list of buckets from the api
-    pub fn get_buckets(&mut self) -> anyhow::Result<()> {
-        let res = self
-            .get("b2_list_buckets")?
-            .query(&[("accountId", &self.account_id)])
-            .send()?;
-
-        let value = res.json::<serde_json::Value>()?;
+    /// Fetch all of the buckets in the account, updating the cached names and ids
+    pub fn get_buckets(&mut self) -> anyhow::Result<Vec<api::Bucket>> {
+        let account_id = self.account_id.clone();
+        let value = self.list("b2_list_buckets", &[("accountId", &account_id)])?;
         let buckets: Vec<api::Bucket> = Deserialize::deserialize(value["buckets"].clone())?;
 
         self.buckets.clear();
 
-        for bucket in buckets {
+        for bucket in &buckets {
             self.buckets
-                .insert(bucket.bucket_name.to_lowercase(), bucket.bucket_id);
+                .insert(bucket.bucket_name.to_lowercase(), bucket.bucket_id.clone());
         }
 
-        Ok(())
+        Ok(buckets)
     }
 
-    /// Return the bucket id for a name, and fetch the latest buckets from the api if we don't have
-    /// the name
+    /// Return the bucket id for a name (ignoring case) or a bucket id, and fetch the latest
+    /// buckets from the api if we don't have it
     /// Returns None if the bucket does not exist
     pub fn get_bucket_id<'a>(&'a mut self, name: &str) -> anyhow::Result<Option<&'a str>> {
-        if self.buckets.contains_key(&name.to_lowercase()) {
-            return Ok(Some(&self.buckets[name]));
+        if self.cached_bucket(name).is_none() {
+            self.get_buckets()?; // update our buckets to make sure the user has not created a new one
+        }
+
+        Ok(self.cached_bucket(name).map(|(_, id)| id))
+    }
+
+    /// Find the name and id of a bucket in the cached buckets, by its name or its id
+    fn cached_bucket(&self, name: &str) -> Option<(&str, &str)> {
+        if let Some((name, id)) = self.buckets.get_key_value(&name.to_lowercase()) {
+            return Some((name, id));
+        }
+        if is_bucket_id(name) {
+            return self
+                .buckets
+                .iter()
+                .find(|(_, id)| id.eq_ignore_ascii_case(name))
+                .map(|(name, id)| (name.as_str(), id.as_str()));
+        }
+        None
+    }
+
+    /// Like [`Config::get_bucket_id`], but a bucket that doesn't exist is a [`BucketNotFound`]
+    /// error
+    pub fn require_bucket_id(&mut self, name: &str) -> anyhow::Result<String> {
+        match self.get_bucket_id(name)? {
+            Some(id) => Ok(id.to_string()),
+            None => Err(self.bucket_not_found(name).into()),
+        }
+    }
+
+    /// The error for a bucket that doesn't exist, suggesting the closest cached name in case it
+    /// was a typo
+    pub fn bucket_not_found(&self, name: &str) -> BucketNotFound {
+        let lower = name.to_lowercase();
+        let closest = self
+            .buckets
+            .keys()
+            .map(|b| (edit_distance(&lower, b), b))
+            .filter(|(d, _)| *d <= std::cmp::max(1, name.len() / 3))
+            .min();
+        BucketNotFound {
+            name: name.to_string(),
+            suggestion: closest.map(|(_, b)| b.clone()),
+        }
+    }
+
+    /// Get the latest version of the file at `path`, or `None` if it does not exist
+    pub fn get_file(&mut self, path: &RemotePath) -> anyhow::Result<Option<api::File>> {
+        self.find_file(path, false)
+    }
+
+    /// Like [`Config::get_file`], but always asks b2 rather than answering from a cached
+    /// listing, for polling until the file changes
+    pub fn get_file_fresh(&mut self, path: &RemotePath) -> anyhow::Result<Option<api::File>> {
+        self.find_file(path, true)
+    }
+
+    fn find_file(&mut self, path: &RemotePath, refresh: bool) -> anyhow::Result<Option<api::File>> {
+        let bucket_id = self.require_bucket_id(&path.bucket)?;
+
+        let res = self.list_with(
+            "b2_list_file_names",
+            &[
+                ("bucketId", bucket_id.as_str()),
+                ("startFileName", &path.key),
+                ("maxFileCount", "1"),
+            ],
+            refresh,
+            true,
+        )?;
+
+        let files: Vec<api::File> = Deserialize::deserialize(res["files"].clone())?;
+        Ok(files.into_iter().find(|f| f.file_name == path.key))
+    }
+
+    /// Get a specific version of a file by its id, which needs no listing
+    pub fn get_file_by_id(&mut self, file_id: &str) -> anyhow::Result<api::File> {
+        self.send_request_de(|cfg| {
+            Ok(cfg
+                .get("b2_get_file_info")?
+                .query(&[("fileId", file_id)])
+                .send()?)
+        })
+    }
+
+    /// Call one of the `b2_list_*` apis once, reusing the response to an identical call made
+    /// since the last change, or one from the disk that is less than `list_cache_ttl` seconds old
+    pub fn list(
+        &mut self,
+        api_name: &str,
+        query: &[(&str, &str)],
+    ) -> anyhow::Result<serde_json::Value> {
+        self.list_with(api_name, query, false, true)
+    }
+
+    /// Fetch one page of a scan through a listing, which isn't kept in memory so that the whole
+    /// listing never is.  Pages are still cached on disk with `list_cache_ttl`, since that's what
+    /// `--offline` answers listings from.
+    pub fn list_page(
+        &mut self,
+        api_name: &str,
+        query: &[(&str, &str)],
+    ) -> anyhow::Result<serde_json::Value> {
+        self.list_with(api_name, query, false, false)
+    }
+
+    /// [`Config::list`], or if `refresh` is set, always call the api and replace whatever was
+    /// cached with the response.  The response is only kept in memory if `memory` is set, and
+    /// never with `--low-memory`.
+    fn list_with(
+        &mut self,
+        api_name: &str,
+        query: &[(&str, &str)],
+        refresh: bool,
+        memory: bool,
+    ) -> anyhow::Result<serde_json::Value> {
+        let memory = memory && !self.low_memory;
+        // Lists are cached by bucket, so that changing one bucket keeps the lists of the others
+        let scope = query
+            .iter()
+            .find(|(k, _)| *k == "bucketId")
+            .map_or(ACCOUNT_SCOPE, |(_, v)| *v);
+        let mut key = format!("{}/{}", scope, api_name);
+        for (k, v) in query {
+            key.push_str(&format!("&{}={}", k, urlencoding::encode(v)));
+        }
+        if let Some(res) = self.list_cache.get(&key).filter(|_| memory && !refresh) {
+            return Ok(res.clone());
+        }
+
+        // Listings depend on what the key may see, so each key has its own
+        let disk = list_cache_dir()
+            .filter(|_| (self.list_cache_ttl > 0 || self.offline) && !self.key_id.is_empty())
+            .map(|dir| {
+                dir.join(&self.key_id)
+                    .join(scope)
+                    .join(format!("{}.json", transfer::sha1_hex(key.as_bytes())))
+            });
+        let modified = disk
+            .as_ref()
+            .and_then(|path| fs::metadata(path).ok()?.modified().ok());
+        let fresh =
+            disk.as_ref()
+                .zip(modified)
+                .filter(|_| !refresh)
+                .and_then(|(path, modified)| {
+                    let age = modified.elapsed().ok()?;
+                    // Offline, anything cached is better than nothing
+                    (self.offline || age.as_secs() < self.list_cache_ttl)
+                        .then(|| fs::read(path).ok())?
+                });
+        if self.offline {
+            let Some(modified) = modified.filter(|_| fresh.is_some()) else {
+                bail!("This listing hasn't been cached, so it can't be answered with --offline");
+            };
+            let modified: DateTime<Utc> = modified.into();
+            eprintln!(
+                "{}",
+                format!(
+                    "Offline, as of {}",
+                    modified
+                        .with_timezone(&chrono::Local)
+                        .format("%e %h %Y %H:%M")
+                )
+                .yellow()
+            );
+        }
+
+        let res = match fresh.and_then(|b| serde_json::from_slice(&b).ok()) {
+            Some(res) => res,
+            None => {
+                let res: serde_json::Value =
+                    self.send_request_de(|cfg| Ok(cfg.get(api_name)?.query(query).send()?))?;
+                if let Some(path) = disk {
+                    // The cache is only an optimisation, so failing to write it is fine
+                    let _ = write_private(&path, res.to_string().as_bytes());
+                }
+                res
+            }
+        };
+        if memory {
+            self.list_cache.insert(key, res.clone());
+        }
+        Ok(res)
+    }
+
+    /// Drop the cached list responses of the bucket with the id `bucket_id`, or every one if it
+    /// is `None`, both in memory and on disk
+    pub fn forget_lists(&mut self, bucket_id: Option<&str>) {
+        match bucket_id {
+            Some(id) => {
+                let prefix = format!("{}/", id);
+                self.list_cache.retain(|key, _| !key.starts_with(&prefix));
+            }
+            None => self.list_cache.clear(),
+        }
+        if self.list_cache_ttl > 0 {
+            if let Some(dir) = list_cache_dir() {
+                match bucket_id {
+                    // Other keys may be able to see the bucket too
+                    Some(id) => {
+                        for key in fs::read_dir(&dir).into_iter().flatten().flatten() {
+                            let _ = fs::remove_dir_all(key.path().join(id));
+                        }
+                    }
+                    None => {
+                        let _ = fs::remove_dir_all(dir);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Delete a single version of a file
+    pub fn delete_file_version(&mut self, file: &api::File) -> anyhow::Result<()> {
+        let _: serde_json::Value = self.send_request_de(|cfg| {
+            Ok(cfg
+                .post("b2_delete_file_version")?
+                .json(&serde_json::json!({
+                    "fileName": file.file_name,
+                    "fileId": file.file_id,
+                }))
+                .send()?)
+        })?;
+        self.forget_lists(Some(&file.bucket_id));
+        Ok(())
+    }
+
+    /// List every file whose name starts with the key of `path`, following the pages of results
+    pub fn list_files(&mut self, path: &RemotePath) -> anyhow::Result<Vec<api::File>> {
+        let mut listing = api::Listing::files(self, path)?;
+        let files = listing.by_ref().collect();
+        listing.update_auth(self);
+        files
+    }
+
+    /// Call `f` with the latest version of every file under `path` as each page of the listing
+    /// arrives, so that the whole listing is never held in memory
+    pub fn for_each_file<F>(&mut self, path: &RemotePath, mut f: F) -> anyhow::Result<()>
+    where
+        F: FnMut(&mut Config, api::File) -> anyhow::Result<()>,
+    {
+        let mut listing = api::Listing::files(self, path)?;
+        while let Some(file) = listing.next() {
+            listing.update_auth(self);
+            f(self, file?)?;
+        }
+        listing.update_auth(self);
+        Ok(())
+    }
+
+    /// Get every version of every file under `path`, including hidden files and unfinished
+    /// large files, ordered by name and then newest first
+    pub fn list_file_versions(&mut self, path: &RemotePath) -> anyhow::Result<Vec<api::File>> {
+        let mut listing = api::Listing::versions(self, path)?;
+        let files = listing.by_ref().collect();
+        listing.update_auth(self);
+        files
+    }
+
+    /// Resolve a path whose bucket may be an alias from the `aliases` table into the real
+    /// bucket, prepending the alias' prefix to the key
+    ///
+    /// A bucket id that is in the cached buckets is also replaced with the bucket's name, since
+    /// download urls need the name.
+    pub fn resolve_alias(&self, path: RemotePath) -> anyhow::Result<RemotePath> {
+        let Some(target) = self.aliases.get(&path.bucket) else {
+            if is_bucket_id(&path.bucket) {
+                if let Some((name, _)) = self.cached_bucket(&path.bucket) {
+                    return Ok(RemotePath::new(name, path.key));
+                }
+            }
+            return Ok(path);
+        };
+
+        let target: RemotePath = target.parse()?;
+        let target = if target.is_dir() {
+            target
+        } else {
+            RemotePath::new(target.bucket, format!("{}/", target.key))
+        };
+
+        Ok(target.join(&path.key))
+    }
+}
+
+/// A bucket that was asked for doesn't exist, or can't be seen with the current key
+#[derive(Debug)]
+pub struct BucketNotFound {
+    pub name: String,
+    /// The closest bucket name, in case `name` was a typo
+    pub suggestion: Option<String>,
+}
+
+impl std::fmt::Display for BucketNotFound {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Bucket `{}` does not exist", self.name)?;
+        if let Some(ref suggestion) = self.suggestion {
+            write!(f, ", did you mean `{}`?", suggestion)?;
         }
+        Ok(())
+    }
+}
+
+impl std::error::Error for BucketNotFound {}
+
+/// Whether `s` looks like a bucket id (24 hex digits) rather than a name
+fn is_bucket_id(s: &str) -> bool {
+    s.len() == 24 && s.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// The number of single character insertions, deletions and substitutions to turn `a` into `b`
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut curr = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let sub = prev[j] + usize::from(ca != *cb);
+            curr[j + 1] = sub.min(prev[j + 1] + 1).min(curr[j] + 1);
+        }
+        prev = curr;
+    }
+    prev[b.len()]
+}
+
+/// The path of the config file in the platform's config directory
+fn default_path() -> anyhow::Result<PathBuf> {
+    let Some(dir) = directories::ProjectDirs::from("com", "funnyboyroks", "b2") else {
+        bail!("No config dir available");
+    };
+    let mut cfg = dir.config_dir().to_path_buf();
+    fs::create_dir_all(&cfg)?;
+    cfg.push("config.toml");
+    Ok(cfg)
+}
+
+/// Where [`Config::list`] keeps responses on disk
+fn list_cache_dir() -> Option<PathBuf> {
+    let dir = directories::ProjectDirs::from("com", "funnyboyroks", "b2")?;
+    Some(dir.cache_dir().join("lists"))
+}
+
+/// Write a cached listing to `path`, which only the user can read since it has the names of
+/// their files
+fn write_private(path: &Path, data: &[u8]) -> std::io::Result<()> {
+    fs::DirBuilder::new()
+        .recursive(true)
+        .mode(0o700)
+        .create(path.parent().unwrap())?;
+    fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(path)?
+        .write_all(data)
+}
 
-        self.get_buckets()?; // update our buckets to make sure the user has not created a new one
+/// The path of the config for the profile `name`, which is kept alongside the default config so
+/// that several accounts can be used side by side
+pub fn profile_path(name: &str) -> anyhow::Result<PathBuf> {
+    if name.is_empty()
+        || !name
+            .chars()
+            .all(|c| c.is_alphanumeric() || "-_.".contains(c))
+    {
+        bail!("Profile names may only contain letters, numbers, `-`, `_` and `.`");
+    }
+    let mut path = default_path()?;
+    path.set_file_name(format!("{}.toml", name));
+    if path.file_name() == Some("config.toml".as_ref()) {
+        bail!("`config` is the default profile");
+    }
+    Ok(path)
+}
 
-        Ok(self.buckets.get(name).map(|x| x.as_str()))
+/// Read the config at `file`, or the default config if it doesn't exist
+fn read(file: &Path) -> anyhow::Result<Config> {
+    if file.exists() {
+        let content = fs::read_to_string(file)?;
+        Ok(toml::from_str(&content)?)
+    } else {
+        Ok(Default::default())
     }
 }
 
@@ -0,0 +1,23 @@
+use std::io::{BufRead, IsTerminal, Write};
+
+/// Ask the user to confirm an action on stderr, defaulting to no.
+///
+/// If `yes` is set, this doesn't ask at all.  When stdin isn't a terminal, there is nobody to
+/// answer so this also doesn't ask, and the action is refused unless `yes` was given.
+pub fn confirm(prompt: &str, yes: bool) -> anyhow::Result<bool> {
+    if yes {
+        return Ok(true);
+    }
+
+    let stdin = std::io::stdin();
+    if !stdin.is_terminal() {
+        return Ok(false);
+    }
+
+    eprint!("{} (y/N) ", prompt);
+    std::io::stderr().flush()?;
+    let mut s = String::with_capacity(1);
+    stdin.lock().read_line(&mut s)?;
+
+    Ok(s.trim().eq_ignore_ascii_case("y"))
+}
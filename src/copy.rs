@@ -0,0 +1,58 @@
+use anyhow::bail;
+use colored::Colorize;
+use humanize_bytes::humanize_bytes_decimal;
+
+use crate::{api::File, config::Config, path::RemotePath, transfer::TransferJob};
+
+/// The largest file that `b2_copy_file` copies in one request
+const MAX_COPY_SIZE: u64 = 5_000_000_000;
+
+/// Copy `src` to `dest` within one account, on the server if the file is small enough to be
+/// copied in one request
+pub fn copy(cfg: &mut Config, src: &RemotePath, dest: &RemotePath) -> anyhow::Result<()> {
+    let Some(file) = cfg.get_file(src)? else {
+        bail!("{} does not exist", src);
+    };
+    copy_version(cfg, &file, src, dest)?;
+
+    println!(
+        "{}",
+        format!(
+            "Copied {} to {}!",
+            humanize_bytes_decimal!(file.content_length),
+            dest
+        )
+        .green()
+    );
+    Ok(())
+}
+
+/// Copy `file`, which is a version of `src`, to `dest` within one account
+pub fn copy_version(
+    cfg: &mut Config,
+    file: &File,
+    src: &RemotePath,
+    dest: &RemotePath,
+) -> anyhow::Result<()> {
+    if file.content_length > MAX_COPY_SIZE {
+        // Downloading by name only gets the latest version
+        let mut from = cfg.clone();
+        TransferJob::copy(src.clone(), dest.clone()).run_between(&mut from, cfg)?;
+        return Ok(());
+    }
+
+    let bucket_id = cfg.require_bucket_id(&dest.bucket)?;
+
+    let _: serde_json::Value = cfg.send_request_de(|cfg| {
+        Ok(cfg
+            .post("b2_copy_file")?
+            .json(&serde_json::json!({
+                "sourceFileId": file.file_id,
+                "destinationBucketId": bucket_id,
+                "fileName": dest.key,
+            }))
+            .send()?)
+    })?;
+    cfg.forget_lists(Some(&bucket_id));
+    Ok(())
+}
@@ -0,0 +1,71 @@
+use anyhow::bail;
+use chrono::{DateTime, Utc};
+use colored::Colorize;
+use humanize_bytes::humanize_bytes_decimal;
+
+use crate::{api::File, config::Config, confirm, path::RemotePath, retention};
+
+/// The file info key holding when a file should be deleted, in milliseconds since the epoch, set
+/// by `upload --delete-after`
+pub const INFO_KEY: &str = "b2-expires";
+
+/// When `file` should be deleted, if it was uploaded with `--delete-after`
+pub fn expires(file: &File) -> Option<DateTime<Utc>> {
+    file.file_info[INFO_KEY]
+        .as_str()
+        .and_then(|ms| ms.parse().ok())
+        .and_then(DateTime::from_timestamp_millis)
+}
+
+/// Delete the files under `path` whose `--delete-after` time has passed
+pub fn expire(cfg: &mut Config, path: &RemotePath, dry_run: bool, yes: bool) -> anyhow::Result<()> {
+    let now = Utc::now();
+    let mut expired = Vec::new();
+    cfg.for_each_file(path, |_, file| {
+        if expires(&file).is_some_and(|e| e <= now) {
+            println!("{}", file.file_name);
+            expired.push(file);
+        }
+        Ok(())
+    })?;
+
+    if expired.is_empty() {
+        println!("No files have expired");
+        return Ok(());
+    }
+
+    let total: u64 = expired.iter().map(|f| f.content_length).sum();
+    if dry_run {
+        println!(
+            "Would delete {} files ({})",
+            expired.len(),
+            humanize_bytes_decimal!(total)
+        );
+        return Ok(());
+    }
+
+    let prompt = format!(
+        "Delete these {} expired files ({})?",
+        expired.len(),
+        humanize_bytes_decimal!(total)
+    );
+    if !confirm::confirm(&prompt, yes)? {
+        bail!("Not deleting anything, pass --yes to delete without asking");
+    }
+
+    let mut locked = 0;
+    for file in &expired {
+        if retention::delete_unless_locked(cfg, file)? {
+            println!("{}", format!("Deleted {}", file.file_name).green());
+        } else {
+            locked += 1;
+        }
+    }
+    if locked > 0 {
+        eprintln!(
+            "{}",
+            format!("Skipped {} files that are locked", locked).yellow()
+        );
+    }
+    Ok(())
+}
@@ -1,8 +1,14 @@
 use std::{
     collections::BTreeMap,
     path::{Component, PathBuf},
+    str::FromStr,
 };
 
+use anyhow::bail;
+use chrono::{
+    format::{Item, StrftimeItems},
+    DateTime, Utc,
+};
 use colored::Colorize;
 use humanize_bytes::humanize_bytes_decimal;
 
@@ -16,7 +22,7 @@ pub enum FileTree {
     },
     File {
         name: String,
-        file: File,
+        file: Box<File>,
     },
     Root {
         children: BTreeMap<String, FileTree>,
@@ -74,7 +80,7 @@ pub fn files_to_tree(files: Vec<File>) -> FileTree {
                     FileTree::Directory { name: _, children } => children.insert(
                         last.to_string(),
                         FileTree::File {
-                            file,
+                            file: Box::new(file),
                             name: last.to_string(),
                         },
                     ),
@@ -82,7 +88,7 @@ pub fn files_to_tree(files: Vec<File>) -> FileTree {
                     FileTree::Root { children } => children.insert(
                         last.to_string(),
                         FileTree::File {
-                            file,
+                            file: Box::new(file),
                             name: last.to_string(),
                         },
                     ),
@@ -91,22 +97,159 @@ pub fn files_to_tree(files: Vec<File>) -> FileTree {
             api::Action::Folder => {
                 unimplemented!("{:?}", file);
             }
+            // Nothing sensible to show for actions that didn't exist when this was written
+            api::Action::Unknown(_) => {}
         }
     }
 
     tree
 }
 
-pub fn print_tree(tree: FileTree, long: bool) {
+impl FileTree {
+    /// The entries of a directory, or `None` for a file
+    pub fn children(&self) -> Option<&BTreeMap<String, FileTree>> {
+        match self {
+            FileTree::File { .. } => None,
+            FileTree::Directory { children, .. } | FileTree::Root { children } => Some(children),
+        }
+    }
+
+    /// Every file in this tree, in order
+    pub fn files(&self) -> Vec<&File> {
+        match self {
+            FileTree::File { file, .. } => vec![file],
+            FileTree::Directory { children, .. } | FileTree::Root { children } => {
+                children.values().flat_map(FileTree::files).collect()
+            }
+        }
+    }
+
+    /// The number of files in this tree and their total size
+    pub fn totals(&self) -> (usize, u64) {
+        match self {
+            FileTree::File { file, .. } => (1, file.content_length),
+            FileTree::Directory { children, .. } | FileTree::Root { children } => children
+                .values()
+                .map(FileTree::totals)
+                .fold((0, 0), |(n, size), (cn, csize)| (n + cn, size + csize)),
+        }
+    }
+}
+
+/// Print the `--summary` footer of a listing
+pub fn print_summary(files: usize, size: u64) {
+    println!(
+        "{} files, {} total",
+        files.to_string().bold(),
+        humanize_bytes_decimal!(size).bold()
+    );
+}
+
+/// How times are shown in listings, set with `--time-style`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum TimeStyle {
+    /// ` 1 Jan 2024`
+    #[default]
+    Short,
+    /// `2024-01-01`
+    Iso,
+    /// `2024-01-01 13:45:10 +0000`
+    Full,
+    /// `3 days ago`
+    Relative,
+    /// A `strftime` format, given as `+FORMAT`
+    Format(String),
+}
+
+impl TimeStyle {
+    pub fn format(&self, time: DateTime<Utc>) -> String {
+        match self {
+            TimeStyle::Short => time.format("%e %h %Y").to_string(),
+            TimeStyle::Iso => time.format("%Y-%m-%d").to_string(),
+            TimeStyle::Full => time.format("%Y-%m-%d %H:%M:%S %z").to_string(),
+            TimeStyle::Relative => relative(time),
+            TimeStyle::Format(format) => time.format(format).to_string(),
+        }
+    }
+}
+
+impl FromStr for TimeStyle {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "short" => Ok(TimeStyle::Short),
+            "iso" => Ok(TimeStyle::Iso),
+            "full" => Ok(TimeStyle::Full),
+            "relative" => Ok(TimeStyle::Relative),
+            _ => {
+                let Some(format) = s.strip_prefix('+') else {
+                    bail!("expected `short`, `iso`, `full`, `relative` or `+FORMAT`");
+                };
+                // chrono panics when printing an invalid format, so catch it here instead
+                if StrftimeItems::new(format).any(|i| i == Item::Error) {
+                    bail!("`{}` is not a valid strftime format", format);
+                }
+                Ok(TimeStyle::Format(format.to_string()))
+            }
+        }
+    }
+}
+
+/// How long ago `time` was, in the largest unit that fits, e.g. `3 days ago`
+fn relative(time: DateTime<Utc>) -> String {
+    const UNITS: [(i64, &str); 6] = [
+        (365 * 24 * 60 * 60, "year"),
+        (30 * 24 * 60 * 60, "month"),
+        (7 * 24 * 60 * 60, "week"),
+        (24 * 60 * 60, "day"),
+        (60 * 60, "hour"),
+        (60, "minute"),
+    ];
+
+    let secs = (Utc::now() - time).num_seconds();
+    let Some(&(unit_secs, unit)) = UNITS.iter().find(|(s, _)| secs.abs() >= *s) else {
+        return "just now".into();
+    };
+
+    let n = secs.abs() / unit_secs;
+    let plural = if n == 1 { "" } else { "s" };
+    if secs < 0 {
+        format!("in {} {}{}", n, unit, plural)
+    } else {
+        format!("{} {}{} ago", n, unit, plural)
+    }
+}
+
+/// Print `tree` with each file indented under its directory, `summary` adds the number of files
+/// and total size to each directory and the end.  Directories deeper than `depth` levels aren't
+/// expanded, but show what is under them like `--summary` does, so that the tree works as a `du`.
+pub fn print_tree(
+    tree: FileTree,
+    long: bool,
+    time_style: &TimeStyle,
+    summary: bool,
+    depth: Option<usize>,
+) {
     if long {
         println!(
             "  {}   {}   {}",
             "Size".underline(),
-            "Date Uploaded".underline(),
+            "Date Modified".underline(),
             "Name".underline()
         );
     }
-    print_tree_recur(tree, long, 0);
+    let totals = tree.totals();
+    let opts = TreeOptions {
+        long,
+        time_style,
+        summary,
+        depth,
+    };
+    print_tree_recur(tree, &opts, 0);
+    if summary {
+        print_summary(totals.0, totals.1);
+    }
 }
 
 fn print_indent(indent: usize) {
@@ -115,11 +258,24 @@ fn print_indent(indent: usize) {
     }
 }
 
-fn print_tree_recur(tree: FileTree, long: bool, indent: usize) {
+struct TreeOptions<'a> {
+    long: bool,
+    time_style: &'a TimeStyle,
+    summary: bool,
+    depth: Option<usize>,
+}
+
+fn print_tree_recur(tree: FileTree, opts: &TreeOptions, indent: usize) {
+    let TreeOptions {
+        long, time_style, ..
+    } = *opts;
+    // The children of a directory at `indent` are `indent + 2` levels deep
+    let collapsed = opts.depth.is_some_and(|depth| indent + 2 > depth);
+    let totals = (opts.summary || collapsed).then(|| tree.totals());
     match tree {
         FileTree::Root { children } => {
             for (_, child) in children {
-                print_tree_recur(child, long, indent);
+                print_tree_recur(child, opts, indent);
             }
         }
         FileTree::Directory { name, children } => {
@@ -127,9 +283,19 @@ fn print_tree_recur(tree: FileTree, long: bool, indent: usize) {
                 print!("                         ");
             }
             print_indent(indent);
-            println!("{}/", name.blue());
+            match totals {
+                Some((files, size)) => println!(
+                    "{}/ {}",
+                    name.blue(),
+                    format!("({} files, {})", files, humanize_bytes_decimal!(size)).dimmed()
+                ),
+                None => println!("{}/", name.blue()),
+            }
+            if collapsed {
+                return;
+            }
             for (_, child) in children {
-                print_tree_recur(child, long, indent + 1);
+                print_tree_recur(child, opts, indent + 1);
             }
         }
         FileTree::File { name, file } => {
@@ -141,7 +307,7 @@ fn print_tree_recur(tree: FileTree, long: bool, indent: usize) {
                         .unwrap()
                         .replace(' ', "")
                         .green(),
-                    file.upload_timestamp.format("%e %h %Y").to_string().blue(),
+                    time_style.format(file.last_modified()).blue(),
                 );
             }
             print_indent(indent);
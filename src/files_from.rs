@@ -0,0 +1,35 @@
+use std::{
+    ffi::OsStr,
+    fs,
+    io::Read,
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+
+/// Read the paths listed in `list` (or stdin if it is `-`), one per line, or separated by NULs if
+/// there are any so that the output of `find -print0` works with any file name.  Empty entries are
+/// skipped.
+pub fn read(list: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let mut data = Vec::new();
+    if list == Path::new("-") {
+        std::io::stdin().read_to_end(&mut data)?;
+    } else {
+        data = fs::read(list).with_context(|| format!("Couldn't read {}", list.display()))?;
+    }
+
+    let separator = if data.contains(&0) { 0 } else { b'\n' };
+    Ok(data
+        .split(|b| *b == separator)
+        .map(|entry| {
+            if separator == b'\n' {
+                entry.strip_suffix(b"\r").unwrap_or(entry)
+            } else {
+                entry
+            }
+        })
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| PathBuf::from(OsStr::from_bytes(entry)))
+        .collect())
+}
@@ -0,0 +1,257 @@
+use std::{process::Command, str::FromStr};
+
+use anyhow::bail;
+use chrono::{Duration, Utc};
+use colored::Colorize;
+use globset::GlobMatcher;
+
+use crate::{api::File, config::Config, confirm, path::RemotePath, retention, rm};
+
+/// How a value is compared against the one given, following the `+`/`-` prefixes of `find`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Comparison {
+    More,
+    Less,
+    Exactly,
+}
+
+/// Split the `+`/`-` prefix off of `s`
+fn comparison(s: &str) -> (Comparison, &str) {
+    if let Some(s) = s.strip_prefix('+') {
+        (Comparison::More, s)
+    } else if let Some(s) = s.strip_prefix('-') {
+        (Comparison::Less, s)
+    } else {
+        (Comparison::Exactly, s)
+    }
+}
+
+/// Split `s` into its number and unit suffix
+fn number(s: &str) -> anyhow::Result<(u64, &str)> {
+    let end = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let Ok(n) = s[..end].parse() else {
+        bail!("`{}` does not start with a number", s);
+    };
+    Ok((n, &s[end..]))
+}
+
+/// The number of bytes in a size unit, following `find -size`
+fn size_unit(unit: &str) -> anyhow::Result<u64> {
+    Ok(match unit {
+        "" | "c" => 1,
+        "k" | "K" => 1 << 10,
+        "M" => 1 << 20,
+        "G" => 1 << 30,
+        "T" => 1 << 40,
+        _ => bail!(
+            "Unknown size unit `{}`, expected one of c, k, M, G or T",
+            unit
+        ),
+    })
+}
+
+/// The length of a time unit, following `find -mtime` but with a few more units
+fn age_unit(unit: &str) -> anyhow::Result<Duration> {
+    Ok(match unit {
+        "s" => Duration::seconds(1),
+        "m" => Duration::minutes(1),
+        "h" => Duration::hours(1),
+        "" | "d" => Duration::days(1),
+        "w" => Duration::weeks(1),
+        _ => bail!(
+            "Unknown time unit `{}`, expected one of s, m, h, d or w",
+            unit
+        ),
+    })
+}
+
+/// Parse an age like `30d`, using the same units as [`AgeFilter`]
+pub fn parse_age(s: &str) -> anyhow::Result<Duration> {
+    let (n, unit) = number(s)?;
+    Ok(age_unit(unit)? * n as i32)
+}
+
+/// Parse a size like `100M` (100 MiB), using the same units as [`SizeFilter`]
+pub fn parse_size(s: &str) -> anyhow::Result<u64> {
+    let (n, unit) = number(s)?;
+    Ok(n * size_unit(unit)?)
+}
+
+/// A file size to compare against, like `+100M` (more than 100 MiB)
+#[derive(Debug, Clone, Copy)]
+pub struct SizeFilter {
+    comparison: Comparison,
+    n: u64,
+    unit: u64,
+}
+
+impl FromStr for SizeFilter {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (comparison, s) = comparison(s);
+        let (n, unit) = number(s)?;
+        let unit = size_unit(unit)?;
+        Ok(Self {
+            comparison,
+            n,
+            unit,
+        })
+    }
+}
+
+impl SizeFilter {
+    fn matches(&self, size: u64) -> bool {
+        match self.comparison {
+            Comparison::More => size > self.n * self.unit,
+            Comparison::Less => size < self.n * self.unit,
+            // Like `find`, sizes are rounded up to the unit
+            Comparison::Exactly => size.div_ceil(self.unit) == self.n,
+        }
+    }
+}
+
+/// An age to compare the modification time against, like `-7d` (modified in the last 7 days)
+#[derive(Debug, Clone, Copy)]
+pub struct AgeFilter {
+    comparison: Comparison,
+    age: Duration,
+    unit: Duration,
+}
+
+impl FromStr for AgeFilter {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (comparison, s) = comparison(s);
+        let (n, unit) = number(s)?;
+        let unit = age_unit(unit)?;
+        Ok(Self {
+            comparison,
+            age: unit * n as i32,
+            unit,
+        })
+    }
+}
+
+impl AgeFilter {
+    fn matches(&self, file: &File) -> bool {
+        let age = Utc::now() - file.last_modified();
+        match self.comparison {
+            Comparison::More => age > self.age,
+            Comparison::Less => age < self.age,
+            Comparison::Exactly => age >= self.age && age < self.age + self.unit,
+        }
+    }
+}
+
+/// The predicates that a file must match to be found
+pub struct Filter {
+    /// Matched against the last segment of the file name
+    pub name: Option<GlobMatcher>,
+    pub size: Option<SizeFilter>,
+    pub mtime: Option<AgeFilter>,
+}
+
+impl Filter {
+    fn matches(&self, file: &File) -> bool {
+        let name = file.file_name.rsplit('/').next().unwrap_or(&file.file_name);
+        self.name.as_ref().is_none_or(|g| g.is_match(name))
+            && self.size.is_none_or(|s| s.matches(file.content_length))
+            && self.mtime.is_none_or(|m| m.matches(file))
+    }
+}
+
+/// What to do with each file that is found
+pub enum Action {
+    Print,
+    /// Delete the latest version of each file, after confirming
+    Delete {
+        yes: bool,
+        /// Copy each file under this path before deleting it
+        trash: Option<RemotePath>,
+    },
+    /// Run a command for each file, replacing `{}` in the arguments with its `b2://` path
+    Exec(Vec<String>),
+}
+
+/// Find the files under `path` that match `filter`, and run `action` on them
+pub fn find(
+    cfg: &mut Config,
+    path: &RemotePath,
+    filter: &Filter,
+    action: &Action,
+) -> anyhow::Result<()> {
+    match action {
+        Action::Print => cfg.for_each_file(path, |_, file| {
+            if filter.matches(&file) {
+                println!("{}", file.file_name);
+            }
+            Ok(())
+        })?,
+        Action::Delete { yes, trash } => {
+            let files: Vec<_> = cfg
+                .list_files(path)?
+                .into_iter()
+                .filter(|f| filter.matches(f))
+                .collect();
+
+            for file in &files {
+                println!("{}", file.file_name);
+            }
+            if files.is_empty() {
+                return Ok(());
+            }
+            let total: u64 = files.iter().map(|f| f.content_length).sum();
+            let prompt = format!(
+                "Delete these {} files ({})?",
+                files.len(),
+                humanize_bytes::humanize_bytes_decimal!(total)
+            );
+            if !confirm::confirm(&prompt, *yes)? {
+                bail!("Not deleting anything, pass --yes to delete without asking");
+            }
+            let mut locked = 0;
+            for file in &files {
+                if let Some(trash) = trash {
+                    let src = RemotePath::new(&path.bucket, &file.file_name);
+                    rm::copy_to_trash(cfg, file, &src, trash)?;
+                }
+                if retention::delete_unless_locked(cfg, file)? {
+                    println!("{}", format!("Deleted {}", file.file_name).green());
+                } else {
+                    locked += 1;
+                }
+            }
+            if locked > 0 {
+                eprintln!(
+                    "{}",
+                    format!("Skipped {} files that are locked", locked).yellow()
+                );
+            }
+        }
+        Action::Exec(command) => {
+            let Some((program, args)) = command.split_first() else {
+                bail!("No command given to --exec");
+            };
+            cfg.for_each_file(path, |_, file| {
+                if !filter.matches(&file) {
+                    return Ok(());
+                }
+                let path = RemotePath::new(path.bucket.clone(), file.file_name).to_string();
+                let status = Command::new(program)
+                    .args(args.iter().map(|a| a.replace("{}", &path)))
+                    .status()?;
+                if !status.success() {
+                    eprintln!(
+                        "{}",
+                        format!("`{}` failed for {} ({})", program, path, status).red()
+                    );
+                }
+                Ok(())
+            })?;
+        }
+    }
+
+    Ok(())
+}
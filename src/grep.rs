@@ -0,0 +1,91 @@
+use std::io::{BufRead, BufReader};
+
+use colored::Colorize;
+use globset::GlobMatcher;
+use regex::Regex;
+
+use crate::{api::File, config::Config, http, path::RemotePath};
+
+/// Which of the files under the prefix are searched
+pub struct Filter {
+    /// Only search files whose name (relative to the prefix) matches this glob
+    pub glob: Option<GlobMatcher>,
+    /// Only search files whose Content-Type starts with this, e.g. `text/`
+    pub content_type: Option<String>,
+}
+
+impl Filter {
+    fn matches(&self, prefix: &str, file: &File) -> bool {
+        let name = file
+            .file_name
+            .strip_prefix(prefix)
+            .unwrap_or(&file.file_name);
+        self.glob.as_ref().is_none_or(|g| g.is_match(name))
+            && self
+                .content_type
+                .as_ref()
+                .is_none_or(|c| file.content_type.starts_with(c.as_str()))
+    }
+}
+
+/// Search every file under `path` for lines matching `pattern`, printing them as
+/// `file:line:match`, and return the number of matching lines
+pub fn grep(
+    cfg: &mut Config,
+    pattern: &Regex,
+    path: &RemotePath,
+    filter: &Filter,
+) -> anyhow::Result<usize> {
+    let mut matches = 0;
+    cfg.for_each_file(path, |cfg, file| {
+        if !filter.matches(&path.key, &file) {
+            return Ok(());
+        }
+
+        let url =
+            RemotePath::new(path.bucket.clone(), file.file_name.clone()).url(&cfg.download_url);
+        let res = cfg.send_request_res(|cfg| {
+            Ok(http::client()
+                .get(&url)
+                .header("Authorization", &cfg.auth_token)
+                .send()?)
+        })?;
+
+        let mut reader = BufReader::new(res);
+        let mut buf = Vec::new();
+        let mut line_no = 0;
+        loop {
+            buf.clear();
+            if reader.read_until(b'\n', &mut buf)? == 0 {
+                break;
+            }
+            line_no += 1;
+
+            let line = String::from_utf8_lossy(&buf);
+            let line = line.trim_end_matches(['\n', '\r']);
+            if !pattern.is_match(line) {
+                continue;
+            }
+            matches += 1;
+
+            let mut out = String::with_capacity(line.len());
+            let mut last = 0;
+            for m in pattern.find_iter(line) {
+                out.push_str(&line[last..m.start()]);
+                out.push_str(&m.as_str().red().bold().to_string());
+                last = m.end();
+            }
+            out.push_str(&line[last..]);
+
+            println!(
+                "{}:{}:{}",
+                file.file_name.yellow(),
+                line_no.to_string().green(),
+                out
+            );
+        }
+        Ok(())
+    })?;
+
+    Ok(matches)
+}
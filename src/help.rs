@@ -0,0 +1,117 @@
+use anyhow::bail;
+use clap::CommandFactory;
+use colored::Colorize;
+
+use crate::cli::Cli;
+
+/// A longer-form guide that can be shown with `b2 help <topic>`
+struct Topic {
+    name: &'static str,
+    summary: &'static str,
+    body: &'static str,
+}
+
+const TOPICS: &[Topic] = &[
+    Topic {
+        name: "auth",
+        summary: "How the account is authorised and where the keys are kept",
+        body: "\
+Run `b2 authorise` and enter an application key id and key, which can be
+created from the \"Application Keys\" page of the Backblaze web UI.  If any
+command needs the api before this has been done, the same prompts are
+shown first.
+
+The key id and key are saved in `config.toml` in the config directory for
+this platform (e.g. `~/.config/b2/` on Linux), along with the auth token
+that they are exchanged for.  With `b2 authorise --keyring`, the key is
+stored in the OS keyring instead.  The token expires after a day, at which
+point it is renewed automatically using the key.
+
+On shared machines, `b2 authorise --ephemeral` only saves the auth token,
+so the key has to be entered again once it expires.
+
+The key can also come from somewhere else, which are checked in order:
+
+1. `B2_APPLICATION_KEY_ID` and `B2_APPLICATION_KEY` in the environment
+2. `credential_process` in the config, a command which prints
+   `{\"key_id\": \"...\", \"key\": \"...\"}`
+3. `credentials_file` in the config, a TOML file with `key_id` and `key`
+4. the OS keyring, if `keyring = true` in the config
+5. the key saved in the config
+
+Keys which are restricted to a single bucket or prefix work, but commands
+that reach outside of what the key allows will fail with `unauthorized`.",
+    },
+    Topic {
+        name: "large-files",
+        summary: "How big files are split into parts when uploading",
+        body: "\
+Files of 1 GiB or more are uploaded using the large file api, which sends
+the file in parts of the size recommended by b2 (at least 5 MB each).
+Smaller files can be sent this way as well with `b2 upload --parts`.
+
+`b2 fetch` streams a url straight into b2, so if the server doesn't say
+how big the response is, it is always uploaded in parts.
+
+Every part is checked against its sha1 by b2 as it is received.  The
+sha1 of a large file as a whole isn't known by b2, so use
+`b2 upload --checksum sha256` and `b2 download --verify sha256` to check
+the file end to end.",
+    },
+    Topic {
+        name: "paths",
+        summary: "The different ways of naming a file in a bucket",
+        body: "\
+Anywhere a bucket and a file are expected, they can be given either as
+two arguments, or as one argument in the form `b2://bucket/path` or
+`bucket/path`:
+
+    b2 download my-bucket photos/cat.jpg
+    b2 download b2://my-bucket/photos/cat.jpg
+
+Names ending in `/` are treated as directories.  Uploading into one keeps
+the name of the local file, and `ls` and `tree` list everything under it.
+
+Aliases for a bucket and prefix can be added to the `[aliases]` table of
+the config file and used in place of the bucket:
+
+    [aliases]
+    photos = \"my-bucket/photos/\"",
+    },
+];
+
+/// Print the guide for `topic`, the help for the command named `topic`, or the list of topics
+pub fn print(topic: Option<&str>) -> anyhow::Result<()> {
+    let mut cmd = Cli::command();
+    // Fills in the usage of the subcommands with the binary name
+    cmd.build();
+
+    let Some(topic) = topic else {
+        cmd.print_help()?;
+        println!();
+        print_topics();
+        return Ok(());
+    };
+
+    if let Some(t) = TOPICS.iter().find(|t| t.name == topic) {
+        println!("{}", t.summary.bold().underline());
+        println!();
+        println!("{}", t.body);
+        return Ok(());
+    }
+
+    if let Some(sub) = cmd.find_subcommand_mut(topic) {
+        sub.print_long_help()?;
+        return Ok(());
+    }
+
+    print_topics();
+    bail!("No help topic or command called `{}`", topic);
+}
+
+fn print_topics() {
+    println!("{}", "Help topics:".bold().underline());
+    for t in TOPICS {
+        println!("  {:<12} {}", t.name.bold(), t.summary);
+    }
+}
@@ -0,0 +1,179 @@
+use std::{
+    sync::{Condvar, Mutex, MutexGuard, OnceLock},
+    time::Duration,
+};
+
+use ::reqwest::header::{self, HeaderMap, HeaderValue};
+use reqwest::blocking as reqwest;
+
+use crate::config::HttpOptions;
+
+/// The client used for every request, so that connections are kept alive and reused rather than
+/// each request paying for a new TCP and TLS handshake
+static CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+
+/// The most requests that [`Limiter`] lets through at once, which is also where it starts
+const MAX_IN_FLIGHT: f64 = 64.0;
+
+/// Shared by every thread, so that one of them being told to slow down slows all of them down
+static LIMITER: Limiter = Limiter::new();
+
+/// Build the shared client from `opts`, this must be called before [`client`] to have any effect
+pub fn init(opts: &HttpOptions) -> anyhow::Result<()> {
+    let client = build(opts)?;
+    // If the client was already built then the defaults are used, which is fine
+    let _ = CLIENT.set(client);
+    Ok(())
+}
+
+/// The shared client, built with the default options if [`init`] hasn't been called
+pub fn client() -> &'static reqwest::Client {
+    CLIENT.get_or_init(|| {
+        build(&HttpOptions::default()).expect("the default http options should be valid")
+    })
+}
+
+fn build(opts: &HttpOptions) -> anyhow::Result<reqwest::Client> {
+    let secs = |s: u64| (s > 0).then(|| Duration::from_secs(s));
+
+    let mut user_agent = format!("b2/{}", env!("CARGO_PKG_VERSION"));
+    if let Some(ref suffix) = opts.user_agent_suffix {
+        user_agent.push(' ');
+        user_agent.push_str(suffix);
+    }
+
+    // Nothing from b2 should be answered by a proxy from its cache, since listings and upload
+    // urls go stale and downloads should be of the file as it is now
+    let mut headers = HeaderMap::new();
+    headers.insert(header::CACHE_CONTROL, HeaderValue::from_static("no-store"));
+    headers.insert(header::PRAGMA, HeaderValue::from_static("no-cache"));
+
+    let mut builder = reqwest::Client::builder()
+        .user_agent(user_agent)
+        .default_headers(headers)
+        .tcp_keepalive(secs(opts.tcp_keepalive))
+        .pool_idle_timeout(secs(opts.pool_idle_timeout))
+        .pool_max_idle_per_host(opts.pool_max_idle_per_host)
+        .http2_adaptive_window(opts.http2_adaptive_window);
+    if opts.http2 {
+        builder = builder.http2_prior_knowledge();
+    }
+
+    Ok(builder.build()?)
+}
+
+/// Limits how many requests are in flight at once across every thread.  The limit is halved
+/// when b2 answers with 429 or 503, and grows back by one for every "limit" requests that
+/// succeed (AIMD, like TCP congestion control), so parallel work settles just under what b2 will
+/// take rather than repeatedly tripping its limits.  Like TCP, it is halved at most once per
+/// window: the requests that were already in flight when it was halved were sent into the same
+/// overload, so their answers don't halve it again.
+pub struct Limiter {
+    state: Mutex<LimiterState>,
+    freed: Condvar,
+}
+
+struct LimiterState {
+    limit: f64,
+    /// The highest that `limit` grows to
+    max: f64,
+    in_flight: usize,
+    /// How many requests have finished, successfully or not
+    completed: usize,
+    /// The value of `completed` before which being overloaded doesn't halve the limit again
+    window_end: usize,
+}
+
+/// A slot for one request, given back when dropped
+pub struct Permit(&'static Limiter);
+
+impl Limiter {
+    const fn new() -> Self {
+        Self {
+            state: Mutex::new(LimiterState {
+                limit: MAX_IN_FLIGHT,
+                max: MAX_IN_FLIGHT,
+                in_flight: 0,
+                completed: 0,
+                window_end: 0,
+            }),
+            freed: Condvar::new(),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, LimiterState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Wait until another request can be sent
+    pub fn acquire(&'static self) -> Permit {
+        let mut state = self.lock();
+        while state.in_flight >= state.limit as usize {
+            state = self.freed.wait(state).unwrap_or_else(|e| e.into_inner());
+        }
+        state.in_flight += 1;
+        Permit(self)
+    }
+
+    /// A request went through, so allow a little more
+    pub fn success(&self) {
+        let mut state = self.lock();
+        state.limit = (state.limit + 1.0 / state.limit).min(state.max);
+    }
+
+    /// Never let more than `max` requests be in flight at once
+    pub fn cap(&self, max: usize) {
+        let mut state = self.lock();
+        state.max = (max as f64).clamp(1.0, MAX_IN_FLIGHT);
+        state.limit = state.limit.min(state.max);
+    }
+
+    /// b2 is overloaded, so allow half as much, unless it was already halved for this overload
+    pub fn overloaded(&self) {
+        let mut state = self.lock();
+        if state.completed < state.window_end {
+            return;
+        }
+        state.limit = (state.limit / 2.0).max(1.0);
+        state.window_end = state.completed + state.in_flight + 1;
+    }
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        let mut state = self.0.lock();
+        state.in_flight -= 1;
+        state.completed += 1;
+        drop(state);
+        self.0.freed.notify_one();
+    }
+}
+
+/// The limiter that every request made through [`crate::config::Config::send_request_res`] goes
+/// through
+pub fn limiter() -> &'static Limiter {
+    &LIMITER
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Limiter, MAX_IN_FLIGHT};
+
+    #[test]
+    fn halves_once_per_window() {
+        static LIMITER: Limiter = Limiter::new();
+
+        // Every request of a burst is turned away by the same overload
+        let burst: Vec<_> = (0..16).map(|_| LIMITER.acquire()).collect();
+        for permit in burst {
+            drop(permit);
+            LIMITER.overloaded();
+        }
+        assert_eq!(LIMITER.lock().limit, MAX_IN_FLIGHT / 2.0);
+
+        // Once those have all come back, a new overload halves it again
+        drop(LIMITER.acquire());
+        LIMITER.overloaded();
+        assert_eq!(LIMITER.lock().limit, MAX_IN_FLIGHT / 4.0);
+    }
+}
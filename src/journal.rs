@@ -0,0 +1,104 @@
+use std::{
+    collections::HashSet,
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+use anyhow::bail;
+
+use crate::{path::RemotePath, transfer::ChecksumAlgorithm};
+
+/// A record of the files that a recursive upload has finished, so that running it again after
+/// it was interrupted carries on from where it stopped.  It is removed once the upload is done.
+pub struct Journal {
+    path: PathBuf,
+    done: HashSet<String>,
+    file: fs::File,
+}
+
+impl Journal {
+    /// Open the journal for uploading `src` into `dest`.  If one was left behind by an earlier
+    /// upload then `resume` or `restart` has to say what to do with it.
+    pub fn open(
+        src: &Path,
+        dest: &RemotePath,
+        resume: bool,
+        restart: bool,
+    ) -> anyhow::Result<Self> {
+        let Some(dirs) = directories::ProjectDirs::from("com", "funnyboyroks", "b2") else {
+            bail!("No data dir available");
+        };
+        let dir = dirs.data_dir().join("journals");
+        fs::create_dir_all(&dir)?;
+
+        let src = fs::canonicalize(src)?;
+        let mut sha = ChecksumAlgorithm::Sha1.hasher();
+        sha.update(src.to_string_lossy().as_bytes());
+        sha.update(&[0]);
+        sha.update(dest.to_string().as_bytes());
+        let path = dir.join(sha.finish_hex());
+
+        let done = match fs::read_to_string(&path) {
+            Ok(s) if resume => s.lines().map(String::from).collect(),
+            Ok(_) if restart => HashSet::new(),
+            Ok(_) => bail!(
+                "An earlier upload of {} to {} was interrupted, pass --resume to carry on with \
+                 it or --restart to start again",
+                src.display(),
+                dest
+            ),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => HashSet::new(),
+            Err(e) => return Err(e.into()),
+        };
+
+        if !done.is_empty() {
+            println!("Resuming, {} files were already uploaded", done.len());
+        }
+
+        let file = fs::OpenOptions::new()
+            .create(true)
+            .append(resume)
+            .write(true)
+            .truncate(!resume)
+            .open(&path)?;
+
+        Ok(Self { path, done, file })
+    }
+
+    /// Whether `entry` was uploaded by the interrupted upload and hasn't changed since
+    pub fn contains(&self, entry: &str) -> bool {
+        self.done.contains(entry)
+    }
+
+    /// Note that `entry` has been uploaded
+    pub fn record(&mut self, entry: &str) -> anyhow::Result<()> {
+        writeln!(self.file, "{}", entry)?;
+        self.file.flush()?;
+        Ok(())
+    }
+
+    /// The upload is finished, so the journal isn't needed any more
+    pub fn finish(self) -> anyhow::Result<()> {
+        drop(self.file);
+        fs::remove_file(self.path)?;
+        Ok(())
+    }
+}
+
+/// How the file at `relative` is recorded in a journal, which includes its size and
+/// modification time so that files changed since they were uploaded are uploaded again
+pub fn entry(relative: &Path, meta: &fs::Metadata) -> String {
+    let modified = meta
+        .modified()
+        .ok()
+        .and_then(|m| m.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |m| m.as_millis());
+    format!(
+        "{}\t{}\t{}",
+        meta.len(),
+        modified,
+        relative.to_string_lossy()
+    )
+}
@@ -0,0 +1,230 @@
+use anyhow::{bail, Context};
+use chrono::Utc;
+use colored::{ColoredString, Colorize};
+use serde::Deserialize;
+
+use crate::{
+    api::{Capability, Key},
+    auth::{self, CredentialProvider},
+    config::Config,
+};
+
+/// Which keys are listed
+pub struct Filter {
+    /// Only keys whose name starts with this
+    pub prefix: Option<String>,
+    /// Only keys restricted to this bucket
+    pub bucket: Option<String>,
+    pub show_expired: bool,
+}
+
+/// List all of the application keys in the account, following the pages of results
+pub fn list(cfg: &mut Config) -> anyhow::Result<Vec<Key>> {
+    let mut keys = Vec::new();
+    let mut start: Option<String> = None;
+    loop {
+        let mut query = vec![
+            ("accountId", cfg.account_id.clone()),
+            ("maxKeyCount", "1000".to_string()),
+        ];
+        if let Some(start) = start {
+            query.push(("startApplicationKeyId", start));
+        }
+
+        let res: serde_json::Value =
+            cfg.send_request_de(|cfg| Ok(cfg.get("b2_list_keys")?.query(&query).send()?))?;
+
+        let page: Vec<Key> = Deserialize::deserialize(res["keys"].clone())?;
+        keys.extend(page);
+
+        match res["nextApplicationKeyId"].as_str() {
+            Some(next) => start = Some(next.to_string()),
+            None => break Ok(keys),
+        }
+    }
+}
+
+/// List the keys that match `filter`
+pub fn list_filtered(cfg: &mut Config, filter: &Filter) -> anyhow::Result<Vec<Key>> {
+    let bucket_id = match filter.bucket {
+        Some(ref bucket) => Some(cfg.require_bucket_id(bucket)?),
+        None => None,
+    };
+
+    let mut keys = list(cfg)?;
+    keys.retain(|k| {
+        filter
+            .prefix
+            .as_ref()
+            .is_none_or(|p| k.key_name.starts_with(p.as_str()))
+            && bucket_id
+                .as_ref()
+                .is_none_or(|id| k.bucket_id.as_ref() == Some(id))
+            && (filter.show_expired || !k.is_expired())
+    });
+    Ok(keys)
+}
+
+/// Replace the application key that the CLI is using with a new one that has the same
+/// capabilities and restrictions, then delete the old key
+pub fn rotate(cfg: &mut Config) -> anyhow::Result<()> {
+    // Keys from elsewhere can't be replaced, and would stop working once the old key is deleted
+    if auth::Env.credentials()?.is_some() {
+        bail!("The application key is set in the environment, so it can't be rotated by the CLI");
+    }
+    if let Some(ref cmd) = cfg.credential_process {
+        bail!(
+            "The application key comes from `{}`, so it can't be rotated by the CLI",
+            cmd
+        );
+    }
+    if let Some(ref file) = cfg.credentials_file {
+        bail!(
+            "The application key comes from {}, so it can't be rotated by the CLI",
+            file.display()
+        );
+    }
+    let Some(old) = auth::resolve(cfg)? else {
+        bail!("There is no saved application key to rotate, run `b2 authorise` first");
+    };
+
+    cfg.authorise(&old)?;
+    let Some(key) = list(cfg)?
+        .into_iter()
+        .find(|k| k.application_key_id == old.key_id)
+    else {
+        bail!(
+            "Key {} was not found, the master application key can't be rotated",
+            old.key_id
+        );
+    };
+
+    for cap in [Capability::WriteKeys, Capability::DeleteKeys] {
+        if !key.capabilities.contains(&cap) {
+            bail!("Key {} does not have the {} capability", key.key_name, cap);
+        }
+    }
+
+    let mut body = serde_json::json!({
+        "accountId": cfg.account_id,
+        "capabilities": key.capabilities,
+        "keyName": key.key_name,
+    });
+    if let Some(ref bucket_id) = key.bucket_id {
+        body["bucketId"] = bucket_id.clone().into();
+    }
+    if let Some(ref prefix) = key.name_prefix {
+        body["namePrefix"] = prefix.clone().into();
+    }
+    if let Some(expires) = key.expires() {
+        // Keep the same expiry rather than extending it
+        let secs = (expires - Utc::now()).num_seconds();
+        if secs <= 0 {
+            bail!("Key {} has expired", key.key_name);
+        }
+        body["validDurationInSeconds"] = secs.into();
+    }
+
+    let res: serde_json::Value =
+        cfg.send_request_de(|cfg| Ok(cfg.post("b2_create_key")?.json(&body).send()?))?;
+    let new = auth::Credentials {
+        key_id: res["applicationKeyId"]
+            .as_str()
+            .context("b2_create_key did not return a key id")?
+            .to_string(),
+        key: res["applicationKey"]
+            .as_str()
+            .context("b2_create_key did not return a key")?
+            .to_string(),
+    };
+    println!("Created key {}", new.key_id.yellow());
+
+    // Make sure that the new key works before anything depends on it
+    if let Err(e) = cfg.authorise(&new) {
+        let _: serde_json::Value = cfg.send_request_de(|cfg| {
+            Ok(cfg
+                .post("b2_delete_key")?
+                .json(&serde_json::json!({ "applicationKeyId": new.key_id }))
+                .send()?)
+        })?;
+        return Err(e.context("The new key could not be used, so it has been deleted"));
+    }
+
+    if cfg.keyring {
+        auth::Keyring::store(&new)?;
+    } else {
+        cfg.key_id = new.key_id.clone();
+        cfg.key = new.key.clone();
+    }
+    // Save now so that the new key isn't lost if deleting the old one fails
+    cfg.save()?;
+
+    let _: serde_json::Value = cfg.send_request_de(|cfg| {
+        Ok(cfg
+            .post("b2_delete_key")?
+            .json(&serde_json::json!({ "applicationKeyId": old.key_id }))
+            .send()?)
+    })?;
+    println!("Deleted key {}", old.key_id.yellow());
+
+    println!("{}", "Rotated!".green());
+    Ok(())
+}
+
+pub fn print(cfg: &Config, keys: &[Key]) {
+    for key in keys {
+        let bucket = match key.bucket_id {
+            Some(ref id) => bucket_name(cfg, id),
+            None => "*",
+        };
+        let prefix = key.name_prefix.as_deref().unwrap_or_default();
+        let expires = match key.expires() {
+            Some(e) if key.is_expired() => format!("expired {}", e.format("%e %h %Y")).red(),
+            Some(e) => format!("expires {}", e.format("%e %h %Y")).normal(),
+            None => "".normal(),
+        };
+
+        println!(
+            "{}   {}   {}{}   {}",
+            key.application_key_id,
+            key.key_name.yellow(),
+            bucket.blue(),
+            if prefix.is_empty() {
+                String::new()
+            } else {
+                format!("/{}", prefix)
+            },
+            expires
+        );
+
+        let caps: Vec<_> = key
+            .capabilities
+            .iter()
+            .map(|c| capability(c.as_str()).to_string())
+            .collect();
+        println!("    {}", caps.join(" "));
+    }
+}
+
+/// The name of the bucket with `id`, or the id if it isn't known
+fn bucket_name<'a>(cfg: &'a Config, id: &'a str) -> &'a str {
+    cfg.buckets
+        .iter()
+        .find(|(_, b)| b.as_str() == id)
+        .map(|(name, _)| name.as_str())
+        .unwrap_or(id)
+}
+
+/// Colour a capability by what it allows: reading is green, writing is yellow, deleting is red
+/// and anything else is blue
+fn capability(cap: &str) -> ColoredString {
+    if cap.starts_with("read") || cap.starts_with("list") {
+        cap.green()
+    } else if cap.starts_with("write") || cap == "shareFiles" {
+        cap.yellow()
+    } else if cap.starts_with("delete") || cap == "bypassGovernance" {
+        cap.red()
+    } else {
+        cap.blue()
+    }
+}
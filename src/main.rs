@@ -1,101 +1,482 @@
 use std::{
+    borrow::Cow,
+    ffi::{OsStr, OsString},
     fs,
-    hash::Hasher,
-    io::{IsTerminal, Seek, SeekFrom, Write},
-    ops::Deref,
-    os::unix::fs::FileExt,
-    path::{Path, PathBuf},
+    io::{IsTerminal, Write},
+    path::{Component, Path, PathBuf},
+    process::ExitCode,
+    time::Duration,
 };
 
-use anyhow::bail;
+use anyhow::{bail, Context};
 use clap::Parser;
 use colored::Colorize;
 use humanize_bytes::humanize_bytes_decimal;
-use progress_bar::{finalize_progress_bar, init_progress_bar_with_eta, set_progress_bar_progress};
-use reqwest::blocking as reqwest;
-use rs_sha1::{HasherContext, Sha1Hasher};
+use ignore::WalkBuilder;
 use serde::Deserialize;
-use walkdir::WalkDir;
 
 use api::File;
-use cli::Command;
+use cli::{
+    BucketCommand, Command, KeyCommand, NotificationsCommand, OutputFormat, SnapshotsCommand,
+    TagCommand,
+};
 use config::Config;
+use transfer::{AfterUpload, TransferJob};
 
 mod api;
+mod auth;
+mod bench;
+mod buckets;
+mod cancel;
+mod changes;
 mod cli;
+mod cmp;
 mod config;
+mod confirm;
+mod copy;
+mod expire;
 mod files;
+mod files_from;
+mod find;
+mod grep;
+mod help;
+mod http;
+mod journal;
+mod keys;
+mod metadata;
+mod mirror;
+mod notifications;
+mod notify;
+mod pager;
+mod path;
+mod peek;
+mod pick;
 mod progress;
-
-/// Does what it says on the can: wraps [`Sha1Hasher`] and gives it a [`Write`] implementation
-struct Sha1HasherWriterWrapper(Sha1Hasher);
-impl Write for Sha1HasherWriterWrapper {
-    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
-        Hasher::write(&mut self.0, buf);
-        Ok(buf.len())
+mod public;
+mod release;
+mod report;
+mod retention;
+mod retry;
+mod rm;
+mod snapshots;
+mod table;
+mod tags;
+mod text;
+mod transfer;
+mod wait;
+
+/// The exit code when `upload --if-none-match` left out a file because it already existed
+const EXIT_EXISTS: u8 = 3;
+
+fn main() -> anyhow::Result<ExitCode> {
+    let cli = {
+        let args: Vec<OsString> = std::env::args_os().collect();
+        match cli::Cli::try_parse_from(&args) {
+            Ok(cli) => cli,
+            Err(e) => pick::missing_bucket(e, args)?,
+        }
+    };
+    // `b2 retry` runs the recorded upload in its place, with the global options it was run with
+    let (cli, retrying) = match cli.command {
+        Command::Retry { file } => {
+            // The upload is run from where it was first run, which may be somewhere else
+            let file = fs::canonicalize(&file)
+                .with_context(|| format!("Couldn't read {}", file.display()))?;
+            (retry::RetryFile::load(&file)?.into_cli()?, Some(file))
+        }
+        _ => (cli, None),
+    };
+    let cli::Cli {
+        profile,
+        low_memory,
+        no_progress,
+        offline,
+        deadline,
+        user_agent_suffix,
+        command,
+    } = cli;
+    if no_progress {
+        progress::disable();
     }
-
-    fn flush(&mut self) -> std::io::Result<()> {
-        Ok(())
+    cancel::install(deadline)?;
+    let mut cfg = Config::load(profile.as_deref().map(config::profile_path).transpose()?)?;
+    cfg.low_memory = low_memory;
+    cfg.offline = offline;
+    if low_memory {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(1)
+            .build_global()?;
     }
-}
-impl Deref for Sha1HasherWriterWrapper {
-    type Target = Sha1Hasher;
+    let mut http_opts = cfg.http.clone();
+    if user_agent_suffix.is_some() {
+        http_opts.user_agent_suffix = user_agent_suffix;
+    }
+    if low_memory {
+        // Everything is streamed over a single connection, one request at a time, so only one
+        // request and response is ever buffered
+        http_opts.pool_max_idle_per_host = 1;
+        http::limiter().cap(1);
+    }
+    http::init(&http_opts)?;
+
+    // Save even if the command failed, so that e.g. a refreshed auth token isn't lost
+    let res = run(&mut cfg, command);
+    cfg.save()?;
 
-    fn deref(&self) -> &Self::Target {
-        &self.0
+    // Anything that failed again has been written to the retry file of the upload, which is
+    // usually this same one
+    if let (Some(file), Ok(ExitCode::SUCCESS)) = (retrying, &res) {
+        fs::remove_file(file)?;
     }
+    res
 }
 
-fn main() -> anyhow::Result<()> {
-    let cli::Cli { command } = cli::Cli::parse();
-    let mut cfg = Config::load(None)?;
+/// Run `command`, returning the exit code for commands that use it to report their result
+fn run(cfg: &mut Config, command: Command) -> anyhow::Result<ExitCode> {
     match command {
-        Command::Authorise => {
+        Command::Api {
+            query,
+            body,
+            endpoint,
+        } => {
+            let body: Option<serde_json::Value> = body
+                .map(|b| anyhow::Ok(serde_json::from_slice(&fs::read(b)?)?))
+                .transpose()?;
+
+            let res: serde_json::Value = cfg.send_request_de(|cfg| {
+                let req = match body {
+                    Some(ref body) => cfg.post(&endpoint)?.json(body),
+                    None => cfg.get(&endpoint)?,
+                };
+                Ok(req.query(&query).send()?)
+            })?;
+
+            println!("{}", serde_json::to_string_pretty(&res)?);
+        }
+        Command::Authorise { keyring, ephemeral } => {
+            cfg.keyring |= keyring;
+            cfg.ephemeral = ephemeral;
             cfg.auth_from_stdin()?;
         }
-        Command::ListBuckets => {
+        Command::Find {
+            name,
+            size,
+            mtime,
+            delete,
+            yes,
+            trash_bucket,
+            exec,
+            bucket,
+            prefix,
+        } => {
+            cfg.confirm_auth()?;
+            let path = cfg.resolve_alias(bucket.join(prefix.as_deref().unwrap_or_default()))?;
+            let filter = find::Filter {
+                name: name
+                    .map(|g| globset::Glob::new(&g).map(|g| g.compile_matcher()))
+                    .transpose()?,
+                size,
+                mtime,
+            };
+            let action = match exec {
+                Some(command) => find::Action::Exec(command),
+                None if delete => find::Action::Delete {
+                    yes,
+                    trash: trash_bucket.map(|trash| rm::trash_dir(trash, &path.bucket)),
+                },
+                None => find::Action::Print,
+            };
+
+            find::find(cfg, &path, &filter, &action)?;
+        }
+        Command::Grep {
+            pattern,
+            glob,
+            content_type,
+            bucket,
+            prefix,
+        } => {
+            cfg.confirm_auth()?;
+            let path = cfg.resolve_alias(bucket.join(prefix.as_deref().unwrap_or_default()))?;
+            let filter = grep::Filter {
+                glob: glob
+                    .map(|g| globset::Glob::new(&g).map(|g| g.compile_matcher()))
+                    .transpose()?,
+                content_type,
+            };
+
+            if grep::grep(cfg, &pattern, &path, &filter)? == 0 {
+                return Ok(ExitCode::FAILURE);
+            }
+        }
+        Command::Head {
+            lines,
+            bucket,
+            file,
+        } => {
+            cfg.confirm_auth()?;
+            let file = cfg.resolve_alias(bucket.join(file.as_deref().unwrap_or_default()))?;
+            if file.is_dir() {
+                bail!("No file specified");
+            }
+            peek::head(cfg, &file, lines)?;
+        }
+        Command::Tail {
+            lines,
+            bucket,
+            file,
+        } => {
+            cfg.confirm_auth()?;
+            let file = cfg.resolve_alias(bucket.join(file.as_deref().unwrap_or_default()))?;
+            if file.is_dir() {
+                bail!("No file specified");
+            }
+            peek::tail(cfg, &file, lines)?;
+        }
+        Command::Cmp {
+            local,
+            bucket,
+            file,
+        } => {
+            cfg.confirm_auth()?;
+            let file = cfg.resolve_alias(bucket.join(file.as_deref().unwrap_or_default()))?;
+            if file.is_dir() {
+                bail!("No file specified");
+            }
+            if !cmp::cmp(cfg, &local, &file)? {
+                return Ok(ExitCode::FAILURE);
+            }
+        }
+        Command::Help { topic } => help::print(topic.as_deref())?,
+        Command::ListBuckets {
+            long,
+            option,
+            output,
+        } => {
             // Always update the buckets when the user asks for us to list them
-            cfg.get_buckets()?;
+            let mut buckets = cfg.get_buckets()?;
+            buckets.sort_by(|a, b| a.bucket_name.cmp(&b.bucket_name));
+
+            if let Some(ref option) = option {
+                buckets.retain(|b| b.options.iter().any(|o| o.eq_ignore_ascii_case(option)));
+            }
 
-            for bucket in cfg.buckets.keys() {
-                println!("{}", bucket);
+            if output != OutputFormat::Table {
+                let mut table = table::Table::new(vec!["name", "id", "type", "options"]);
+                for bucket in buckets {
+                    table.row(vec![
+                        bucket.bucket_name,
+                        bucket.bucket_id,
+                        bucket.bucket_type.to_string(),
+                        bucket.options.join(","),
+                    ]);
+                }
+                table.print(output)?;
+            } else if long {
+                println!(
+                    "{:<11}   {}   {}",
+                    "Type".underline(),
+                    "Options".underline(),
+                    "Name".underline()
+                );
+                for bucket in buckets {
+                    println!(
+                        "{:<11}   {:<7}   {}",
+                        bucket.bucket_type.as_str().green(),
+                        bucket.options.join(",").blue(),
+                        bucket.bucket_name.yellow()
+                    );
+                }
+            } else {
+                for bucket in buckets {
+                    println!("{}", bucket.bucket_name);
+                }
+            }
+        }
+        Command::Changes {
+            since,
+            output,
+            time_style,
+            bucket,
+            prefix,
+        } => {
+            let path = cfg.resolve_alias(bucket.join(prefix.as_deref().unwrap_or_default()))?;
+            let files = changes::changes(cfg, &path, since)?;
+            changes::print(files, output, &time_style)?;
+        }
+        Command::VerifyMirror { output, src, dst } => {
+            let src = cfg.resolve_alias(src)?;
+            let dst = cfg.resolve_alias(dst)?;
+            let (diffs, unhashed) = mirror::verify(cfg, &src, &dst)?;
+            mirror::print(&diffs, unhashed, output)?;
+            if !diffs.is_empty() {
+                return Ok(ExitCode::FAILURE);
             }
         }
+        Command::Cp {
+            from_profile,
+            to_profile,
+            src,
+            dest,
+        } => {
+            let load = |name: Option<String>| match name {
+                Some(name) => Config::load(Some(config::profile_path(&name)?)),
+                None => Ok(cfg.clone()),
+            };
+            let same_account = from_profile == to_profile;
+            let mut from = load(from_profile)?;
+            let mut to = load(to_profile)?;
+
+            let src = from.resolve_alias(src)?;
+            let Some(name) = src.file_name() else {
+                bail!("No file specified");
+            };
+            let dest = to.resolve_alias(dest)?;
+            let dest = if dest.is_dir() { dest.join(name) } else { dest };
+
+            if same_account {
+                copy::copy(&mut from, &src, &dest)?;
+            } else {
+                // b2 can't copy between accounts, so it is downloaded and uploaded again
+                TransferJob::copy(src, dest).run_between(&mut from, &mut to)?;
+            }
+        }
+        Command::Report { output } => {
+            let reports = report::report(cfg)?;
+            report::print(&reports, output)?;
+        }
+        Command::Bench {
+            size,
+            count,
+            concurrency,
+            bucket,
+        } => {
+            let bucket = cfg.resolve_alias(bucket)?;
+            let opts = bench::Options {
+                size,
+                count,
+                concurrency,
+            };
+            bench::bench(cfg, &bucket, &opts)?;
+        }
+        Command::Key { command } => match command {
+            KeyCommand::Rotate => keys::rotate(cfg)?,
+        },
+        Command::ListKeys {
+            prefix,
+            bucket,
+            show_expired,
+        } => {
+            let filter = keys::Filter {
+                prefix,
+                bucket,
+                show_expired,
+            };
+            let keys = keys::list_filtered(cfg, &filter)?;
+            keys::print(cfg, &keys);
+        }
+        Command::Notifications { command } => match command {
+            NotificationsCommand::Ls { bucket } => {
+                let rules = notifications::get_rules(cfg, &bucket)?;
+                notifications::print_rules(&rules);
+            }
+            NotificationsCommand::Test { bucket, rule } => {
+                notifications::test_rule(cfg, &bucket, &rule)?;
+            }
+        },
         Command::Ls {
             bucket,
             long,
+            summary,
             all,
-            search: prefix,
+            print0,
+            tag,
+            output,
+            anonymous,
+            time_style,
+            search,
         } => {
-            let bucket_id = cfg
-                .get_bucket_id(&bucket)?
-                .unwrap_or_else(|| {
-                    eprintln!("Bucket `{}` does not exist", bucket);
-                    std::process::exit(1);
-                })
-                .to_string();
-
-            let mut query = Vec::with_capacity(2);
-            query.push(("bucketId", bucket_id));
+            let path = cfg.resolve_alias(bucket.join(search.as_deref().unwrap_or_default()))?;
 
-            if let Some(prefix) = prefix {
-                query.push(("prefix", prefix));
+            if anonymous {
+                if path.is_dir() {
+                    bail!(
+                        "b2 doesn't allow listing files without authorising, even in a public \
+                         bucket, so --anonymous needs the path of a file"
+                    );
+                }
+                let Some(file) = public::head(cfg, &path)? else {
+                    bail!("{} does not exist", path);
+                };
+                file.print(long);
+                return Ok(ExitCode::SUCCESS);
             }
 
-            let res: serde_json::Value = cfg.send_request_de(|cfg| {
-                Ok(cfg.get("b2_list_file_names")?.query(&query).send()?)
-            })?;
+            let bucket_id = cfg.require_bucket_id(&path.bucket)?;
 
-            let files: Vec<File> = Deserialize::deserialize(res["files"].clone())?;
+            let mut files: Vec<File> = if summary {
+                // The totals should be of the whole prefix, not just the first page
+                cfg.list_files(&path)?
+            } else {
+                let mut query = Vec::with_capacity(2);
+                query.push(("bucketId", bucket_id));
 
-            if all {
+                if !path.key.is_empty() {
+                    query.push(("prefix", path.key));
+                }
+
+                let query: Vec<_> = query.iter().map(|(k, v)| (*k, v.as_str())).collect();
+                let res = cfg.list("b2_list_file_names", &query)?;
+                Deserialize::deserialize(res["files"].clone())?
+            };
+            if let Some(ref tag) = tag {
+                files.retain(|f| tags::get(f).contains(&tag.as_str()));
+            }
+            let totals = (
+                files.len(),
+                files.iter().map(|f| f.content_length).sum::<u64>(),
+            );
+
+            if output != OutputFormat::Table {
+                let mut table =
+                    table::Table::new(vec!["name", "size", "modified", "content_type", "id"]);
+                for file in files {
+                    table.row(vec![
+                        file.file_name.clone(),
+                        file.content_length.to_string(),
+                        file.last_modified().to_rfc3339(),
+                        file.content_type,
+                        file.file_id,
+                    ]);
+                }
+                table.print(output)?;
+            } else if print0 {
+                let mut stdout = std::io::stdout().lock();
+                if all {
+                    for file in files {
+                        write!(stdout, "{}\0", file.file_name)?;
+                    }
+                } else if let files::FileTree::Root { children } = files::files_to_tree(files) {
+                    for (_, child) in children {
+                        match child {
+                            files::FileTree::Directory { name, .. } => {
+                                write!(stdout, "{}/\0", name)?
+                            }
+                            files::FileTree::File { file, .. } => {
+                                write!(stdout, "{}\0", file.file_name)?
+                            }
+                            files::FileTree::Root { .. } => unreachable!(),
+                        }
+                    }
+                }
+                stdout.flush()?;
+            } else if all {
                 if long {
                     // TODO: make this less shit
                     println!(
                         "  {}   {}   {}",
                         "Size".underline(),
-                        "Date Uploaded".underline(),
+                        "Date Modified".underline(),
                         "Name".underline()
                     );
                     for file in files {
@@ -106,7 +487,7 @@ fn main() -> anyhow::Result<()> {
                                 .unwrap()
                                 .replace(' ', "")
                                 .green(),
-                            file.upload_timestamp.format("%e %h %Y").to_string().blue(),
+                            time_style.format(file.last_modified()).blue(),
                         );
                         if file.file_name.contains('/') {
                             let parts: Vec<_> = file.file_name.split('/').collect();
@@ -129,7 +510,7 @@ fn main() -> anyhow::Result<()> {
                     println!(
                         "  {}   {}   {}",
                         "Size".underline(),
-                        "Date Uploaded".underline(),
+                        "Date Modified".underline(),
                         "Name".underline()
                     );
                 }
@@ -150,7 +531,7 @@ fn main() -> anyhow::Result<()> {
                                             .unwrap()
                                             .replace(' ', "")
                                             .green(),
-                                        file.upload_timestamp.format("%e %h %Y").to_string().blue(),
+                                        time_style.format(file.last_modified()).blue(),
                                         file.file_name.yellow(),
                                     );
                                 }
@@ -160,177 +541,687 @@ fn main() -> anyhow::Result<()> {
                     }
                 }
             }
+
+            if summary {
+                files::print_summary(totals.0, totals.1);
+            }
+        }
+        Command::Snapshots { command } => match command {
+            SnapshotsCommand::Create {
+                dir,
+                bucket,
+                prefix,
+            } => {
+                let repo = cfg.resolve_alias(bucket.join(prefix.as_deref().unwrap_or_default()))?;
+                snapshots::create(cfg, &dir, &repo)?;
+            }
+            SnapshotsCommand::Prune {
+                keep_last,
+                keep_hourly,
+                keep_daily,
+                keep_weekly,
+                keep_monthly,
+                keep_yearly,
+                dry_run,
+                yes,
+                bucket,
+                prefix,
+            } => {
+                let repo = cfg.resolve_alias(bucket.join(prefix.as_deref().unwrap_or_default()))?;
+                let policy = snapshots::Policy {
+                    last: keep_last,
+                    hourly: keep_hourly,
+                    daily: keep_daily,
+                    weekly: keep_weekly,
+                    monthly: keep_monthly,
+                    yearly: keep_yearly,
+                };
+                snapshots::prune(cfg, &repo, &policy, dry_run, yes)?;
+            }
+            SnapshotsCommand::Ls { bucket, prefix } => {
+                let repo = cfg.resolve_alias(bucket.join(prefix.as_deref().unwrap_or_default()))?;
+                for snapshot in snapshots::list(cfg, &repo)? {
+                    let manifest = snapshots::read(cfg, &repo, &snapshot)?;
+                    let size: u64 = manifest.files.values().map(|e| e.size).sum();
+                    println!(
+                        "{}   {}   {} files   {}   {}",
+                        snapshot
+                            .file_name
+                            .rsplit('/')
+                            .next()
+                            .unwrap_or_default()
+                            .yellow(),
+                        manifest.created.format("%e %h %Y %H:%M"),
+                        manifest.files.len(),
+                        humanize_bytes_decimal!(size),
+                        manifest.source
+                    );
+                }
+            }
+        },
+        Command::Gc {
+            yes,
+            bucket,
+            prefix,
+        } => {
+            let repo = cfg.resolve_alias(bucket.join(prefix.as_deref().unwrap_or_default()))?;
+            snapshots::gc(cfg, &repo, yes)?;
+        }
+        Command::Expire {
+            dry_run,
+            yes,
+            bucket,
+            prefix,
+        } => {
+            cfg.confirm_auth()?;
+            let path = cfg.resolve_alias(bucket.join(prefix.as_deref().unwrap_or_default()))?;
+            expire::expire(cfg, &path, dry_run, yes)?;
+        }
+        Command::Tag { command } => {
+            let (bucket, file) = match command {
+                TagCommand::Add {
+                    ref bucket,
+                    ref file,
+                    ..
+                }
+                | TagCommand::Remove {
+                    ref bucket,
+                    ref file,
+                    ..
+                }
+                | TagCommand::List {
+                    ref bucket,
+                    ref file,
+                } => (bucket, file),
+            };
+            let path = cfg.resolve_alias(bucket.join(file.as_deref().unwrap_or_default()))?;
+            if path.is_dir() {
+                bail!("No file specified");
+            }
+            let Some(file) = cfg.get_file_fresh(&path)? else {
+                bail!("{} does not exist", path);
+            };
+
+            let mut current = tags::get(&file);
+            match command {
+                TagCommand::Add { ref tags, .. } => {
+                    for tag in tags {
+                        if !current.contains(&tag.as_str()) {
+                            current.push(tag);
+                        }
+                    }
+                    tags::set(cfg, &file, &current)?;
+                }
+                TagCommand::Remove { ref tags, .. } => {
+                    current.retain(|t| !tags.iter().any(|r| r == t));
+                    tags::set(cfg, &file, &current)?;
+                }
+                TagCommand::List { .. } => tags::print(&file),
+            }
         }
         Command::Tree {
             bucket,
             long,
-            search: prefix,
+            summary,
+            depth,
+            time_style,
+            search,
         } => {
-            let bucket_id = cfg
-                .get_bucket_id(&bucket)?
-                .unwrap_or_else(|| {
-                    eprintln!("Bucket `{}` does not exist", bucket);
-                    std::process::exit(1);
-                })
-                .to_string();
-
-            let mut query = Vec::with_capacity(2);
-            query.push(("bucketId", bucket_id));
-
-            if let Some(prefix) = prefix {
-                query.push(("prefix", prefix));
-            }
-
-            let res: serde_json::Value = cfg.send_request_de(|cfg| {
-                Ok(cfg.get("b2_list_file_names")?.query(&query).send()?)
-            })?;
-
-            let files: Vec<File> = Deserialize::deserialize(res["files"].clone())?;
+            let path = cfg.resolve_alias(bucket.join(search.as_deref().unwrap_or_default()))?;
+            // Every page is needed for the totals of each directory to be right
+            let files = cfg.list_files(&path)?;
 
             let tree = files::files_to_tree(files);
-            files::print_tree(tree, long);
+            files::print_tree(tree, long, &time_style, summary, depth.map(|d| d as usize));
         }
         Command::Upload {
             parts,
+            delta,
+            verify_after_upload,
             file,
             bucket,
             dest,
             content_type,
+            checksum,
+            compress,
             recursive,
+            empty_dirs,
+            skip_invalid_names,
+            hidden,
+            no_hidden: _,
+            no_ignore,
+            resume,
+            restart,
+            strip_components,
+            rewrite,
+            delete_after_upload,
+            attach_metadata_from_json,
+            if_none_match,
+            delete_after,
+            move_to,
+            files_from,
+            retries,
+            retry_file,
+            retry_files,
+            notify,
         } => {
+            let mut existed = false;
+            let expires = delete_after.map(|age| chrono::Utc::now() + age);
+            let metadata = match attach_metadata_from_json {
+                Some(ref path) => metadata::Metadata::load(path)?,
+                None => metadata::Metadata::default(),
+            };
             cfg.confirm_auth()?;
-
-            if file.is_dir() {
-                if !recursive {
+            let dest = cfg.resolve_alias(bucket.join(dest.as_deref().unwrap_or_default()))?;
+            let notifier = notify::Notifier::start(format!("Upload of {}", file.display()), notify);
+            // `relative` is the path of a file relative to what was given to upload
+            let after = |relative: &Path| match move_to {
+                Some(ref dir) => AfterUpload::Move(dir.join(relative)),
+                None if delete_after_upload => AfterUpload::Delete,
+                None => AfterUpload::Keep,
+            };
+
+            // The files to upload from a directory, if they were given rather than walked
+            let listed = match (retry_files, files_from) {
+                (Some(files), _) => Some(files),
+                (None, Some(list)) => Some(files_from::read(&list)?),
+                (None, None) => None,
+            };
+
+            if listed.is_some() || file.is_dir() {
+                if !recursive && listed.is_none() {
                     bail!("-r not specified, omitting directory {}", file.display());
                 }
 
-                for entry in WalkDir::new(file)
-                    .into_iter()
-                    .filter_map(|e| e.ok())
-                    .filter(|d| !d.path().is_dir())
-                {
-                    let pb = if let Some(ref dest) = dest {
-                        dest.components().chain(entry.path().components()).collect()
-                    } else {
-                        entry.path().to_path_buf()
+                let paths: Vec<PathBuf> = match listed {
+                    Some(_) if !file.is_dir() => {
+                        bail!("{} must be a directory with --files-from", file.display())
+                    }
+                    Some(listed) => {
+                        let mut paths = Vec::new();
+                        for listed in listed {
+                            let path = file.join(&listed);
+                            let escapes = listed.components().any(|c| c == Component::ParentDir);
+                            if escapes || !path.starts_with(&file) {
+                                bail!("{} is not under {}", listed.display(), file.display());
+                            }
+                            paths.push(path);
+                        }
+                        paths
+                    }
+                    None => {
+                        let mut walk = WalkBuilder::new(&file);
+                        walk.standard_filters(!no_ignore)
+                            .hidden(!hidden)
+                            // Respect .gitignore even if the directory isn't in a repository
+                            .require_git(false);
+                        if !no_ignore {
+                            walk.add_custom_ignore_filename(".b2ignore");
+                        }
+                        walk.build()
+                            .filter_map(|e| e.ok())
+                            .map(|e| e.into_path())
+                            .collect()
+                    }
+                };
+
+                let remap = path::Remap {
+                    strip_components,
+                    rewrites: rewrite,
+                };
+
+                let mut journal = journal::Journal::open(&file, &dest, resume, restart)?;
+                let mut dir_metadata = metadata::DirMetadata::new(&file, metadata);
+
+                let mut jobs = Vec::new();
+                let mut entries = Vec::new();
+                for path in paths {
+                    if path.to_str().is_none() {
+                        if skip_invalid_names {
+                            warn_invalid_name(&path, None);
+                            continue;
+                        }
+                        warn_invalid_name(&path, Some(&path.to_string_lossy()));
+                    }
+                    let Some(key) = remap.apply(&path)? else {
+                        continue;
                     };
-                    println!("{}", pb.display());
-                    upload_file(
-                        &mut cfg,
-                        parts,
-                        entry.path(),
-                        &bucket,
-                        Some(pb),
-                        content_type.as_deref(),
-                    )?;
+                    let dest = dest.join(&key);
+
+                    let file_metadata = fs::metadata(&path)
+                        .with_context(|| format!("Couldn't read {}", path.display()))?;
+                    if file_metadata.is_dir() {
+                        if empty_dirs && fs::read_dir(&path)?.next().is_none() {
+                            transfer::create_placeholder(cfg, &dest)?;
+                        }
+                        continue;
+                    }
+
+                    if path.file_name() == Some(OsStr::new(metadata::DIR_FILE)) {
+                        continue;
+                    }
+
+                    let relative = path.strip_prefix(&file)?.to_path_buf();
+                    let journal_entry = journal::entry(&relative, &file_metadata);
+                    if journal.contains(&journal_entry) {
+                        continue;
+                    }
+
+                    let metadata = dir_metadata.get(&path)?;
+                    let mut job = TransferJob::upload(path.clone(), dest);
+                    job.parts = parts;
+                    job.delta = delta;
+                    job.verify_after = verify_after_upload;
+                    job.content_type = content_type.clone().or(metadata.content_type.clone());
+                    job.info = metadata.file_info();
+                    job.checksums = checksum.clone();
+                    job.compress = compress;
+                    job.expires = expires;
+                    job.after = after(&relative);
+                    jobs.push(job);
+                    entries.push((journal_entry, relative));
+                }
+
+                println!("Hashing {} files", jobs.len());
+                let hashed = transfer::hash_ahead(&mut jobs);
+                cancel::check()?;
+
+                // Files that couldn't be hashed have failed their first attempt, and are retried
+                // with the rest
+                let mut queue = Vec::new();
+                let mut unhashed = Vec::new();
+                for ((job, (entry, relative)), hashed) in jobs.into_iter().zip(entries).zip(hashed)
+                {
+                    match hashed {
+                        Ok(()) => queue.push((job, (entry, relative))),
+                        Err(e) => {
+                            eprintln!(
+                                "{}",
+                                format!("Failed to hash {}: {:#}", relative.display(), e).red()
+                            );
+                            unhashed.push((job, (entry, relative)));
+                        }
+                    }
+                }
+                for attempt in 0..=retries {
+                    if attempt > 0 && !queue.is_empty() {
+                        println!(
+                            "{}",
+                            format!("Retrying {} failed files", queue.len()).yellow()
+                        );
+                    }
+
+                    let mut failed = std::mem::take(&mut unhashed);
+                    for (job, (entry, relative)) in queue {
+                        // Everything finished so far is in the journal, so this can be resumed
+                        cancel::check()?;
+                        match upload_unless_exists(cfg, &job, if_none_match) {
+                            Ok(true) => journal.record(&entry)?,
+                            Ok(false) => existed = true,
+                            Err(e) if !cancel::is_cancelled() => {
+                                eprintln!(
+                                    "{}",
+                                    format!("Failed to upload {}: {:#}", relative.display(), e)
+                                        .red()
+                                );
+                                failed.push((job, (entry, relative)));
+                            }
+                            Err(e) => return Err(e),
+                        }
+                    }
+                    queue = failed;
+                }
+
+                // What is left to do is in the retry file, which `b2 retry` runs without the
+                // journal getting in the way
+                journal.finish()?;
+                if !queue.is_empty() {
+                    let files = queue.into_iter().map(|(_, (_, rel))| rel).collect();
+                    retry::RetryFile::write(&retry_file, files)?;
+                    bail!(
+                        "Some files still failed after {} retries, run `b2 retry {}` to try them \
+                         again",
+                        retries,
+                        retry_file.display()
+                    );
                 }
             } else {
-                upload_file(
-                    &mut cfg,
-                    parts,
-                    &file,
-                    &bucket,
-                    dest,
-                    content_type.as_deref(),
-                )?;
+                let dest = if dest.is_dir() {
+                    let Some(name) = file.file_name() else {
+                        bail!("{} is not a file", file.display());
+                    };
+                    let name = name.to_string_lossy();
+                    if let Cow::Owned(ref name) = name {
+                        warn_invalid_name(&file, Some(name));
+                    }
+                    dest.join(&name)
+                } else {
+                    dest
+                };
+
+                if if_none_match && already_exists(cfg, &dest)? {
+                    existed = true;
+                } else {
+                    let mut job = TransferJob::upload(file.clone(), dest);
+                    job.parts = parts;
+                    job.delta = delta;
+                    job.verify_after = verify_after_upload;
+                    job.content_type = content_type.or(metadata.content_type.clone());
+                    job.info = metadata.file_info();
+                    job.checksums = checksum;
+                    job.compress = compress;
+                    job.expires = expires;
+                    if let Some(name) = file.file_name() {
+                        job.after = after(Path::new(name));
+                    }
+                    job.run(cfg)?;
+                }
+            }
+
+            if let Some(notifier) = notifier {
+                notifier.done();
+            }
+            if existed {
+                return Ok(ExitCode::from(EXIT_EXISTS));
             }
         }
-        Command::Share { bucket, file } => {
-            let file = file.display().to_string();
+        Command::Retry { .. } => unreachable!("`b2 retry` is replaced by its upload in main"),
+        Command::Release {
+            sign,
+            file,
+            bucket,
+            prefix,
+        } => {
+            cfg.confirm_auth()?;
+            let dest = cfg.resolve_alias(bucket.join(prefix.as_deref().unwrap_or_default()))?;
+            release::release(cfg, &file, &dest, sign.as_deref())?;
+        }
+        Command::Fetch {
+            content_type,
+            url,
+            bucket,
+            dest,
+        } => {
+            let dest = cfg.resolve_alias(bucket.join(dest.as_deref().unwrap_or_default()))?;
+            let dest = if dest.is_dir() {
+                let name = ::reqwest::Url::parse(&url)?
+                    .path_segments()
+                    .and_then(|mut s| s.next_back())
+                    .filter(|s| !s.is_empty())
+                    .map(|s| urlencoding::decode(s).map(|s| s.into_owned()))
+                    .transpose()?;
+                let Some(name) = name else {
+                    bail!("Unable to get a file name from `{}`, specify `dest`", url);
+                };
+                dest.join(&name)
+            } else {
+                dest
+            };
 
-            if cfg.get_bucket_id(&bucket)?.is_none() {
-                eprintln!(
-                    "{}",
-                    format!("A bucket by the name {} does not exist.", bucket).red()
-                );
-                std::process::exit(1);
+            let mut job = TransferJob::fetch(url, dest);
+            job.content_type = content_type;
+            job.run(cfg)?;
+        }
+        Command::Share { bucket, file, qr } => {
+            let file = cfg.resolve_alias(bucket.join(file.as_deref().unwrap_or_default()))?;
+            if file.is_dir() {
+                bail!("No file specified");
             }
 
-            let url = format!("{}/file/{}/{}", &cfg.download_url, bucket, file);
+            cfg.require_bucket_id(&file.bucket)?;
+
+            let url = file.url(&cfg.download_url);
             println!("{}", url.green());
+            if qr {
+                print_qr(&url)?;
+            }
         }
         Command::Download {
             output,
+            verify,
+            anonymous,
+            no_decompress,
+            notify,
+            files_from,
+            pick,
             bucket,
             file,
         } => {
-            cfg.confirm_auth()?;
-            let url = format!("{}/file/{}/{}", &cfg.download_url, bucket, file.display());
-            let mut res = cfg.send_request_res(|cfg| {
-                Ok(reqwest::Client::new()
-                    .get(&url)
-                    .header("Authorization", &cfg.auth_token)
-                    .send()?)
-            })?;
-
-            let output = output
-                .unwrap_or_else(|| {
-                    file.file_name()
-                        .unwrap()
-                        .to_str()
-                        .expect("Invalid file name")
-                        .into()
-                })
-                .display()
-                .to_string();
-
-            let mut file = progress::WriterProgress::new(
-                fs::File::create(&output)?,
-                res.content_length().unwrap() as usize,
-            );
+            // Each listed or picked file is downloaded to the same path under the output
+            // directory as it has under `bucket`
+            if files_from.is_some() || pick {
+                let mut prefix = cfg.resolve_alias(bucket)?;
+                let listed: Vec<PathBuf> = match files_from {
+                    Some(list) => files_from::read(&list)?,
+                    None => {
+                        if !prefix.is_dir() {
+                            prefix.key.push('/');
+                        }
+                        pick::files(cfg, &prefix, true)?
+                            .into_iter()
+                            .map(PathBuf::from)
+                            .collect()
+                    }
+                };
+                let output = output.unwrap_or_default();
+                let notifier =
+                    notify::Notifier::start(format!("Download of {} files", listed.len()), notify);
+                for listed in listed {
+                    if listed.to_str().is_none() {
+                        bail!("{} is not a valid UTF-8 name", listed.display());
+                    }
+                    let file = prefix.join_path(&listed)?;
+                    if file.is_dir() {
+                        bail!("{} is not a file", file);
+                    }
+                    let relative: PathBuf = listed
+                        .components()
+                        .filter(|c| matches!(c, Component::Normal(_)))
+                        .collect();
+                    let local = output.join(relative);
+                    if let Some(parent) = local.parent() {
+                        fs::create_dir_all(parent)?;
+                    }
 
-            let n = std::io::copy(&mut res, &mut file)?;
+                    cancel::check()?;
+                    println!("{}", file);
+                    let mut job = TransferJob::download(file, local);
+                    job.checksums = verify.clone();
+                    job.anonymous = anonymous;
+                    job.decompress = !no_decompress;
+                    job.run(cfg)?;
+                }
+                if let Some(notifier) = notifier {
+                    notifier.done();
+                }
+                return Ok(ExitCode::SUCCESS);
+            }
 
-            finalize_progress_bar();
-            println!(
-                "{}",
-                format!("Downloaded {} to {}!", humanize_bytes_decimal!(n), output).green()
-            );
+            let file = cfg.resolve_alias(bucket.join(file.as_deref().unwrap_or_default()))?;
+            let Some(name) = file.file_name() else {
+                bail!("No file specified");
+            };
+
+            let output = output.unwrap_or_else(|| name.into());
+
+            let notifier = notify::Notifier::start(format!("Download of {}", file), notify);
+            let mut job = TransferJob::download(file, output);
+            job.checksums = verify;
+            job.anonymous = anonymous;
+            job.decompress = !no_decompress;
+            job.run(cfg)?;
+            if let Some(notifier) = notifier {
+                notifier.done();
+            }
         }
         Command::Cat {
             force,
+            plain,
+            encoding,
+            bytes,
+            hex,
+            no_decompress,
+            pick,
             bucket,
             file,
         } => {
             cfg.confirm_auth()?;
-            let url = format!("{}/file/{}/{}", &cfg.download_url, bucket, file.display());
-            let mut res = reqwest::Client::new()
-                .get(url)
+            let file = if pick {
+                let mut dir = cfg.resolve_alias(bucket)?;
+                if !dir.is_dir() {
+                    dir.key.push('/');
+                }
+                let Some(picked) = pick::files(cfg, &dir, false)?.pop() else {
+                    return Ok(ExitCode::SUCCESS);
+                };
+                dir.join(&picked)
+            } else {
+                cfg.resolve_alias(bucket.join(file.as_deref().unwrap_or_default()))?
+            };
+            if file.is_dir() {
+                bail!("No file specified");
+            }
+
+            let mut res = http::client()
+                .get(file.url(&cfg.download_url))
                 .header("Authorization", &cfg.auth_token)
                 .send()?;
 
-            let mut s: Vec<u8> = Vec::with_capacity(res.content_length().unwrap_or(0) as usize);
-            res.copy_to(&mut s)?;
-
-            match String::from_utf8(s) {
-                Ok(s) => {
-                    println!("{}", s);
+            let codec = if no_decompress {
+                None
+            } else {
+                transfer::Codec::of_response(res.headers())
+            };
+            let mut decoder = transfer::Decoder::new(
+                Vec::with_capacity(res.content_length().unwrap_or(0) as usize),
+                codec,
+            );
+            res.copy_to(&mut decoder)?;
+            let s = decoder.finish()?;
+
+            let mut stdout = std::io::stdout();
+            if bytes {
+                stdout.write_all(&s)?;
+            } else if hex {
+                let dump = text::hex_dump(&s);
+                if plain || !stdout.is_terminal() {
+                    stdout.write_all(dump.as_bytes())?;
+                } else {
+                    pager::page(&dump)?;
                 }
-                Err(e) => {
-                    let mut stdout = std::io::stdout();
-                    let mut f = force || !stdout.is_terminal();
-                    if !f {
-                        eprint!("This file is not in a plaintext format. Are you sure you want to print? (y/N) ");
-                        std::io::stderr().flush()?;
-                        let mut s = String::with_capacity(1);
-                        std::io::stdin().read_line(&mut s)?;
-                        let s = s.trim().to_lowercase();
-                        if s == "y" {
-                            f = true;
+            } else {
+                match text::decode(&s, encoding) {
+                    Some(s) if plain || !stdout.is_terminal() => {
+                        println!("{}", s);
+                    }
+                    Some(s) => pager::page(&pager::highlight(&file.key, &s))?,
+                    None => {
+                        if confirm::confirm(
+                            "This file is not in a plaintext format. Are you sure you want to print?",
+                            force || !stdout.is_terminal(),
+                        )? {
+                            stdout.write_all(&s)?;
+                        } else {
+                            eprintln!("Exiting.");
                         }
                     }
+                }
+            }
+        }
+        Command::Wait {
+            new,
+            interval,
+            timeout,
+            bucket,
+            file,
+        } => {
+            let path = cfg.resolve_alias(bucket.join(file.as_deref().unwrap_or_default()))?;
+            if path.is_dir() {
+                bail!("No file specified");
+            }
 
-                    if f {
-                        stdout.write_all(e.as_bytes())?;
-                    } else {
-                        eprintln!("Exiting.");
+            wait::wait(
+                cfg,
+                &path,
+                new,
+                Duration::from_secs(interval),
+                Duration::from_secs(timeout),
+            )?;
+        }
+        Command::Rm {
+            all_versions,
+            yes,
+            file_id,
+            files_from,
+            trash_bucket,
+            bucket,
+            file,
+        } => match (file_id, bucket) {
+            (Some(id), _) => {
+                let file = cfg.get_file_by_id(&id)?;
+                rm::delete(cfg, &file)?;
+            }
+            (None, Some(bucket)) => {
+                let path = cfg.resolve_alias(bucket.join(file.as_deref().unwrap_or_default()))?;
+                let trash = trash_bucket.map(|trash| rm::trash_dir(trash, &path.bucket));
+                if let Some(list) = files_from {
+                    let listed = files_from::read(&list)?;
+                    rm::delete_listed(cfg, &path, listed, all_versions, yes, trash.as_ref())?;
+                } else if path.is_dir() {
+                    bail!("No file specified");
+                } else if all_versions {
+                    rm::delete_all_versions(cfg, &path, yes)?;
+                } else {
+                    let Some(file) = cfg.get_file_fresh(&path)? else {
+                        bail!("{} does not exist", path);
+                    };
+                    if let Some(ref trash) = trash {
+                        rm::copy_to_trash(cfg, &file, &path, trash)?;
                     }
+                    rm::delete(cfg, &file)?;
                 }
             }
+            (None, None) => unreachable!("clap requires a bucket without --file-id"),
+        },
+        Command::UpdateFileRetention {
+            mode,
+            retain_until,
+            bypass_governance,
+            file_id,
+            bucket,
+            file,
+        } => {
+            let file = match (file_id, bucket) {
+                (Some(id), _) => cfg.get_file_by_id(&id)?,
+                (None, Some(bucket)) => {
+                    let path =
+                        cfg.resolve_alias(bucket.join(file.as_deref().unwrap_or_default()))?;
+                    if path.is_dir() {
+                        bail!("No file specified");
+                    }
+                    let Some(file) = cfg.get_file_fresh(&path)? else {
+                        bail!("{} does not exist", path);
+                    };
+                    file
+                }
+                (None, None) => unreachable!("clap requires a bucket without --file-id"),
+            };
+
+            retention::update(cfg, &file, mode, retain_until, bypass_governance)?;
         }
+        Command::Bucket { command } => match command {
+            BucketCommand::Export { bucket, file } => {
+                buckets::export(cfg, &bucket, file.as_deref())?
+            }
+            BucketCommand::Import { bucket, file } => buckets::import(cfg, &bucket, &file)?,
+        },
         Command::CreateBucket { name, visibility } => {
-            let res: serde_json::Value = cfg.send_request_de(|cfg| {
+            buckets::validate_name(&name)?;
+            if cfg.get_bucket_id(&name)?.is_some() {
+                bail!("You already have a bucket called `{}`", name);
+            }
+
+            let res: anyhow::Result<serde_json::Value> = cfg.send_request_de(|cfg| {
                 Ok(cfg
                     .post("b2_create_bucket")?
                     .json(&serde_json::json!({
@@ -343,214 +1234,69 @@ fn main() -> anyhow::Result<()> {
                         },
                     }))
                     .send()?)
-            })?;
+            });
+            res.map_err(|e| buckets::explain_create_error(e, &name))?;
 
             cfg.get_buckets()?;
         }
     };
-    cfg.save()?;
-    Ok(())
+    Ok(ExitCode::SUCCESS)
 }
 
-fn upload_file(
+/// Whether a file already exists at `dest`, saying so if it does
+/// Run the upload `job`, unless `if_none_match` is set and its destination already exists.
+/// Returns whether it was uploaded.
+fn upload_unless_exists(
     cfg: &mut Config,
-    parts: bool,
-    file: &Path,
-    bucket: &str,
-    dest: Option<PathBuf>,
-    content_type: Option<&str>,
-) -> anyhow::Result<()> {
-    if !file.is_file() {
+    job: &TransferJob,
+    if_none_match: bool,
+) -> anyhow::Result<bool> {
+    if let transfer::Location::Remote(ref dest) = job.destination {
+        if if_none_match && already_exists(cfg, dest)? {
+            return Ok(false);
+        }
+        println!("{}", dest);
+    }
+    job.clone().run(cfg)?;
+    Ok(true)
+}
+
+fn already_exists(cfg: &mut Config, dest: &path::RemotePath) -> anyhow::Result<bool> {
+    let exists = cfg.get_file_fresh(dest)?.is_some();
+    if exists {
         eprintln!(
-            "{} {}",
-            file.display().to_string().red(),
-            "is not a file.".red()
+            "{}",
+            format!("{} already exists, not uploading it", dest).yellow()
         );
     }
-
-    let dest = dest.map(|p| p.display().to_string()).unwrap_or_else(|| {
-        let a: PathBuf = file
-            .file_name()
-            .unwrap()
-            .to_str()
-            .expect("Invalid file name")
-            .into();
-        a.display().to_string()
-    });
-
-    let Some(bucket_id) = cfg.get_bucket_id(bucket)? else {
-        eprintln!("{}", format!("Bucket `{}` does not exist", bucket).red());
-        std::process::exit(1);
-    };
-
-    let bucket_id = bucket_id.to_string();
-
-    let len = fs::metadata(file)?.len();
-
-    let file = if parts || len >= 1024 * 1024 * 1024 {
-        // >= 1 GiB
-        println!("Uploading as parts");
-        upload_file_parts(cfg, &bucket_id, file, len, &dest, content_type)?
-    } else {
-        upload_file_non_parts(cfg, &bucket_id, file, len, &dest, content_type)?
-    };
-
-    println!(
-        "{}",
-        format!(
-            "Uploaded {} to {}!",
-            humanize_bytes_decimal!(len),
-            file.file_name
-        )
-        .green()
-    );
-
-    Ok(())
+    Ok(exists)
 }
 
-fn upload_file_non_parts(
-    cfg: &mut Config,
-    bucket_id: &str,
-    file: &Path,
-    len: u64,
-    dest: &str,
-    content_type: Option<&str>,
-) -> anyhow::Result<File> {
-    let res: serde_json::Value = cfg.send_request_de(|cfg| {
-        Ok(cfg
-            .get("b2_get_upload_url")?
-            .query(&[("bucketId", bucket_id)])
-            .send()?)
-    })?;
-
-    let upload_url = res["uploadUrl"].as_str().unwrap();
-    let auth = res["authorizationToken"].as_str().unwrap();
-
-    let mut sha = Sha1HasherWriterWrapper(Sha1Hasher::default());
-
-    let mut file = fs::File::open(file)?;
-
-    std::io::copy(&mut file, &mut sha)?;
-
-    file.seek(SeekFrom::Start(0))?;
-
-    let hash = HasherContext::finish(&mut sha.0);
-
-    let file = progress::ReaderProgress::new(file, len as usize, "Uploading");
-
-    // TODO: make this work with `cfg.send_request`
-    let out: File = reqwest::Client::new()
-        .post(upload_url)
-        .header("Authorization", auth)
-        .header("X-Bz-File-Name", urlencoding::encode(dest).to_string())
-        .header(
-            "Content-Type",
-            content_type.unwrap_or_else(|| {
-                mime_guess::from_path(dest)
-                    .first_raw()
-                    .unwrap_or("text/plain")
-            }),
-        )
-        .header("Content-Length", len)
-        .header("X-Bz-Content-Sha1", format!("{:02x}", hash))
-        .body(reqwest::Body::new(file))
-        .send()?
-        .json()?;
-
-    finalize_progress_bar();
-
-    Ok(out)
+/// Warn that `path` isn't valid UTF-8, and what it will be uploaded as if it isn't being skipped
+fn warn_invalid_name(path: &Path, lossy: Option<&str>) {
+    let msg = match lossy {
+        Some(lossy) => format!(
+            "`{}` is not valid UTF-8, uploading it as `{}`",
+            path.display(),
+            lossy
+        ),
+        None => format!("`{}` is not valid UTF-8, skipping it", path.display()),
+    };
+    eprintln!("{}", msg.yellow());
 }
 
-fn upload_file_parts(
-    cfg: &mut Config,
-    bucket_id: &str,
-    file: &Path,
-    len: u64,
-    dest: &str,
-    content_type: Option<&str>,
-) -> anyhow::Result<File> {
-    let res: serde_json::Value = cfg.send_request_de(|cfg| {
-        Ok(cfg
-            .post("b2_start_large_file")?
-            .json(&serde_json::json!({
-                "bucketId": bucket_id,
-                "fileName": dest,
-                "contentType": content_type.unwrap_or_else(|| {
-                    mime_guess::from_path(dest)
-                        .first_raw()
-                        .unwrap_or("text/plain")
-                }),
-            }))
-            .send()?)
-    })?;
-
-    let file_id = res["fileId"].as_str().unwrap();
-
-    // TODO: Parallelise this stuff
-
-    let res: serde_json::Value = cfg.send_request_de(|cfg| {
-        Ok(cfg
-            .get("b2_get_upload_part_url")?
-            .query(&[("fileId", file_id)])
-            .send()?)
-    })?;
-
-    let file = fs::File::open(file)?;
-
-    let mut chunk_size = cfg.recommended_part_size;
-
-    let chunks = len / chunk_size;
-    if chunks == 0 || chunks == 1 && chunks % chunk_size == 0 {
-        // split it into two chunks or chunks of 5MB if that's bigger (because 5MB is the minimum)
-        chunk_size = std::cmp::max(len / 2 + 100, 5_000_000);
-    }
-    let chunks = len / chunk_size;
-
-    if chunks == 0 {
-        bail!("Not enough data to upload by parts");
-    }
-
-    let upload_url = res["uploadUrl"].as_str().unwrap();
-    let auth = res["authorizationToken"].as_str().unwrap();
-
-    init_progress_bar_with_eta(len as usize);
-    let mut buf = vec![0u8; chunk_size as usize];
-    let mut shas = Vec::with_capacity(chunks as usize);
-    let mut total = 0;
-    for n in 0..=chunks {
-        let num_bytes = file.read_at(&mut buf, chunk_size * n)?;
-
-        let mut shash = Sha1Hasher::default();
-        shash.write(&buf);
-        let hash = HasherContext::finish(&mut shash);
-
-        shas.push(format!("{:02x}", hash));
-
-        let _: serde_json::Value = cfg.send_request_de(|_| {
-            Ok(reqwest::Client::new()
-                .post(upload_url)
-                .header("Authorization", auth)
-                .header("X-Bz-Part-Number", n + 1)
-                .header("Content-Length", num_bytes)
-                .header("X-Bz-Content-Sha1", shas.last().unwrap())
-                .body(buf.clone()) // TODO: find out how to remove this clone
-                .send()?)
-        })?;
-
-        total += num_bytes;
-        set_progress_bar_progress(total);
-    }
-
-    finalize_progress_bar();
-
-    cfg.send_request_de(|cfg| {
-        Ok(cfg
-            .post("b2_finish_large_file")?
-            .json(&serde_json::json!({
-                "fileId": file_id,
-                "partSha1Array": shas,
-            }))
-            .send()?)
-    })
+/// Print `text` as a QR code using unicode half blocks, which fit two modules into each character
+fn print_qr(text: &str) -> anyhow::Result<()> {
+    use qrcode::render::unicode::Dense1x2;
+
+    let code = qrcode::QrCode::new(text)?;
+    // Most terminals are light text on a dark background, so the colours are swapped so that the
+    // code is dark on light like scanners expect
+    let image = code
+        .render::<Dense1x2>()
+        .dark_color(Dense1x2::Light)
+        .light_color(Dense1x2::Dark)
+        .build();
+    println!("{}", image);
+    Ok(())
 }
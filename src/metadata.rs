@@ -0,0 +1,122 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context};
+use serde::Deserialize;
+
+/// The name of the file whose metadata applies to every file in its directory and below, when
+/// uploading recursively
+pub const DIR_FILE: &str = ".b2meta.json";
+
+/// The file info key that b2 serves as the `Cache-Control` header
+const CACHE_CONTROL_KEY: &str = "b2-cache-control";
+
+/// Metadata to upload files with, read from `--attach-metadata-from-json` or a [`DIR_FILE`], e.g.
+/// `{"contentType": "text/html", "cacheControl": "max-age=3600", "info": {"author": "me"}}`
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct Metadata {
+    pub content_type: Option<String>,
+    pub cache_control: Option<String>,
+    /// Custom file info
+    #[serde(default)]
+    pub info: BTreeMap<String, String>,
+}
+
+impl Metadata {
+    pub fn load(file: &Path) -> anyhow::Result<Self> {
+        let metadata: Self = serde_json::from_str(&fs::read_to_string(file)?)
+            .with_context(|| format!("{} is not a valid metadata file", file.display()))?;
+
+        for key in metadata.info.keys() {
+            if key.is_empty()
+                || key.len() > 50
+                || !key
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+            {
+                bail!(
+                    "Invalid file info key `{}` in {}, keys are up to 50 letters, numbers, `-`, \
+                     `_` and `.`",
+                    key,
+                    file.display()
+                );
+            }
+        }
+        Ok(metadata)
+    }
+
+    /// Apply `other` on top of this metadata, replacing anything that they both set
+    pub fn merge(&mut self, other: &Metadata) {
+        if other.content_type.is_some() {
+            self.content_type.clone_from(&other.content_type);
+        }
+        if other.cache_control.is_some() {
+            self.cache_control.clone_from(&other.cache_control);
+        }
+        self.info
+            .extend(other.info.iter().map(|(k, v)| (k.clone(), v.clone())));
+    }
+
+    /// The file info to store, including the cache control
+    pub fn file_info(&self) -> Vec<(String, String)> {
+        let mut info: Vec<_> = self
+            .info
+            .iter()
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect();
+        if let Some(ref cache_control) = self.cache_control {
+            info.push((CACHE_CONTROL_KEY.into(), cache_control.clone()));
+        }
+        info
+    }
+}
+
+/// Works out the metadata of each file in a recursive upload from the [`DIR_FILE`]s of the
+/// directories above it, reading each one once
+pub struct DirMetadata {
+    root: PathBuf,
+    base: Metadata,
+    dirs: HashMap<PathBuf, Option<Metadata>>,
+}
+
+impl DirMetadata {
+    /// `base` applies to everything under `root`, beneath the metadata of each directory
+    pub fn new(root: &Path, base: Metadata) -> Self {
+        Self {
+            root: root.to_path_buf(),
+            base,
+            dirs: HashMap::new(),
+        }
+    }
+
+    /// The metadata of `file`, with deeper directories taking precedence
+    pub fn get(&mut self, file: &Path) -> anyhow::Result<Metadata> {
+        let mut dirs: Vec<_> = file
+            .ancestors()
+            .skip(1)
+            .take_while(|d| d.starts_with(&self.root))
+            .collect();
+        dirs.reverse();
+
+        let mut metadata = self.base.clone();
+        for dir in dirs {
+            if !self.dirs.contains_key(dir) {
+                let path = dir.join(DIR_FILE);
+                let found = if path.is_file() {
+                    Some(Metadata::load(&path)?)
+                } else {
+                    None
+                };
+                self.dirs.insert(dir.to_path_buf(), found);
+            }
+            if let Some(Some(dir)) = self.dirs.get(dir) {
+                metadata.merge(dir);
+            }
+        }
+        Ok(metadata)
+    }
+}
@@ -0,0 +1,164 @@
+use std::collections::BTreeMap;
+
+use colored::Colorize;
+use humanize_bytes::humanize_bytes_decimal;
+
+use crate::{api::File, cli::OutputFormat, config::Config, path::RemotePath, table::Table};
+
+/// A way in which a file differs between the source and the mirror
+#[derive(Debug)]
+pub enum Difference {
+    /// The file is in the source but not the mirror
+    Missing {
+        name: String,
+    },
+    /// The file is in the mirror but not the source
+    Extra {
+        name: String,
+    },
+    Size {
+        name: String,
+        src: u64,
+        dst: u64,
+    },
+    Sha1 {
+        name: String,
+        src: String,
+        dst: String,
+    },
+}
+
+impl Difference {
+    fn name(&self) -> &str {
+        match self {
+            Difference::Missing { name }
+            | Difference::Extra { name }
+            | Difference::Size { name, .. }
+            | Difference::Sha1 { name, .. } => name,
+        }
+    }
+
+    fn kind(&self) -> &'static str {
+        match self {
+            Difference::Missing { .. } => "missing",
+            Difference::Extra { .. } => "extra",
+            Difference::Size { .. } => "size",
+            Difference::Sha1 { .. } => "sha1",
+        }
+    }
+
+    fn detail(&self) -> String {
+        match self {
+            Difference::Missing { .. } | Difference::Extra { .. } => String::new(),
+            Difference::Size { src, dst, .. } => format!("{} -> {}", src, dst),
+            Difference::Sha1 { src, dst, .. } => format!("{} -> {}", src, dst),
+        }
+    }
+}
+
+/// The sha1 of a file, which large files only have if the uploader set `large_file_sha1`
+fn sha1(file: &File) -> Option<&str> {
+    match file.content_sha1.trim_start_matches("unverified:") {
+        "none" => file.file_info["large_file_sha1"].as_str(),
+        sha1 => Some(sha1),
+    }
+}
+
+/// The latest version of every file under `path`, keyed by its name relative to `path`
+fn files(cfg: &mut Config, path: &RemotePath) -> anyhow::Result<BTreeMap<String, File>> {
+    Ok(cfg
+        .list_files(path)?
+        .into_iter()
+        .map(|f| (f.file_name[path.key.len()..].to_string(), f))
+        .collect())
+}
+
+/// Compare the files under `src` with those under `dst`, returning every difference and the
+/// number of files whose sha1s couldn't be compared
+pub fn verify(
+    cfg: &mut Config,
+    src: &RemotePath,
+    dst: &RemotePath,
+) -> anyhow::Result<(Vec<Difference>, usize)> {
+    let src_files = files(cfg, src)?;
+    let mut dst_files = files(cfg, dst)?;
+
+    let mut diffs = Vec::new();
+    let mut unhashed = 0;
+    for (name, src) in src_files {
+        let Some(dst) = dst_files.remove(&name) else {
+            diffs.push(Difference::Missing { name });
+            continue;
+        };
+
+        if src.content_length != dst.content_length {
+            diffs.push(Difference::Size {
+                name,
+                src: src.content_length,
+                dst: dst.content_length,
+            });
+            continue;
+        }
+
+        match (sha1(&src), sha1(&dst)) {
+            (Some(a), Some(b)) if !a.eq_ignore_ascii_case(b) => diffs.push(Difference::Sha1 {
+                name,
+                src: a.to_string(),
+                dst: b.to_string(),
+            }),
+            (Some(_), Some(_)) => {}
+            _ => unhashed += 1,
+        }
+    }
+    diffs.extend(dst_files.into_keys().map(|name| Difference::Extra { name }));
+    diffs.sort_by(|a, b| a.name().cmp(b.name()));
+
+    Ok((diffs, unhashed))
+}
+
+pub fn print(diffs: &[Difference], unhashed: usize, output: OutputFormat) -> anyhow::Result<()> {
+    if output != OutputFormat::Table {
+        let mut table = Table::new(vec!["name", "difference", "detail"]);
+        for diff in diffs {
+            table.row(vec![
+                diff.name().to_string(),
+                diff.kind().to_string(),
+                diff.detail(),
+            ]);
+        }
+        return table.print(output);
+    }
+
+    for diff in diffs {
+        match diff {
+            Difference::Missing { name } => println!("{} {}", "missing".red(), name),
+            Difference::Extra { name } => println!("{}   {}", "extra".yellow(), name),
+            Difference::Size { name, src, dst } => println!(
+                "{}    {} ({} -> {})",
+                "size".red(),
+                name,
+                humanize_bytes_decimal!(*src),
+                humanize_bytes_decimal!(*dst)
+            ),
+            Difference::Sha1 { name, .. } => println!("{}    {}", "sha1".red(), name),
+        }
+    }
+
+    if unhashed > 0 {
+        eprintln!(
+            "{}",
+            format!(
+                "{} large files have no sha1, so only their sizes were compared",
+                unhashed
+            )
+            .yellow()
+        );
+    }
+
+    if diffs.is_empty() {
+        println!("{}", "The mirror matches the source".green());
+    } else {
+        println!("{}", format!("{} differences", diffs.len()).red());
+    }
+    Ok(())
+}
@@ -0,0 +1,105 @@
+use std::time::Duration;
+
+use anyhow::bail;
+use colored::Colorize;
+use serde::Deserialize;
+
+use crate::{api::NotificationRule, config::Config, path::RemotePath, transfer};
+
+/// How long to give B2 to deliver the probe events before checking the rule again
+const DELIVERY_WAIT: Duration = Duration::from_secs(10);
+
+/// Get the notification rules for a bucket from the api
+pub fn get_rules(cfg: &mut Config, bucket: &str) -> anyhow::Result<Vec<NotificationRule>> {
+    let bucket_id = cfg.require_bucket_id(bucket)?;
+
+    let res: serde_json::Value = cfg.send_request_de(|cfg| {
+        Ok(cfg
+            .get("b2_get_bucket_notification_rules")?
+            .query(&[("bucketId", &bucket_id)])
+            .send()?)
+    })?;
+
+    Ok(Deserialize::deserialize(
+        res["eventNotificationRules"].clone(),
+    )?)
+}
+
+pub fn print_rules(rules: &[NotificationRule]) {
+    for rule in rules {
+        let state = if rule.is_suspended {
+            "suspended".red()
+        } else if rule.is_enabled {
+            "enabled".green()
+        } else {
+            "disabled".yellow()
+        };
+
+        println!(
+            "{} ({}) {} -> {}",
+            rule.name.blue(),
+            state,
+            rule.event_types.join(", "),
+            rule.target_configuration["url"].as_str().unwrap_or("?"),
+        );
+        if let Some(ref reason) = rule.suspension_reason {
+            println!("    {}", reason.red());
+        }
+    }
+}
+
+/// Upload and delete a probe file that matches `rule_name`, then check whether B2 has suspended
+/// the rule
+pub fn test_rule(cfg: &mut Config, bucket: &str, rule_name: &str) -> anyhow::Result<()> {
+    let rules = get_rules(cfg, bucket)?;
+    let Some(rule) = rules.into_iter().find(|r| r.name == rule_name) else {
+        bail!("Bucket `{}` has no rule named `{}`", bucket, rule_name);
+    };
+
+    if !rule.is_enabled {
+        bail!("Rule `{}` is disabled", rule.name);
+    }
+
+    let probe = RemotePath::new(bucket, rule.object_name_prefix.clone()).join(&format!(
+        "b2-notification-probe-{}",
+        chrono::Utc::now().timestamp_millis()
+    ));
+
+    let file = transfer::upload_bytes(cfg, &probe, b"b2 notification probe", "text/plain")?;
+    println!("Uploaded probe {}", probe.key.yellow());
+
+    cfg.delete_file_version(&file)?;
+    println!("Deleted probe {}", probe.key.yellow());
+
+    println!(
+        "Waiting {}s for the events to be delivered...",
+        DELIVERY_WAIT.as_secs()
+    );
+    std::thread::sleep(DELIVERY_WAIT);
+
+    let rules = get_rules(cfg, bucket)?;
+    let Some(rule) = rules.into_iter().find(|r| r.name == rule_name) else {
+        bail!("Rule `{}` was removed during the test", rule_name);
+    };
+
+    if rule.is_suspended {
+        bail!(
+            "Rule `{}` has been suspended: {}",
+            rule.name,
+            rule.suspension_reason
+                .as_deref()
+                .unwrap_or("unknown reason")
+        );
+    }
+
+    println!(
+        "{}",
+        format!(
+            "Probe events sent for `{}` and the rule is still active.",
+            rule.name
+        )
+        .green()
+    );
+
+    Ok(())
+}
@@ -0,0 +1,57 @@
+use std::time::{Duration, Instant};
+
+use colored::Colorize;
+
+/// Raises a desktop notification once a transfer that took at least `after` is over, saying that
+/// it failed unless [`Notifier::done`] was called first
+pub struct Notifier {
+    what: String,
+    after: Duration,
+    start: Instant,
+    done: bool,
+}
+
+impl Notifier {
+    /// Start timing `what`, if `--notify` was given with a number of seconds
+    pub fn start(what: impl Into<String>, after: Option<u64>) -> Option<Self> {
+        Some(Self {
+            what: what.into(),
+            after: Duration::from_secs(after?),
+            start: Instant::now(),
+            done: false,
+        })
+    }
+
+    /// Mark the transfer as having succeeded
+    pub fn done(mut self) {
+        self.done = true;
+    }
+}
+
+impl Drop for Notifier {
+    fn drop(&mut self) {
+        let elapsed = self.start.elapsed();
+        if elapsed < self.after {
+            return;
+        }
+
+        let (summary, body) = if self.done {
+            ("b2: Transfer finished", format!("{} finished", self.what))
+        } else {
+            ("b2: Transfer failed", format!("{} failed", self.what))
+        };
+        let body = format!("{} after {}s", body, elapsed.as_secs());
+
+        // Not being able to notify shouldn't take the transfer down with it
+        if let Err(e) = notify_rust::Notification::new()
+            .summary(summary)
+            .body(&body)
+            .show()
+        {
+            eprintln!(
+                "{}",
+                format!("Unable to show a notification: {}", e).yellow()
+            );
+        }
+    }
+}
@@ -0,0 +1,76 @@
+use std::{
+    io::Write,
+    process::{Command, Stdio},
+};
+
+use syntect::{
+    easy::HighlightLines,
+    highlighting::ThemeSet,
+    parsing::SyntaxSet,
+    util::{as_24_bit_terminal_escaped, LinesWithEndings},
+};
+
+/// The pager that is used if `$PAGER` is not set. `-F` makes it exit straight away if the text
+/// fits on one screen, so short files behave the same as just printing them.
+const DEFAULT_PAGER: &str = "less -RFX";
+
+const THEME: &str = "base16-ocean.dark";
+
+/// Add terminal colours to `text` based on the syntax guessed from `name`, or from the first line
+/// of the text if the name doesn't have a known extension
+pub fn highlight(name: &str, text: &str) -> String {
+    let syntaxes = SyntaxSet::load_defaults_newlines();
+    let name = name.rsplit('/').next().unwrap_or(name);
+    // Some syntaxes are matched on the whole name, like `Makefile`
+    let extension = name.rsplit_once('.').map(|(_, ext)| ext).unwrap_or(name);
+    let Some(syntax) = syntaxes
+        .find_syntax_by_extension(extension)
+        .or_else(|| syntaxes.find_syntax_by_first_line(text))
+    else {
+        return text.to_string();
+    };
+
+    let themes = ThemeSet::load_defaults();
+    let mut h = HighlightLines::new(syntax, &themes.themes[THEME]);
+
+    let mut out = String::with_capacity(text.len() * 2);
+    for line in LinesWithEndings::from(text) {
+        match h.highlight_line(line, &syntaxes) {
+            Ok(ranges) => out.push_str(&as_24_bit_terminal_escaped(&ranges, false)),
+            // Give up on highlighting the rest rather than failing to show the file
+            Err(_) => return text.to_string(),
+        }
+    }
+    out.push_str("\x1B[0m");
+
+    out
+}
+
+/// Show `text` using `$PAGER`, or print it directly if the pager can't be started
+pub fn page(text: &str) -> anyhow::Result<()> {
+    let pager = std::env::var("PAGER").unwrap_or_else(|_| DEFAULT_PAGER.to_string());
+    let mut args = pager.split_whitespace();
+
+    let child = args.next().and_then(|cmd| {
+        Command::new(cmd)
+            .args(args)
+            .stdin(Stdio::piped())
+            .spawn()
+            .ok()
+    });
+    let Some(mut child) = child else {
+        println!("{}", text);
+        return Ok(());
+    };
+
+    let mut stdin = child.stdin.take().unwrap();
+    match stdin.write_all(text.as_bytes()) {
+        // The user quit the pager before reading everything
+        Err(e) if e.kind() == std::io::ErrorKind::BrokenPipe => {}
+        r => r?,
+    }
+    drop(stdin);
+    child.wait()?;
+
+    Ok(())
+}
@@ -0,0 +1,186 @@
+use std::{
+    fmt::Display,
+    path::{Component, Path, PathBuf},
+    str::FromStr,
+};
+
+use anyhow::bail;
+
+/// The maximum length of a file name in b2, in bytes
+const MAX_KEY_LEN: usize = 1024;
+
+/// A path to a file (or prefix) within a bucket
+///
+/// Can be parsed from either `b2://bucket/key` or `bucket/key`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RemotePath {
+    pub bucket: String,
+    pub key: String,
+}
+
+impl RemotePath {
+    pub fn new(bucket: impl Into<String>, key: impl Into<String>) -> Self {
+        Self {
+            bucket: bucket.into(),
+            key: key.into(),
+        }
+    }
+
+    /// Join `path` onto the end of the key, inserting a `/` between them if needed
+    pub fn join(&self, path: &str) -> Self {
+        let path = path.trim_start_matches('/');
+        let key = if self.key.is_empty() || self.key.ends_with('/') || path.is_empty() {
+            format!("{}{}", self.key, path)
+        } else {
+            format!("{}/{}", self.key, path)
+        };
+
+        Self::new(self.bucket.clone(), key)
+    }
+
+    /// Join a relative local path onto the end of the key, using `/` as the separator
+    /// regardless of platform
+    ///
+    /// Root and `.` components are ignored so that a local path can never escape the key, and
+    /// any invalid UTF-8 is replaced with `U+FFFD`.
+    pub fn join_path(&self, path: &Path) -> anyhow::Result<Self> {
+        let mut out = self.clone();
+        for comp in path.components() {
+            match comp {
+                Component::Normal(comp) => out = out.join(&comp.to_string_lossy()),
+                Component::ParentDir => bail!("`{}` may not contain `..`", path.display()),
+                Component::Prefix(_) | Component::RootDir | Component::CurDir => {}
+            }
+        }
+
+        Ok(out)
+    }
+
+    /// Whether this path refers to a "directory", i.e. the key is empty or ends with `/`
+    pub fn is_dir(&self) -> bool {
+        self.key.is_empty() || self.key.ends_with('/')
+    }
+
+    /// The last segment of the key, or `None` if this path is a directory
+    pub fn file_name(&self) -> Option<&str> {
+        if self.is_dir() {
+            None
+        } else {
+            self.key.rsplit('/').next()
+        }
+    }
+
+    /// The friendly url for downloading this file, given the account's download url.  Each
+    /// segment of the key is percent-encoded, keeping the `/`s between them.
+    pub fn url(&self, download_url: &str) -> String {
+        let key: Vec<_> = self.key.split('/').map(urlencoding::encode).collect();
+        format!("{}/file/{}/{}", download_url, self.bucket, key.join("/"))
+    }
+}
+
+/// How the local paths of a recursive upload are turned into keys
+#[derive(Debug, Default)]
+pub struct Remap {
+    /// How many leading components to remove from each local path, like `tar`
+    pub strip_components: usize,
+    /// `(from, to)` pairs, the first `from` that the key starts with is replaced with `to`
+    pub rewrites: Vec<(String, String)>,
+}
+
+impl Remap {
+    /// The key that the local `path` is uploaded to, relative to the destination, or `None` if
+    /// the whole path was stripped
+    pub fn apply(&self, path: &Path) -> anyhow::Result<Option<String>> {
+        let stripped: PathBuf = path
+            .components()
+            .filter(|c| {
+                !matches!(
+                    c,
+                    Component::Prefix(_) | Component::RootDir | Component::CurDir
+                )
+            })
+            .skip(self.strip_components)
+            .collect();
+        if stripped.as_os_str().is_empty() {
+            return Ok(None);
+        }
+
+        let key = RemotePath::new("", "").join_path(&stripped)?.key;
+        for (from, to) in &self.rewrites {
+            if let Some(rest) = key.strip_prefix(from.as_str()) {
+                return Ok(Some(format!("{}{}", to, rest)));
+            }
+        }
+        Ok(Some(key))
+    }
+}
+
+impl FromStr for RemotePath {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.strip_prefix("b2://").unwrap_or(s);
+        let (bucket, key) = s.split_once('/').unwrap_or((s, ""));
+
+        if bucket.is_empty() {
+            bail!("No bucket specified");
+        }
+
+        if key.starts_with('/') {
+            bail!("File names may not start with `/`");
+        }
+
+        if key.len() > MAX_KEY_LEN {
+            bail!("File names may not be longer than {} bytes", MAX_KEY_LEN);
+        }
+
+        if key.chars().any(|c| c.is_control()) {
+            bail!("File names may not contain control characters");
+        }
+
+        Ok(Self::new(bucket, key))
+    }
+}
+
+impl Display for RemotePath {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "b2://{}/{}", self.bucket, self.key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RemotePath;
+
+    const DOWNLOAD_URL: &str = "https://f003.backblazeb2.com";
+
+    #[test]
+    fn url() {
+        let path = RemotePath::new("photos", "2024/cat.jpg");
+        assert_eq!(
+            path.url(DOWNLOAD_URL),
+            "https://f003.backblazeb2.com/file/photos/2024/cat.jpg"
+        );
+    }
+
+    #[test]
+    fn url_encodes_segments() {
+        let path = RemotePath::new("photos", "summer 2024/50% off?#1&más.jpg");
+        assert_eq!(
+            path.url(DOWNLOAD_URL),
+            "https://f003.backblazeb2.com/file/photos/summer%202024/50%25%20off%3F%231%26m%C3%A1s.jpg"
+        );
+    }
+
+    #[test]
+    fn url_keeps_empty_segments() {
+        assert_eq!(
+            RemotePath::new("photos", "a//b/").url(DOWNLOAD_URL),
+            "https://f003.backblazeb2.com/file/photos/a//b/"
+        );
+        assert_eq!(
+            RemotePath::new("photos", "").url(DOWNLOAD_URL),
+            "https://f003.backblazeb2.com/file/photos/"
+        );
+    }
+}
@@ -0,0 +1,101 @@
+use std::io::Write;
+
+use anyhow::bail;
+
+use crate::{config::Config, http, path::RemotePath};
+
+/// How much of the file is requested at a time while looking for enough lines
+const CHUNK_SIZE: u64 = 64 * 1024;
+
+/// Print the first `lines` lines of `path`, only downloading as much of it as is needed
+pub fn head(cfg: &mut Config, path: &RemotePath, lines: usize) -> anyhow::Result<()> {
+    if lines == 0 {
+        return Ok(());
+    }
+    let size = file_size(cfg, path)?;
+
+    let mut buf = Vec::new();
+    let mut offset = 0;
+    while offset < size && count_lines(&buf) < lines {
+        let end = std::cmp::min(offset + CHUNK_SIZE, size);
+        buf.extend(get_range(cfg, path, offset, end)?);
+        offset = end;
+    }
+
+    let end = buf
+        .iter()
+        .enumerate()
+        .filter(|(_, b)| **b == b'\n')
+        .nth(lines - 1)
+        .map(|(i, _)| i + 1)
+        .unwrap_or(buf.len());
+
+    std::io::stdout().write_all(&buf[..end])?;
+    Ok(())
+}
+
+/// Print the last `lines` lines of `path`, downloading it backwards from the end until enough
+/// lines have been found
+pub fn tail(cfg: &mut Config, path: &RemotePath, lines: usize) -> anyhow::Result<()> {
+    if lines == 0 {
+        return Ok(());
+    }
+    let size = file_size(cfg, path)?;
+
+    let mut buf = Vec::new();
+    let mut start = size;
+    // The newline at the very end of the file doesn't start another line
+    while start > 0 && count_lines(buf.strip_suffix(b"\n").unwrap_or(&buf)) < lines {
+        let next = start.saturating_sub(CHUNK_SIZE);
+        let mut chunk = get_range(cfg, path, next, start)?;
+        chunk.extend(buf);
+        buf = chunk;
+        start = next;
+    }
+
+    let body = buf.strip_suffix(b"\n").unwrap_or(&buf);
+    let begin = body
+        .iter()
+        .enumerate()
+        .rev()
+        .filter(|(_, b)| **b == b'\n')
+        .nth(lines - 1)
+        .map(|(i, _)| i + 1)
+        .unwrap_or(0);
+
+    std::io::stdout().write_all(&buf[begin..])?;
+    Ok(())
+}
+
+fn count_lines(buf: &[u8]) -> usize {
+    buf.iter().filter(|b| **b == b'\n').count()
+}
+
+/// The size of the latest version of `path`
+pub fn file_size(cfg: &mut Config, path: &RemotePath) -> anyhow::Result<u64> {
+    let Some(file) = cfg.get_file(path)? else {
+        bail!("{} does not exist", path);
+    };
+    Ok(file.content_length)
+}
+
+/// Download the bytes from `start` up to (but not including) `end`
+pub fn get_range(
+    cfg: &mut Config,
+    path: &RemotePath,
+    start: u64,
+    end: u64,
+) -> anyhow::Result<Vec<u8>> {
+    let url = path.url(&cfg.download_url);
+    let mut res = cfg.send_request_res(|cfg| {
+        Ok(http::client()
+            .get(&url)
+            .header("Authorization", &cfg.auth_token)
+            .header("Range", format!("bytes={}-{}", start, end - 1))
+            .send()?)
+    })?;
+
+    let mut buf = Vec::with_capacity((end - start) as usize);
+    res.copy_to(&mut buf)?;
+    Ok(buf)
+}
@@ -0,0 +1,203 @@
+use std::{collections::BTreeSet, ffi::OsString, io::IsTerminal};
+
+use anyhow::bail;
+use clap::{
+    error::{ContextKind, ContextValue, ErrorKind},
+    CommandFactory, Parser,
+};
+use dialoguer::{theme::ColorfulTheme, FuzzySelect};
+use humanize_bytes::humanize_bytes_decimal;
+
+use crate::{
+    cli::Cli,
+    config::{self, Config},
+    files::{self, FileTree},
+    path::RemotePath,
+};
+
+/// Whether there is someone at the terminal to pick from a list
+fn interactive() -> bool {
+    std::io::stdin().is_terminal() && std::io::stderr().is_terminal()
+}
+
+/// Handle `err` from parsing `args`: if the only thing missing is the bucket and someone is at the
+/// terminal, let them pick one of the cached buckets and parse the command again with it.
+/// Otherwise the error is printed and the program exits, as clap would have done.
+pub fn missing_bucket(err: clap::Error, mut args: Vec<OsString>) -> anyhow::Result<Cli> {
+    let only_bucket = err.kind() == ErrorKind::MissingRequiredArgument
+        && matches!(
+            err.get(ContextKind::InvalidArg),
+            Some(ContextValue::Strings(missing)) if missing == &["<bucket>"]
+        );
+    if !only_bucket || !interactive() {
+        err.exit();
+    }
+
+    // The command line didn't parse, so the profile is picked out of it without checking the rest
+    let profile = Cli::command()
+        .ignore_errors(true)
+        .try_get_matches_from(&args)
+        .ok()
+        .and_then(|m| m.get_one::<String>("profile").cloned());
+    let cfg = Config::load(profile.as_deref().map(config::profile_path).transpose()?)?;
+
+    let mut buckets: Vec<_> = cfg.buckets.keys().collect();
+    if buckets.is_empty() {
+        err.exit();
+    }
+    buckets.sort();
+
+    let Some(i) = FuzzySelect::with_theme(&ColorfulTheme::default())
+        .with_prompt("Bucket")
+        .items(&buckets)
+        .default(0)
+        .interact_opt()?
+    else {
+        err.exit();
+    };
+
+    // The bucket is the last positional that is missing, so it goes on the end, after `--` so
+    // that an option with an optional value can't take it
+    if !args.iter().any(|a| a == "--") {
+        args.push("--".into());
+    }
+    args.push(buckets[i].into());
+    Ok(Cli::parse_from(args))
+}
+
+/// An entry in the list of [`files`]
+enum Entry {
+    Done,
+    Up,
+    /// Pick every file under the current directory, or unpick them if they all are already
+    All,
+    Dir(String),
+    /// A file, by its name relative to the picked directory
+    File(String),
+}
+
+/// Let the user pick files under the directory `dir` by moving through its tree, typing to
+/// narrow down each directory.  If `multiple` is set, files are toggled until "Done" is picked,
+/// otherwise the first file picked is the only one.  Returns the names of the picked files
+/// relative to `dir`, which are none if the picker was cancelled.
+pub fn files(cfg: &mut Config, dir: &RemotePath, multiple: bool) -> anyhow::Result<Vec<String>> {
+    if !interactive() {
+        bail!("Picking files needs a terminal");
+    }
+
+    let mut listing = cfg.list_files(dir)?;
+    for file in &mut listing {
+        file.file_name.drain(..dir.key.len());
+    }
+    let tree = files::files_to_tree(listing);
+    if tree.children().is_some_and(|c| c.is_empty()) {
+        bail!("There are no files in {}", dir);
+    }
+
+    let mut path: Vec<String> = Vec::new();
+    // Start in the first directory that has more than one thing in it
+    let mut curr = &tree;
+    while let Some(children) = curr.children() {
+        match children.iter().next() {
+            Some((name, child @ FileTree::Directory { .. })) if children.len() == 1 => {
+                path.push(name.clone());
+                curr = child;
+            }
+            _ => break,
+        }
+    }
+
+    let mut picked = BTreeSet::new();
+    loop {
+        let curr = path.iter().fold(&tree, |tree, name| {
+            &tree.children().expect("only directories are entered")[name]
+        });
+        let children = curr.children().expect("only directories are entered");
+
+        let mut entries = Vec::new();
+        let mut items = Vec::new();
+        if multiple {
+            entries.push(Entry::Done);
+            items.push(format!("Done ({} picked)", picked.len()));
+        }
+        if !path.is_empty() {
+            entries.push(Entry::Up);
+            items.push("../".to_string());
+        }
+        if multiple {
+            let (files, size) = curr.totals();
+            entries.push(Entry::All);
+            items.push(format!(
+                "Pick everything in here ({} files, {})",
+                files,
+                humanize_bytes_decimal!(size)
+            ));
+        }
+        for (name, child) in children {
+            match child {
+                FileTree::File { file, .. } => {
+                    let mark = match (multiple, picked.contains(&file.file_name)) {
+                        (false, _) => "",
+                        (true, false) => "[ ] ",
+                        (true, true) => "[x] ",
+                    };
+                    items.push(format!(
+                        "{}{} ({})",
+                        mark,
+                        name,
+                        humanize_bytes_decimal!(file.content_length)
+                    ));
+                    entries.push(Entry::File(file.file_name.clone()));
+                }
+                _ => {
+                    let (files, size) = child.totals();
+                    items.push(format!(
+                        "{}/ ({} files, {})",
+                        name,
+                        files,
+                        humanize_bytes_decimal!(size)
+                    ));
+                    entries.push(Entry::Dir(name.clone()));
+                }
+            }
+        }
+
+        let prompt = if path.is_empty() {
+            dir.to_string()
+        } else {
+            format!("{}{}/", dir, path.join("/"))
+        };
+        let Some(i) = FuzzySelect::with_theme(&ColorfulTheme::default())
+            .with_prompt(prompt)
+            .items(&items)
+            .default(0)
+            .interact_opt()?
+        else {
+            return Ok(Vec::new());
+        };
+
+        match &entries[i] {
+            Entry::Done => return Ok(picked.into_iter().collect()),
+            Entry::Up => {
+                path.pop();
+            }
+            Entry::All => {
+                let under: Vec<_> = curr.files().into_iter().map(|f| &f.file_name).collect();
+                if under.iter().all(|f| picked.contains(*f)) {
+                    for f in under {
+                        picked.remove(f);
+                    }
+                } else {
+                    picked.extend(under.into_iter().cloned());
+                }
+            }
+            Entry::Dir(name) => path.push(name.clone()),
+            Entry::File(name) if !multiple => return Ok(vec![name.clone()]),
+            Entry::File(name) => {
+                if !picked.remove(name) {
+                    picked.insert(name.clone());
+                }
+            }
+        }
+    }
+}
@@ -1,19 +1,146 @@
+use colored::Colorize;
+use humanize_bytes::humanize_bytes_decimal;
 use progress_bar as bar;
 use std::{
-    io::{Read, Write},
+    io::{IsTerminal, Read, Write},
     ops::{Deref, DerefMut},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
 };
 
+/// The frames of the spinner shown when the length of a transfer is unknown
+const SPINNER_FRAMES: [char; 4] = ['|', '/', '-', '\\'];
+
+/// The label of the spinner that is currently being shown, if there is one
+///
+/// Like the progress bar, there can only be one of these at a time.
+static SPINNER: Mutex<Option<(String, usize)>> = Mutex::new(None);
+
+/// Set by `--no-progress`
+static DISABLED: AtomicBool = AtomicBool::new(false);
+
+/// Stop [`ProgressSink::auto`] from drawing progress bars
+pub fn disable() {
+    DISABLED.store(true, Ordering::Relaxed);
+}
+
+/// Where the progress of a transfer is reported to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProgressSink {
+    /// Draw a progress bar in the terminal
+    Bar,
+    /// Don't report progress at all
+    None,
+}
+
+impl ProgressSink {
+    /// Draw a bar unless `--no-progress` was given or stdout (where the bars are drawn) isn't a
+    /// terminal, so that redirected output isn't full of half-drawn bars
+    pub fn auto() -> Self {
+        if DISABLED.load(Ordering::Relaxed) || !std::io::stdout().is_terminal() {
+            ProgressSink::None
+        } else {
+            ProgressSink::Bar
+        }
+    }
+
+    /// Start reporting progress, showing a spinner instead of a bar if `len` is unknown
+    pub fn start(self, len: Option<usize>, label: &str) {
+        match (self, len) {
+            (ProgressSink::Bar, Some(len)) => {
+                bar::init_progress_bar_with_eta(len);
+                bar::set_progress_bar_action(label, bar::Color::Green, bar::Style::Bold);
+            }
+            (ProgressSink::Bar, None) => {
+                *SPINNER.lock().unwrap() = Some((label.to_string(), 0));
+                draw_spinner(label, 0, 0);
+            }
+            (ProgressSink::None, _) => {}
+        }
+    }
+
+    /// Replace the label in front of the bar, which only has room for 12 characters
+    pub fn label(self, label: &str) {
+        match self {
+            ProgressSink::Bar => {
+                if let Some((ref mut current, _)) = *SPINNER.lock().unwrap() {
+                    *current = label.to_string();
+                    return;
+                }
+                bar::set_progress_bar_action(label, bar::Color::Green, bar::Style::Bold)
+            }
+            ProgressSink::None => {}
+        }
+    }
+
+    /// Print a line above the bar, which is then drawn again below it
+    pub fn info(self, name: &str, text: &str) {
+        match self {
+            ProgressSink::Bar => {
+                if SPINNER.lock().unwrap().is_some() {
+                    println!("\r{} {}\x1B[K", name.blue().bold(), text);
+                    return;
+                }
+                bar::print_progress_bar_info(name, text, bar::Color::Blue, bar::Style::Bold)
+            }
+            ProgressSink::None => {}
+        }
+    }
+
+    pub fn set(self, curr: usize) {
+        match self {
+            ProgressSink::Bar => {
+                if let Some((ref label, ref mut frame)) = *SPINNER.lock().unwrap() {
+                    *frame += 1;
+                    draw_spinner(label, *frame, curr);
+                    return;
+                }
+                bar::set_progress_bar_progress(curr)
+            }
+            ProgressSink::None => {}
+        }
+    }
+
+    pub fn finish(self) {
+        match self {
+            ProgressSink::Bar => {
+                if SPINNER.lock().unwrap().take().is_some() {
+                    println!();
+                    return;
+                }
+                bar::finalize_progress_bar()
+            }
+            ProgressSink::None => {}
+        }
+    }
+}
+
+fn draw_spinner(label: &str, frame: usize, curr: usize) {
+    print!(
+        "\r{} {} {}\x1B[K",
+        label.green().bold(),
+        SPINNER_FRAMES[frame % SPINNER_FRAMES.len()],
+        humanize_bytes_decimal!(curr)
+    );
+    let _ = std::io::stdout().flush();
+}
+
 pub struct ReaderProgress<R> {
     inner: R,
     curr: usize,
+    sink: ProgressSink,
 }
 
 impl<R> ReaderProgress<R> {
-    pub fn new(r: R, len: usize, label: &str) -> Self {
-        bar::init_progress_bar_with_eta(len);
-        bar::set_progress_bar_action(label, bar::Color::Green, bar::Style::Bold);
-        Self { inner: r, curr: 0 }
+    pub fn new(r: R, len: usize, label: &str, sink: ProgressSink) -> Self {
+        sink.start(Some(len), label);
+        Self {
+            inner: r,
+            curr: 0,
+            sink,
+        }
     }
 }
 
@@ -25,7 +152,7 @@ where
         match self.inner.read(buf) {
             Ok(n) => {
                 self.curr += n;
-                bar::set_progress_bar_progress(self.curr);
+                self.sink.set(self.curr);
                 Ok(n)
             }
             Err(e) => Err(e),
@@ -50,13 +177,17 @@ impl<R> DerefMut for ReaderProgress<R> {
 pub struct WriterProgress<W> {
     inner: W,
     curr: usize,
+    sink: ProgressSink,
 }
 
 impl<W> WriterProgress<W> {
-    pub fn new(w: W, len: usize) -> Self {
-        bar::init_progress_bar_with_eta(len);
-        bar::set_progress_bar_action("Downloading", bar::Color::Green, bar::Style::Bold);
-        Self { inner: w, curr: 0 }
+    pub fn new(w: W, len: Option<usize>, sink: ProgressSink) -> Self {
+        sink.start(len, "Downloading");
+        Self {
+            inner: w,
+            curr: 0,
+            sink,
+        }
     }
 }
 
@@ -68,7 +199,7 @@ where
         match self.inner.write(buf) {
             Ok(n) => {
                 self.curr += n;
-                bar::set_progress_bar_progress(self.curr);
+                self.sink.set(self.curr);
                 Ok(n)
             }
             Err(e) => Err(e),
@@ -79,3 +210,10 @@ where
         self.inner.flush()
     }
 }
+
+impl<W> WriterProgress<W> {
+    /// Get back the writer that is being wrapped
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
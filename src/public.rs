@@ -0,0 +1,76 @@
+use anyhow::bail;
+use chrono::{DateTime, TimeZone, Utc};
+use colored::Colorize;
+use humanize_bytes::humanize_bytes_decimal;
+use reqwest::StatusCode;
+
+use crate::{config::Config, http, path::RemotePath};
+
+/// A file in a public bucket, as much as can be seen of it without authorising
+pub struct PublicFile {
+    pub name: String,
+    pub size: u64,
+    pub content_type: String,
+    pub modified: Option<DateTime<Utc>>,
+}
+
+/// The download url to use without authorising, which is saved by `b2 authorise` or can be set
+/// as `download_url` in the config
+pub fn download_url(cfg: &Config) -> anyhow::Result<&str> {
+    if cfg.download_url.is_empty() {
+        bail!(
+            "The download url for the bucket isn't known, set `download_url` in the config to \
+             the start of its friendly url, e.g. `https://f003.backblazeb2.com`"
+        );
+    }
+    Ok(&cfg.download_url)
+}
+
+/// Get the details of the file at `path` in a public bucket, without authorising
+///
+/// b2 has no way to list the files in a bucket without authorising, even when it is public, so
+/// this only works for a single file.
+pub fn head(cfg: &Config, path: &RemotePath) -> anyhow::Result<Option<PublicFile>> {
+    let res = http::client().head(path.url(download_url(cfg)?)).send()?;
+    match res.status() {
+        StatusCode::NOT_FOUND => return Ok(None),
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => {
+            bail!("`{}` is not a public bucket", path.bucket)
+        }
+        _ => {}
+    }
+    let res = res.error_for_status()?;
+
+    let header = |name: &str| res.headers().get(name).and_then(|h| h.to_str().ok());
+    let modified = header("X-Bz-Info-src_last_modified_millis")
+        .or(header("X-Bz-Upload-Timestamp"))
+        .and_then(|ms| ms.parse().ok())
+        .and_then(|ms| Utc.timestamp_millis_opt(ms).single());
+
+    Ok(Some(PublicFile {
+        name: path.key.clone(),
+        // The body of a HEAD response is empty, so `content_length` would always be 0
+        size: header("Content-Length")
+            .and_then(|l| l.parse().ok())
+            .unwrap_or_default(),
+        content_type: header("Content-Type").unwrap_or_default().to_string(),
+        modified,
+    }))
+}
+
+impl PublicFile {
+    pub fn print(&self, long: bool) {
+        if long {
+            println!(
+                "  {:>10}   {}   {}   {}",
+                humanize_bytes_decimal!(self.size),
+                self.modified
+                    .map_or("-".into(), |m| m.format("%e %h %Y %H:%M").to_string()),
+                self.name.yellow(),
+                self.content_type
+            );
+        } else {
+            println!("{}", self.name);
+        }
+    }
+}
@@ -0,0 +1,79 @@
+use std::{path::Path, process::Command};
+
+use anyhow::bail;
+use colored::Colorize;
+
+use crate::{
+    config::Config,
+    path::RemotePath,
+    transfer::{self, ChecksumAlgorithm, TransferJob},
+};
+
+/// Upload `file` into the directory `dest` along with a `<file>.sha256` sidecar in the format of
+/// `sha256sum`, and a `<file>.sig` holding what `sign` prints if it is given, then print the url
+/// of each
+pub fn release(
+    cfg: &mut Config,
+    file: &Path,
+    dest: &RemotePath,
+    sign: Option<&[String]>,
+) -> anyhow::Result<()> {
+    let Some(name) = file.file_name().and_then(|n| n.to_str()) else {
+        bail!("{} is not a file with a UTF-8 name", file.display());
+    };
+
+    // Signing first means nothing is uploaded if it fails
+    let signature = match sign {
+        Some(command) => Some(run_sign(command, file)?),
+        None => None,
+    };
+
+    let hashes = transfer::hash_file_all(file, &[ChecksumAlgorithm::Sha256])?;
+    let sha256 = hashes[0].1.clone();
+
+    let artifact = dest.join(name);
+    let mut job = TransferJob::upload(file.to_path_buf(), artifact.clone());
+    job.checksums = vec![ChecksumAlgorithm::Sha256];
+    job.hashes = hashes;
+    job.run(cfg)?;
+
+    let checksum = dest.join(&format!("{}.sha256", name));
+    let sidecar = format!("{}  {}\n", sha256, name);
+    transfer::upload_bytes(cfg, &checksum, sidecar.as_bytes(), "text/plain")?;
+
+    let mut uploaded = vec![artifact, checksum];
+    if let Some(signature) = signature {
+        let sig = dest.join(&format!("{}.sig", name));
+        transfer::upload_bytes(cfg, &sig, &signature, "application/octet-stream")?;
+        uploaded.push(sig);
+    }
+
+    println!();
+    for path in uploaded {
+        println!("{}", path.url(&cfg.download_url).blue());
+    }
+    Ok(())
+}
+
+/// Run the signing command for `file`, returning what it printed
+fn run_sign(command: &[String], file: &Path) -> anyhow::Result<Vec<u8>> {
+    let Some((program, args)) = command.split_first() else {
+        bail!("No command given to --sign");
+    };
+    let path = file.to_string_lossy();
+    let output = Command::new(program)
+        .args(args.iter().map(|a| a.replace("{}", &path)))
+        .output()?;
+    if !output.status.success() {
+        bail!(
+            "`{}` failed ({}): {}",
+            program,
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    if output.stdout.is_empty() {
+        bail!("`{}` didn't print a signature", program);
+    }
+    Ok(output.stdout)
+}
@@ -0,0 +1,183 @@
+use chrono::{DateTime, TimeZone, Utc};
+use humanize_bytes::humanize_bytes_decimal;
+use serde::Serialize;
+
+use crate::{
+    api::{BucketType, File},
+    config::Config,
+    path::RemotePath,
+};
+
+/// How many of the largest and oldest files are listed for each bucket
+const TOP: usize = 5;
+
+/// How `b2 report` is printed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Format {
+    Markdown,
+    Json,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FileSummary {
+    pub name: String,
+    pub size: u64,
+    pub uploaded: DateTime<Utc>,
+}
+
+impl From<&File> for FileSummary {
+    fn from(file: &File) -> Self {
+        Self {
+            name: file.file_name.clone(),
+            size: file.content_length,
+            uploaded: file.upload_timestamp,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct BucketReport {
+    pub name: String,
+    /// Whether anyone can download the files without authorising
+    pub public: bool,
+    pub file_count: usize,
+    pub total_size: u64,
+    pub largest: Vec<FileSummary>,
+    pub unfinished_large_files: usize,
+    pub oldest_unfinished: Vec<FileSummary>,
+    pub lifecycle_rules: usize,
+    /// How many of the files fall under the prefix of at least one lifecycle rule
+    pub lifecycle_covered_files: usize,
+}
+
+/// Summarise every bucket that the key can see
+pub fn report(cfg: &mut Config) -> anyhow::Result<Vec<BucketReport>> {
+    let mut buckets = cfg.get_buckets()?;
+    buckets.sort_by(|a, b| a.bucket_name.cmp(&b.bucket_name));
+
+    let mut reports = Vec::with_capacity(buckets.len());
+    for bucket in buckets {
+        let mut files = cfg.list_files(&RemotePath::new(&bucket.bucket_name, ""))?;
+
+        let prefixes: Vec<_> = bucket
+            .lifecycle_rules
+            .iter()
+            .filter_map(|r| r["fileNamePrefix"].as_str())
+            .collect();
+        let covered = files
+            .iter()
+            .filter(|f| prefixes.iter().any(|p| f.file_name.starts_with(p)))
+            .count();
+
+        let mut unfinished = unfinished_large_files(cfg, &bucket.bucket_id)?;
+        unfinished.sort_by_key(|f| f.uploaded);
+
+        files.sort_by_key(|f| std::cmp::Reverse(f.content_length));
+
+        reports.push(BucketReport {
+            public: bucket.bucket_type == BucketType::AllPublic,
+            file_count: files.len(),
+            total_size: files.iter().map(|f| f.content_length).sum(),
+            largest: files.iter().take(TOP).map(FileSummary::from).collect(),
+            unfinished_large_files: unfinished.len(),
+            oldest_unfinished: unfinished.into_iter().take(TOP).collect(),
+            lifecycle_rules: bucket.lifecycle_rules.len(),
+            lifecycle_covered_files: covered,
+            name: bucket.bucket_name,
+        });
+    }
+
+    Ok(reports)
+}
+
+/// The large files in a bucket which were started but never finished or cancelled, which are
+/// still billed for the parts uploaded so far
+fn unfinished_large_files(cfg: &mut Config, bucket_id: &str) -> anyhow::Result<Vec<FileSummary>> {
+    let mut files = Vec::new();
+    let mut start: Option<String> = None;
+    loop {
+        let mut query = vec![
+            ("bucketId", bucket_id.to_string()),
+            ("maxFileCount", "100".to_string()),
+        ];
+        if let Some(start) = start {
+            query.push(("startFileId", start));
+        }
+
+        let res: serde_json::Value = cfg.send_request_de(|cfg| {
+            Ok(cfg
+                .get("b2_list_unfinished_large_files")?
+                .query(&query)
+                .send()?)
+        })?;
+
+        for file in res["files"].as_array().into_iter().flatten() {
+            files.push(FileSummary {
+                name: file["fileName"].as_str().unwrap_or_default().to_string(),
+                size: 0,
+                uploaded: file["uploadTimestamp"]
+                    .as_i64()
+                    .and_then(|ms| Utc.timestamp_millis_opt(ms).single())
+                    .unwrap_or_default(),
+            });
+        }
+
+        match res["nextFileId"].as_str() {
+            Some(next) => start = Some(next.to_string()),
+            None => break Ok(files),
+        }
+    }
+}
+
+pub fn print(reports: &[BucketReport], format: Format) -> anyhow::Result<()> {
+    match format {
+        Format::Json => println!("{}", serde_json::to_string_pretty(reports)?),
+        Format::Markdown => {
+            println!("# B2 report ({})", Utc::now().format("%Y-%m-%d %H:%M UTC"));
+            for r in reports {
+                println!();
+                println!("## {}", r.name);
+                println!();
+                if r.public {
+                    println!("- **Public**: anyone can download its files");
+                } else {
+                    println!("- Private");
+                }
+                println!(
+                    "- {} files, {}",
+                    r.file_count,
+                    humanize_bytes_decimal!(r.total_size)
+                );
+                println!(
+                    "- {} lifecycle rules, covering {} of {} files",
+                    r.lifecycle_rules, r.lifecycle_covered_files, r.file_count
+                );
+                println!("- {} unfinished large files", r.unfinished_large_files);
+
+                if !r.largest.is_empty() {
+                    println!();
+                    println!("| Largest files | Size | Uploaded |");
+                    println!("| --- | --- | --- |");
+                    for f in &r.largest {
+                        println!(
+                            "| `{}` | {} | {} |",
+                            f.name,
+                            humanize_bytes_decimal!(f.size),
+                            f.uploaded.format("%Y-%m-%d")
+                        );
+                    }
+                }
+
+                if !r.oldest_unfinished.is_empty() {
+                    println!();
+                    println!("| Oldest unfinished large files | Started |");
+                    println!("| --- | --- |");
+                    for f in &r.oldest_unfinished {
+                        println!("| `{}` | {} |", f.name, f.uploaded.format("%Y-%m-%d"));
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
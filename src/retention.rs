@@ -0,0 +1,150 @@
+use anyhow::bail;
+use chrono::{DateTime, Utc};
+use colored::Colorize;
+
+use crate::{
+    api::{ApiError, File},
+    cli::RetentionMode,
+    config::Config,
+};
+
+/// Set the retention of `file`, explaining any errors caused by its current retention
+pub fn update(
+    cfg: &mut Config,
+    file: &File,
+    mode: RetentionMode,
+    retain_until: Option<DateTime<Utc>>,
+    bypass_governance: bool,
+) -> anyhow::Result<()> {
+    let retention = match (mode, retain_until) {
+        (RetentionMode::None, _) => serde_json::json!({
+            "mode": null,
+            "retainUntilTimestamp": null,
+        }),
+        (_, None) => bail!("--retain-until is required unless the mode is `none`"),
+        (mode, Some(until)) => serde_json::json!({
+            "mode": match mode {
+                RetentionMode::Governance => "governance",
+                RetentionMode::Compliance => "compliance",
+                RetentionMode::None => unreachable!(),
+            },
+            "retainUntilTimestamp": until.timestamp_millis(),
+        }),
+    };
+
+    let res: anyhow::Result<serde_json::Value> = cfg.send_request_de(|cfg| {
+        Ok(cfg
+            .post("b2_update_file_retention")?
+            .json(&serde_json::json!({
+                "fileName": file.file_name,
+                "fileId": file.file_id,
+                "fileRetention": retention,
+                "bypassGovernance": bypass_governance,
+            }))
+            .send()?)
+    });
+
+    if let Err(e) = res {
+        return Err(explain_lock_error(e, file, bypass_governance));
+    }
+    cfg.forget_lists(Some(&file.bucket_id));
+
+    println!(
+        "{}",
+        format!("Updated the retention of {}!", file.file_name).green()
+    );
+
+    Ok(())
+}
+
+/// Replace an api error caused by a file's retention or legal hold with one that says why the
+/// file is locked and what can be done about it
+pub fn explain_lock_error(
+    err: anyhow::Error,
+    file: &File,
+    bypass_governance: bool,
+) -> anyhow::Error {
+    let Some(api_error) = err.downcast_ref::<ApiError>() else {
+        return err;
+    };
+
+    if bypass_governance && api_error.code == "access_denied" {
+        return anyhow::anyhow!(
+            "Unable to bypass governance mode for {}: the application key needs the `bypassGovernance` capability",
+            file.file_name
+        );
+    }
+
+    if !api_error.is_file_lock() {
+        return err;
+    }
+
+    let retention = file.file_retention.as_ref().map(|r| &r.value);
+    let mode = retention.and_then(|r| r["mode"].as_str());
+    let until = retention
+        .and_then(|r| r["retainUntilTimestamp"].as_i64())
+        .and_then(DateTime::<Utc>::from_timestamp_millis);
+
+    match (mode, until) {
+        (Some("governance"), Some(until)) => anyhow::anyhow!(
+            "{} is locked in governance mode until {}, use --bypass-governance to override it",
+            file.file_name,
+            until.format("%e %h %Y %H:%M")
+        ),
+        (Some("compliance"), Some(until)) => anyhow::anyhow!(
+            "{} is locked in compliance mode until {}, which cannot be shortened or removed",
+            file.file_name,
+            until.format("%e %h %Y %H:%M")
+        ),
+        _ => anyhow::anyhow!(
+            "{} is protected by its retention settings or a legal hold: {}",
+            file.file_name,
+            api_error.message
+        ),
+    }
+}
+
+/// Why `file` can't be deleted, if its retention or legal hold is known to prevent it
+///
+/// This relies on the listing including the retention and legal hold, which needs the
+/// `readFileRetentions` and `readFileLegalHolds` capabilities.
+pub fn lock_reason(file: &File) -> Option<String> {
+    let legal_hold = file.legal_hold.as_ref().map(|h| &h.value);
+    if legal_hold.and_then(|h| h.as_str()) == Some("on") {
+        return Some(format!("{} is under a legal hold", file.file_name));
+    }
+
+    let retention = file.file_retention.as_ref().map(|r| &r.value);
+    let mode = retention.and_then(|r| r["mode"].as_str())?;
+    let until = retention
+        .and_then(|r| r["retainUntilTimestamp"].as_i64())
+        .and_then(DateTime::<Utc>::from_timestamp_millis)
+        .filter(|until| *until > Utc::now())?;
+    Some(format!(
+        "{} is locked in {} mode until {}",
+        file.file_name,
+        mode,
+        until.format("%e %h %Y %H:%M")
+    ))
+}
+
+/// Delete `file` unless its retention or legal hold prevents it, in which case why is printed
+/// and `false` is returned rather than failing
+pub fn delete_unless_locked(cfg: &mut Config, file: &File) -> anyhow::Result<bool> {
+    let reason = match lock_reason(file) {
+        Some(reason) => reason,
+        None => match cfg.delete_file_version(file) {
+            Ok(()) => return Ok(true),
+            Err(e)
+                if e.downcast_ref::<ApiError>()
+                    .is_some_and(ApiError::is_file_lock) =>
+            {
+                explain_lock_error(e, file, false).to_string()
+            }
+            Err(e) => return Err(e),
+        },
+    };
+
+    eprintln!("{} {}", "skipped".yellow(), reason);
+    Ok(false)
+}
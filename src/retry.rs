@@ -0,0 +1,179 @@
+use std::{
+    ffi::OsString,
+    fs,
+    os::unix::ffi::OsStringExt,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+use anyhow::{bail, Context};
+use clap::Parser;
+use serde::{Deserialize, Serialize};
+
+use crate::cli::{Cli, Command};
+
+/// The arguments of the upload being retried, which are recorded again if it still fails rather
+/// than those of `b2 retry`
+static RETRYING: OnceLock<Vec<OsString>> = OnceLock::new();
+
+/// An argument or path, kept as a string when it is valid UTF-8 so that the file can be read and
+/// edited, and as its bytes otherwise so that any name survives the round trip
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+enum OsText {
+    Utf8(String),
+    Bytes(Vec<u8>),
+}
+
+impl From<OsString> for OsText {
+    fn from(s: OsString) -> Self {
+        match s.into_string() {
+            Ok(s) => Self::Utf8(s),
+            Err(s) => Self::Bytes(s.into_vec()),
+        }
+    }
+}
+
+impl From<OsText> for OsString {
+    fn from(s: OsText) -> Self {
+        match s {
+            OsText::Utf8(s) => s.into(),
+            OsText::Bytes(b) => OsString::from_vec(b),
+        }
+    }
+}
+
+/// The files that a recursive upload still couldn't upload after retrying them, along with the
+/// command that was uploading them, so that `b2 retry` can run it again for just those files
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RetryFile {
+    /// Where the command was run, since its paths may be relative
+    dir: OsText,
+    /// The arguments of the command, including the global options, without the program name
+    args: Vec<OsText>,
+    /// The failed files, relative to the uploaded directory
+    files: Vec<OsText>,
+}
+
+impl RetryFile {
+    /// Record `files` as having failed in the current command
+    pub fn write(path: &Path, files: Vec<PathBuf>) -> anyhow::Result<()> {
+        let args = match RETRYING.get() {
+            Some(args) => args.clone(),
+            None => std::env::args_os().skip(1).collect(),
+        };
+        Self::new(std::env::current_dir()?, args, files).save(path)
+    }
+
+    fn new(dir: PathBuf, args: Vec<OsString>, files: Vec<PathBuf>) -> Self {
+        Self {
+            dir: dir.into_os_string().into(),
+            args: args.into_iter().map(OsText::from).collect(),
+            files: files
+                .into_iter()
+                .map(|f| f.into_os_string().into())
+                .collect(),
+        }
+    }
+
+    fn save(&self, path: &Path) -> anyhow::Result<()> {
+        fs::write(path, serde_json::to_string_pretty(self)? + "\n")?;
+        Ok(())
+    }
+
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        serde_json::from_str(&fs::read_to_string(path)?)
+            .with_context(|| format!("{} is not a valid retry file", path.display()))
+    }
+
+    /// Move into the directory the upload was run from and return its command line, with the
+    /// global options it was run with and the upload limited to the files that failed.  A
+    /// journal left by an interrupted retry is resumed rather than refused.
+    pub fn into_cli(self) -> anyhow::Result<Cli> {
+        let dir = PathBuf::from(OsString::from(self.dir));
+        std::env::set_current_dir(&dir)
+            .with_context(|| format!("Couldn't change to {}", dir.display()))?;
+
+        let args: Vec<OsString> = self.args.into_iter().map(OsString::from).collect();
+        let mut cli =
+            Cli::try_parse_from(std::iter::once(OsString::from("b2")).chain(args.clone()))?;
+        let _ = RETRYING.set(args);
+        let Command::Upload {
+            ref mut retry_files,
+            ref mut resume,
+            restart,
+            ..
+        } = cli.command
+        else {
+            bail!("The retry file isn't for an upload");
+        };
+        *retry_files = Some(
+            self.files
+                .into_iter()
+                .map(|f| PathBuf::from(OsString::from(f)))
+                .collect(),
+        );
+        *resume = !restart;
+        Ok(cli)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        ffi::{OsStr, OsString},
+        os::unix::ffi::OsStrExt,
+        path::PathBuf,
+        time::Duration,
+    };
+
+    use super::RetryFile;
+    use crate::cli::Command;
+
+    #[test]
+    fn round_trip() {
+        let dir = std::env::current_dir().unwrap();
+        let path = std::env::temp_dir().join(format!("b2-retry-test-{}.json", std::process::id()));
+        let invalid = OsStr::from_bytes(b"caf\xe9");
+        let args: Vec<OsString> = [
+            OsStr::new("--profile"),
+            OsStr::new("work"),
+            OsStr::new("--low-memory"),
+            OsStr::new("--deadline"),
+            OsStr::new("1h"),
+            OsStr::new("upload"),
+            OsStr::new("-r"),
+            OsStr::new("--retries"),
+            OsStr::new("1"),
+            invalid,
+            OsStr::new("bucket"),
+        ]
+        .map(OsString::from)
+        .to_vec();
+        let files = vec![PathBuf::from("a.jpg"), PathBuf::from(invalid).join("b.jpg")];
+        RetryFile::new(dir, args, files.clone())
+            .save(&path)
+            .unwrap();
+
+        let cli = RetryFile::load(&path).unwrap().into_cli().unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(cli.profile.as_deref(), Some("work"));
+        assert!(cli.low_memory);
+        assert_eq!(cli.deadline, Some(Duration::from_secs(60 * 60)));
+        let Command::Upload {
+            file,
+            retries,
+            retry_files,
+            resume,
+            ..
+        } = cli.command
+        else {
+            panic!("not an upload: {:?}", cli.command);
+        };
+        assert_eq!(file, PathBuf::from(invalid));
+        assert_eq!(retries, 1);
+        assert_eq!(retry_files, Some(files));
+        // The journal of an interrupted retry mustn't stop it from being run again
+        assert!(resume);
+    }
+}
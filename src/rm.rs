@@ -0,0 +1,169 @@
+use std::path::PathBuf;
+
+use anyhow::bail;
+use colored::Colorize;
+
+use crate::{
+    api::{self, Action, File},
+    config::Config,
+    confirm, copy,
+    path::RemotePath,
+    retention,
+};
+
+/// Where files deleted from `bucket` are copied to in the bucket `trash`.  Everything deleted at
+/// once shares a prefix, so it can be restored together.
+pub fn trash_dir(trash: String, bucket: &str) -> RemotePath {
+    RemotePath::new(
+        trash,
+        format!(
+            "{}/{}/",
+            chrono::Utc::now().format("%Y-%m-%dT%H-%M-%SZ"),
+            bucket
+        ),
+    )
+}
+
+/// Copy `file`, the latest version of `path`, under `trash` before it is deleted.  Locked files
+/// aren't deleted, so there's no need for a copy of them.
+pub fn copy_to_trash(
+    cfg: &mut Config,
+    file: &File,
+    path: &RemotePath,
+    trash: &RemotePath,
+) -> anyhow::Result<()> {
+    if retention::lock_reason(file).is_none() {
+        copy::copy_version(cfg, file, path, &trash.join(&file.file_name))?;
+    }
+    Ok(())
+}
+
+/// Delete the single version `file`, explaining why if its retention or legal hold prevents it
+pub fn delete(cfg: &mut Config, file: &File) -> anyhow::Result<()> {
+    cfg.delete_file_version(file)
+        .map_err(|e| retention::explain_lock_error(e, file, false))?;
+    println!("{}", format!("Deleted {}", file.file_name).green());
+    Ok(())
+}
+
+/// The versions of the file at `path`, including the markers left by hiding it
+fn versions(cfg: &mut Config, path: &RemotePath) -> anyhow::Result<Vec<File>> {
+    // Listing from the key itself, its versions come first, so the listing stops at the first
+    // other name rather than going through every name that starts with the key
+    let mut listing = api::Listing::versions_from(cfg, path)?;
+    let mut versions = Vec::new();
+    for file in listing.by_ref() {
+        let file = file?;
+        if file.file_name != path.key {
+            break;
+        }
+        // An unfinished large file has to be cancelled rather than deleted, and isn't a version
+        // yet
+        if file.action != Action::Start {
+            versions.push(file);
+        }
+    }
+    listing.update_auth(cfg);
+    Ok(versions)
+}
+
+/// Delete each of `versions` that isn't locked, returning how many were
+fn delete_versions(cfg: &mut Config, versions: &[File]) -> anyhow::Result<usize> {
+    let mut locked = 0;
+    for file in versions {
+        if retention::delete_unless_locked(cfg, file)? {
+            println!(
+                "{}",
+                format!("Deleted {} ({})", file.file_name, file.file_id).green()
+            );
+        } else {
+            locked += 1;
+        }
+    }
+    Ok(locked)
+}
+
+/// Delete every version of the file at `path`, including the markers left by hiding it, so that
+/// nothing of it is left in the bucket
+pub fn delete_all_versions(cfg: &mut Config, path: &RemotePath, yes: bool) -> anyhow::Result<()> {
+    let versions = versions(cfg, path)?;
+    if versions.is_empty() {
+        bail!("{} does not exist", path);
+    }
+
+    if versions.len() > 1 {
+        let prompt = format!("Delete all {} versions of {}?", versions.len(), path);
+        if !confirm::confirm(&prompt, yes)? {
+            bail!("Not deleting anything, pass --yes to delete without asking");
+        }
+    }
+
+    let locked = delete_versions(cfg, &versions)?;
+    if locked > 0 {
+        bail!("{} versions are locked and were not deleted", locked);
+    }
+    Ok(())
+}
+
+/// Delete the files `listed` under `prefix` (every version of each if `all_versions` is set),
+/// after confirming them all at once.  Files that don't exist or are locked are skipped rather
+/// than stopping the rest from being deleted.
+pub fn delete_listed(
+    cfg: &mut Config,
+    prefix: &RemotePath,
+    listed: Vec<PathBuf>,
+    all_versions: bool,
+    yes: bool,
+    trash: Option<&RemotePath>,
+) -> anyhow::Result<()> {
+    let mut skipped = 0;
+    let mut found = Vec::new();
+    for listed in listed {
+        if listed.to_str().is_none() {
+            bail!("{} is not a valid UTF-8 name", listed.display());
+        }
+        let path = prefix.join_path(&listed)?;
+        if path.is_dir() {
+            bail!("{} is not a file", path);
+        }
+        let versions = if all_versions {
+            versions(cfg, &path)?
+        } else {
+            cfg.get_file_fresh(&path)?.into_iter().collect()
+        };
+        if versions.is_empty() {
+            eprintln!("{} {} does not exist", "skipped".yellow(), path);
+            skipped += 1;
+            continue;
+        }
+        println!("{}", path);
+        found.push((path, versions));
+    }
+
+    if !found.is_empty() {
+        let prompt = if all_versions {
+            let count: usize = found.iter().map(|(_, v)| v.len()).sum();
+            format!(
+                "Delete all {} versions of these {} files?",
+                count,
+                found.len()
+            )
+        } else {
+            format!("Delete these {} files?", found.len())
+        };
+        if !confirm::confirm(&prompt, yes)? {
+            bail!("Not deleting anything, pass --yes to delete without asking");
+        }
+    }
+
+    for (path, versions) in &found {
+        if let Some(trash) = trash {
+            copy_to_trash(cfg, &versions[0], path, trash)?;
+        }
+        skipped += delete_versions(cfg, versions)?;
+    }
+    if skipped > 0 {
+        bail!("{} files or versions were not deleted", skipped);
+    }
+    Ok(())
+}
@@ -0,0 +1,475 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    fs,
+    path::Path,
+};
+
+use anyhow::{bail, Context};
+use chrono::{DateTime, Datelike, Duration, Local, Timelike, Utc};
+use colored::Colorize;
+use humanize_bytes::humanize_bytes_decimal;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use walkdir::WalkDir;
+
+use crate::{
+    api::File,
+    config::Config,
+    confirm, http,
+    path::RemotePath,
+    retention,
+    transfer::{self, ChecksumAlgorithm, TransferJob},
+};
+
+/// Where the contents of files are stored within a repository, named by their sha1
+pub const BLOBS_DIR: &str = "blobs/";
+
+/// Where the manifest of each snapshot is stored within a repository
+pub const SNAPSHOTS_DIR: &str = "snapshots/";
+
+/// Blobs uploaded more recently than this are never collected, since they might belong to a
+/// snapshot that is still being created and so isn't in any manifest yet
+const GC_GRACE: Duration = Duration::hours(1);
+
+/// The list of files in a snapshot
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Manifest {
+    pub created: DateTime<Utc>,
+    /// The local directory that the snapshot was taken of
+    pub source: String,
+    /// Path relative to the source : Entry
+    pub files: BTreeMap<String, Entry>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Entry {
+    /// The sha1 of the contents, which is also the name of the blob
+    pub sha1: String,
+    pub size: u64,
+    /// Milliseconds since the epoch, like `src_last_modified_millis`
+    pub modified: i64,
+}
+
+/// Take a snapshot of `dir` into the repository at `repo`, only uploading files whose contents
+/// aren't already stored
+pub fn create(cfg: &mut Config, dir: &Path, repo: &RemotePath) -> anyhow::Result<()> {
+    if !dir.is_dir() {
+        bail!("{} is not a directory", dir.display());
+    }
+
+    let mut paths = Vec::new();
+    for entry in WalkDir::new(dir) {
+        let entry = entry?;
+        if entry.file_type().is_file() {
+            paths.push(entry.into_path());
+        }
+    }
+
+    println!("Hashing {} files", paths.len());
+    let hashed = paths
+        .par_iter()
+        .map(|path| {
+            let meta = fs::metadata(path)?;
+            let modified: DateTime<Utc> = meta.modified()?.into();
+            let sha1 = transfer::hash_file_all(path, &[ChecksumAlgorithm::Sha1])?
+                .remove(0)
+                .1;
+            Ok(Entry {
+                sha1,
+                size: meta.len(),
+                modified: modified.timestamp_millis(),
+            })
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    let blobs = repo.join(BLOBS_DIR);
+    let mut stored: HashSet<String> = cfg
+        .list_files(&blobs)?
+        .into_iter()
+        .filter_map(|f| f.file_name.strip_prefix(&blobs.key).map(String::from))
+        .collect();
+
+    let mut files = BTreeMap::new();
+    let (mut uploaded, mut reused) = (0, 0);
+    for (path, entry) in paths.iter().zip(hashed) {
+        if stored.insert(entry.sha1.clone()) {
+            let mut job = TransferJob::upload(path.clone(), blobs.join(&entry.sha1));
+            job.size = Some(entry.size);
+            job.hashes = vec![(ChecksumAlgorithm::Sha1, entry.sha1.clone())];
+            println!("{}", path.display());
+            job.run(cfg)?;
+            uploaded += entry.size;
+        } else {
+            reused += entry.size;
+        }
+
+        let name = path
+            .strip_prefix(dir)?
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        files.insert(name, entry);
+    }
+
+    let manifest = Manifest {
+        created: Utc::now(),
+        source: dir.display().to_string(),
+        files,
+    };
+    // The manifest is written last, so that a snapshot only exists once all of its blobs do
+    let name = format!("{}.json", manifest.created.format("%Y-%m-%dT%H-%M-%SZ"));
+    transfer::upload_bytes(
+        cfg,
+        &repo.join(SNAPSHOTS_DIR).join(&name),
+        &serde_json::to_vec(&manifest)?,
+        "application/json",
+    )?;
+
+    println!(
+        "{}",
+        format!(
+            "Created snapshot {} of {} files, uploaded {} and reused {}",
+            name,
+            manifest.files.len(),
+            humanize_bytes_decimal!(uploaded),
+            humanize_bytes_decimal!(reused)
+        )
+        .green()
+    );
+    Ok(())
+}
+
+/// The manifest file of each snapshot in the repository at `repo`, oldest first
+pub fn list(cfg: &mut Config, repo: &RemotePath) -> anyhow::Result<Vec<File>> {
+    let mut files = cfg.list_files(&repo.join(SNAPSHOTS_DIR))?;
+    files.retain(|f| f.file_name.ends_with(".json"));
+    Ok(files)
+}
+
+/// Download and parse the manifest of `snapshot`, which is in the same bucket as `repo`
+pub fn read(cfg: &mut Config, repo: &RemotePath, snapshot: &File) -> anyhow::Result<Manifest> {
+    let path = RemotePath::new(&repo.bucket, &snapshot.file_name);
+    let url = path.url(&cfg.download_url);
+    let res = cfg.send_request_res(|cfg| {
+        Ok(http::client()
+            .get(&url)
+            .header("Authorization", &cfg.auth_token)
+            .send()?)
+    })?;
+    res.json()
+        .with_context(|| format!("{} is not a valid snapshot manifest", path))
+}
+
+/// Delete the blobs in the repository at `repo` that no snapshot refers to
+///
+/// This shouldn't be run while a snapshot is being created, which might reuse a blob that isn't
+/// referenced yet.
+pub fn gc(cfg: &mut Config, repo: &RemotePath, yes: bool) -> anyhow::Result<()> {
+    let mut referenced = HashSet::new();
+    for snapshot in list(cfg, repo)? {
+        let manifest = read(cfg, repo, &snapshot)?;
+        referenced.extend(manifest.files.into_values().map(|e| e.sha1));
+    }
+
+    let blobs = repo.join(BLOBS_DIR);
+    let cutoff = Utc::now() - GC_GRACE;
+    let unreferenced: Vec<_> = cfg
+        .list_files(&blobs)?
+        .into_iter()
+        .filter(|f| {
+            f.file_name
+                .strip_prefix(&blobs.key)
+                .is_some_and(|sha| !referenced.contains(sha))
+                && f.upload_timestamp < cutoff
+        })
+        .collect();
+
+    if unreferenced.is_empty() {
+        println!("No unreferenced blobs");
+        return Ok(());
+    }
+
+    let total: u64 = unreferenced.iter().map(|f| f.content_length).sum();
+    let prompt = format!(
+        "Delete {} unreferenced blobs ({})?",
+        unreferenced.len(),
+        humanize_bytes_decimal!(total)
+    );
+    if !confirm::confirm(&prompt, yes)? {
+        bail!("Not deleting anything, pass --yes to delete without asking");
+    }
+
+    let mut locked = 0;
+    for file in &unreferenced {
+        if retention::delete_unless_locked(cfg, file)? {
+            println!("{}", format!("Deleted {}", file.file_name).green());
+        } else {
+            locked += 1;
+        }
+    }
+    if locked > 0 {
+        eprintln!(
+            "{}",
+            format!("Skipped {} blobs that are locked", locked).yellow()
+        );
+    }
+    Ok(())
+}
+
+/// The period of time that a snapshot falls into, for one of the rules of a [`Policy`]
+type Period = fn(&DateTime<Local>) -> (i32, u32);
+
+/// Which snapshots [`prune`] keeps, like `restic forget`: the newest snapshot in each of the
+/// last `daily` days that have snapshots is kept, and so on for the other periods
+#[derive(Debug, Default)]
+pub struct Policy {
+    /// Keep this many of the newest snapshots
+    pub last: usize,
+    pub hourly: usize,
+    pub daily: usize,
+    pub weekly: usize,
+    pub monthly: usize,
+    pub yearly: usize,
+}
+
+impl Policy {
+    /// Whether to keep each of the snapshots created at `created`, which is newest first
+    fn keep(&self, created: &[DateTime<Utc>]) -> Vec<bool> {
+        let mut keep = vec![false; created.len()];
+        keep.iter_mut().take(self.last).for_each(|k| *k = true);
+
+        // The period that a snapshot falls into for each rule, in local time since that's what
+        // a day means to whoever set the policy
+        let rules: [(usize, Period); 5] = [
+            (self.hourly, |t| (t.num_days_from_ce(), t.hour())),
+            (self.daily, |t| (t.year(), t.ordinal())),
+            (self.weekly, |t| (t.iso_week().year(), t.iso_week().week())),
+            (self.monthly, |t| (t.year(), t.month())),
+            (self.yearly, |t| (t.year(), 0)),
+        ];
+        for (count, period) in rules {
+            let mut last = None;
+            let mut left = count;
+            for (i, created) in created.iter().enumerate() {
+                if left == 0 {
+                    break;
+                }
+                let p = period(&created.with_timezone(&Local));
+                if last != Some(p) {
+                    keep[i] = true;
+                    last = Some(p);
+                    left -= 1;
+                }
+            }
+        }
+
+        keep
+    }
+
+    fn is_empty(&self) -> bool {
+        self.last + self.hourly + self.daily + self.weekly + self.monthly + self.yearly == 0
+    }
+}
+
+/// Delete the snapshots in the repository at `repo` that `policy` doesn't keep, along with the
+/// blobs that only they refer to
+///
+/// Like [`gc`], this shouldn't be run while a snapshot is being created.
+pub fn prune(
+    cfg: &mut Config,
+    repo: &RemotePath,
+    policy: &Policy,
+    dry_run: bool,
+    yes: bool,
+) -> anyhow::Result<()> {
+    if policy.is_empty() {
+        bail!("No --keep-* options were given, which would delete every snapshot");
+    }
+
+    let mut snapshots = Vec::new();
+    for file in list(cfg, repo)? {
+        let manifest = read(cfg, repo, &file)?;
+        snapshots.push((file, manifest));
+    }
+    snapshots.sort_by_key(|(_, m)| std::cmp::Reverse(m.created));
+
+    let created: Vec<_> = snapshots.iter().map(|(_, m)| m.created).collect();
+    // Snapshots that are locked can't be removed, so they're kept along with their blobs
+    let (kept, removed): (Vec<_>, Vec<_>) = snapshots
+        .into_iter()
+        .zip(policy.keep(&created))
+        .partition(|((file, _), keep)| *keep || retention::lock_reason(file).is_some());
+
+    for ((file, _), keep) in &kept {
+        match retention::lock_reason(file) {
+            Some(reason) if !keep => println!("{} {}", "locked".yellow(), reason),
+            _ => println!("{} {}", "keep  ".green(), file.file_name),
+        }
+    }
+    for ((file, _), _) in &removed {
+        println!("{} {}", "remove".red(), file.file_name);
+    }
+
+    // Blobs are only deleted when no kept snapshot uses them
+    let referenced: HashSet<_> = kept
+        .iter()
+        .flat_map(|((_, m), _)| m.files.values().map(|e| e.sha1.as_str()))
+        .collect();
+    let exclusive: HashSet<_> = removed
+        .iter()
+        .flat_map(|((_, m), _)| m.files.values().map(|e| e.sha1.as_str()))
+        .filter(|sha| !referenced.contains(sha))
+        .collect();
+
+    let blobs = repo.join(BLOBS_DIR);
+    let unused: Vec<_> = if exclusive.is_empty() {
+        Vec::new()
+    } else {
+        cfg.list_files(&blobs)?
+            .into_iter()
+            .filter(|f| {
+                f.file_name
+                    .strip_prefix(&blobs.key)
+                    .is_some_and(|sha| exclusive.contains(sha))
+            })
+            .collect()
+    };
+    let total: u64 = unused.iter().map(|f| f.content_length).sum();
+
+    if removed.is_empty() {
+        println!("No snapshots to remove");
+        return Ok(());
+    }
+    if dry_run {
+        println!(
+            "Would remove {} snapshots and {} blobs ({})",
+            removed.len(),
+            unused.len(),
+            humanize_bytes_decimal!(total)
+        );
+        return Ok(());
+    }
+
+    let prompt = format!(
+        "Remove {} snapshots and {} blobs ({})?",
+        removed.len(),
+        unused.len(),
+        humanize_bytes_decimal!(total)
+    );
+    if !confirm::confirm(&prompt, yes)? {
+        bail!("Not deleting anything, pass --yes to delete without asking");
+    }
+
+    // Manifests go first, so that a snapshot never refers to blobs that are gone
+    let mut locked = 0;
+    for ((file, _), _) in &removed {
+        if retention::delete_unless_locked(cfg, file)? {
+            println!("{}", format!("Deleted {}", file.file_name).green());
+        } else {
+            locked += 1;
+        }
+    }
+    if locked > 0 {
+        bail!(
+            "{} snapshots are locked, so no blobs were deleted, `b2 gc` will delete the \
+             ones that are unused",
+            locked
+        );
+    }
+
+    let mut deleted = 0;
+    let mut freed = 0;
+    for file in &unused {
+        if retention::delete_unless_locked(cfg, file)? {
+            deleted += 1;
+            freed += file.content_length;
+        }
+    }
+    println!(
+        "{}",
+        format!(
+            "Deleted {} blobs ({})",
+            deleted,
+            humanize_bytes_decimal!(freed)
+        )
+        .green()
+    );
+    if deleted < unused.len() {
+        eprintln!(
+            "{}",
+            format!("Skipped {} blobs that are locked", unused.len() - deleted).yellow()
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{DateTime, Local, TimeZone, Utc};
+
+    use super::Policy;
+
+    /// A snapshot taken around midday on a day in March 2024, in local time since that's what
+    /// the periods are of
+    fn at(day: u32, minute: u32) -> DateTime<Utc> {
+        Local
+            .with_ymd_and_hms(2024, 3, day, 12, minute, 0)
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    /// The indices of the snapshots that are kept
+    fn kept(policy: &Policy, created: &[DateTime<Utc>]) -> Vec<usize> {
+        policy
+            .keep(created)
+            .into_iter()
+            .enumerate()
+            .filter_map(|(i, keep)| keep.then_some(i))
+            .collect()
+    }
+
+    #[test]
+    fn last_daily_and_weekly_overlap() {
+        // One a day from Thursday the 14th back to the 1st
+        let created: Vec<_> = (1..=14).rev().map(|day| at(day, 0)).collect();
+        let policy = Policy {
+            last: 2,
+            daily: 3,
+            weekly: 2,
+            ..Default::default()
+        };
+        // The days overlap with the last two rather than going further back, and the weeks are
+        // the newest of this week (the 14th) and of the week before (Sunday the 10th)
+        assert_eq!(kept(&policy, &created), [0, 1, 2, 4]);
+    }
+
+    #[test]
+    fn several_in_one_period() {
+        let created = [at(14, 30), at(14, 15), at(14, 0), at(13, 30), at(13, 0)];
+        let policy = Policy {
+            daily: 2,
+            ..Default::default()
+        };
+        assert_eq!(kept(&policy, &created), [0, 3]);
+    }
+
+    #[test]
+    fn more_than_there_are() {
+        let created = [at(14, 0), at(13, 0), at(12, 0)];
+        let policy = Policy {
+            last: 10,
+            daily: 10,
+            ..Default::default()
+        };
+        assert_eq!(kept(&policy, &created), [0, 1, 2]);
+
+        let policy = Policy {
+            monthly: 5,
+            yearly: 5,
+            ..Default::default()
+        };
+        assert_eq!(kept(&policy, &created), [0]);
+    }
+}
@@ -0,0 +1,77 @@
+use crate::cli::OutputFormat;
+
+/// Rows of data that can be printed in one of the machine-readable [`OutputFormat`]s
+pub struct Table {
+    headers: Vec<&'static str>,
+    rows: Vec<Vec<String>>,
+}
+
+impl Table {
+    pub fn new(headers: Vec<&'static str>) -> Self {
+        Self {
+            headers,
+            rows: Vec::new(),
+        }
+    }
+
+    pub fn row(&mut self, row: Vec<String>) {
+        debug_assert_eq!(row.len(), self.headers.len());
+        self.rows.push(row);
+    }
+
+    /// Print the table in `format`, which should not be [`OutputFormat::Table`] since each
+    /// command has its own human-readable output
+    pub fn print(&self, format: OutputFormat) -> anyhow::Result<()> {
+        match format {
+            OutputFormat::Table => unreachable!("tables are printed by each command"),
+            OutputFormat::Json => {
+                let rows: Vec<serde_json::Map<_, _>> = self
+                    .rows
+                    .iter()
+                    .map(|row| {
+                        self.headers
+                            .iter()
+                            .map(|h| h.to_string())
+                            .zip(row.iter().map(|c| c.clone().into()))
+                            .collect()
+                    })
+                    .collect();
+                println!("{}", serde_json::to_string_pretty(&rows)?);
+            }
+            OutputFormat::Csv => {
+                println!("{}", self.headers.join(","));
+                for row in &self.rows {
+                    let row: Vec<_> = row.iter().map(|c| csv_escape(c)).collect();
+                    println!("{}", row.join(","));
+                }
+            }
+            OutputFormat::Tsv => {
+                println!("{}", self.headers.join("\t"));
+                for row in &self.rows {
+                    let row: Vec<_> = row.iter().map(|c| tsv_escape(c)).collect();
+                    println!("{}", row.join("\t"));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Quote a CSV field if it contains anything that would break the row
+fn csv_escape(cell: &str) -> String {
+    if cell.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", cell.replace('"', "\"\""))
+    } else {
+        cell.to_string()
+    }
+}
+
+/// TSV has no quoting, so escape the characters that would break the row the same way as
+/// `\t` and `\n` are written in most tools
+fn tsv_escape(cell: &str) -> String {
+    cell.replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+}
@@ -0,0 +1,55 @@
+use anyhow::bail;
+use colored::Colorize;
+
+use crate::{api::File, config::Config};
+
+/// The file info key holding the comma-separated tags of a file
+const TAGS_KEY: &str = "b2-tags";
+
+/// The tags of `file`
+pub fn get(file: &File) -> Vec<&str> {
+    file.file_info[TAGS_KEY]
+        .as_str()
+        .map(|tags| tags.split(',').filter(|t| !t.is_empty()).collect())
+        .unwrap_or_default()
+}
+
+/// Replace the tags of `file` by copying it over itself with the new file info, which creates a
+/// new version of the file
+pub fn set(cfg: &mut Config, file: &File, tags: &[&str]) -> anyhow::Result<()> {
+    if let Some(tag) = tags.iter().find(|t| t.is_empty() || t.contains(',')) {
+        bail!(
+            "Invalid tag `{}`, tags may not be empty or contain `,`",
+            tag
+        );
+    }
+
+    let mut info = file.file_info.as_object().cloned().unwrap_or_default();
+    if tags.is_empty() {
+        info.remove(TAGS_KEY);
+    } else {
+        info.insert(TAGS_KEY.into(), tags.join(",").into());
+    }
+
+    let _: File = cfg.send_request_de(|cfg| {
+        Ok(cfg
+            .post("b2_copy_file")?
+            .json(&serde_json::json!({
+                "sourceFileId": file.file_id,
+                "fileName": file.file_name,
+                "metadataDirective": "REPLACE",
+                "contentType": file.content_type,
+                "fileInfo": info,
+            }))
+            .send()?)
+    })?;
+    cfg.forget_lists(Some(&file.bucket_id));
+
+    Ok(())
+}
+
+pub fn print(file: &File) {
+    for tag in get(file) {
+        println!("{}", tag.blue());
+    }
+}
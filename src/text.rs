@@ -0,0 +1,107 @@
+use std::fmt::Write;
+
+/// A text encoding that `cat` can transcode from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Encoding {
+    Utf8,
+    Utf16le,
+    Utf16be,
+    /// ISO-8859-1, where every byte is the code point of the same value
+    Latin1,
+}
+
+/// Decode `bytes` as text in `encoding`, or if it isn't given, in whichever encoding its BOM
+/// says or it looks like
+///
+/// Returns `None` if the encoding wasn't given and the bytes don't look like text.
+pub fn decode(bytes: &[u8], encoding: Option<Encoding>) -> Option<String> {
+    let Some(encoding) = encoding else {
+        return detect(bytes);
+    };
+
+    Some(match encoding {
+        Encoding::Utf8 => {
+            String::from_utf8_lossy(bytes.strip_prefix(b"\xef\xbb\xbf").unwrap_or(bytes))
+                .into_owned()
+        }
+        Encoding::Utf16le => utf16(bytes.strip_prefix(b"\xff\xfe").unwrap_or(bytes), false),
+        Encoding::Utf16be => utf16(bytes.strip_prefix(b"\xfe\xff").unwrap_or(bytes), true),
+        Encoding::Latin1 => bytes.iter().map(|&b| b as char).collect(),
+    })
+}
+
+fn detect(bytes: &[u8]) -> Option<String> {
+    if let Some(rest) = bytes.strip_prefix(b"\xef\xbb\xbf") {
+        return std::str::from_utf8(rest).ok().map(String::from);
+    }
+    if let Some(rest) = bytes.strip_prefix(b"\xff\xfe") {
+        return Some(utf16(rest, false));
+    }
+    if let Some(rest) = bytes.strip_prefix(b"\xfe\xff") {
+        return Some(utf16(rest, true));
+    }
+    if let Ok(s) = std::str::from_utf8(bytes) {
+        return Some(s.to_string());
+    }
+
+    // UTF-16 without a BOM: mostly ASCII text has a zero in every other byte
+    if bytes.len() >= 2 && bytes.len().is_multiple_of(2) {
+        let zeros = |start: usize| {
+            bytes
+                .iter()
+                .skip(start)
+                .step_by(2)
+                .filter(|&&b| b == 0)
+                .count()
+        };
+        let half = bytes.len() / 2;
+        if zeros(1) * 10 >= half * 9 && zeros(0) == 0 {
+            return Some(utf16(bytes, false));
+        }
+        if zeros(0) * 10 >= half * 9 && zeros(1) == 0 {
+            return Some(utf16(bytes, true));
+        }
+    }
+
+    None
+}
+
+fn utf16(bytes: &[u8], big_endian: bool) -> String {
+    let units: Vec<u16> = bytes
+        .chunks_exact(2)
+        .map(|c| {
+            if big_endian {
+                u16::from_be_bytes([c[0], c[1]])
+            } else {
+                u16::from_le_bytes([c[0], c[1]])
+            }
+        })
+        .collect();
+    String::from_utf16_lossy(&units)
+}
+
+/// Format `bytes` like `xxd`: the offset, 16 bytes in hex, and then those bytes as ASCII
+pub fn hex_dump(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len() * 4 + 16);
+    for (i, line) in bytes.chunks(16).enumerate() {
+        let _ = write!(out, "{:08x}: ", i * 16);
+        for (j, b) in line.iter().enumerate() {
+            let _ = write!(out, "{:02x}", b);
+            if j % 2 == 1 {
+                out.push(' ');
+            }
+        }
+        // Pad short lines so that the ASCII column lines up
+        let width = line.len() * 2 + line.len() / 2;
+        out.extend(std::iter::repeat_n(' ', 40 - width + 1));
+        out.extend(line.iter().map(|&b| {
+            if b.is_ascii_graphic() || b == b' ' {
+                b as char
+            } else {
+                '.'
+            }
+        }));
+        out.push('\n');
+    }
+    out
+}
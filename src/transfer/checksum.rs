@@ -0,0 +1,163 @@
+use std::{fs, io::Read, path::Path};
+
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+
+use crate::cancel;
+
+/// A checksum algorithm that can be stored alongside a file and verified when downloading it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ChecksumAlgorithm {
+    /// The sha1 that b2 always stores for a file
+    Sha1,
+    Sha256,
+    Crc32c,
+}
+
+impl ChecksumAlgorithm {
+    /// The key used for this checksum in the file info
+    pub fn info_key(self) -> &'static str {
+        match self {
+            ChecksumAlgorithm::Sha1 => "large_file_sha1",
+            ChecksumAlgorithm::Sha256 => "sha256",
+            ChecksumAlgorithm::Crc32c => "crc32c",
+        }
+    }
+
+    /// A new hasher for this algorithm
+    pub fn hasher(self) -> Box<dyn Checksum> {
+        match self {
+            ChecksumAlgorithm::Sha1 => Box::new(Sha1::new()),
+            ChecksumAlgorithm::Sha256 => Box::new(Sha256::new()),
+            ChecksumAlgorithm::Crc32c => Box::new(Crc32c(0)),
+        }
+    }
+}
+
+/// An incremental checksum, so that the implementation behind each algorithm can be swapped
+/// without touching the code that hashes things
+pub trait Checksum: Send {
+    fn update(&mut self, data: &[u8]);
+
+    /// The hex-encoded checksum of everything so far, starting again afterwards
+    fn finish_hex(&mut self) -> String;
+}
+
+/// The `sha1` crate picks the fastest implementation the CPU supports at runtime, using the SHA
+/// extensions where they are available
+impl Checksum for Sha1 {
+    fn update(&mut self, data: &[u8]) {
+        Digest::update(self, data);
+    }
+
+    fn finish_hex(&mut self) -> String {
+        format!("{:02x}", self.finalize_reset())
+    }
+}
+
+impl Checksum for Sha256 {
+    fn update(&mut self, data: &[u8]) {
+        Digest::update(self, data);
+    }
+
+    fn finish_hex(&mut self) -> String {
+        format!("{:02x}", self.finalize_reset())
+    }
+}
+
+struct Crc32c(u32);
+
+impl Checksum for Crc32c {
+    fn update(&mut self, data: &[u8]) {
+        self.0 = crc32c::crc32c_append(self.0, data);
+    }
+
+    fn finish_hex(&mut self) -> String {
+        format!("{:08x}", std::mem::take(&mut self.0))
+    }
+}
+
+/// The hex-encoded sha1 of `data`
+pub fn sha1_hex(data: &[u8]) -> String {
+    let mut sha = Sha1::new();
+    Checksum::update(&mut sha, data);
+    sha.finish_hex()
+}
+
+/// Computes several checksums at once over the same data
+pub struct MultiHasher {
+    hashers: Vec<(ChecksumAlgorithm, Box<dyn Checksum>)>,
+}
+
+impl MultiHasher {
+    pub fn new(algorithms: &[ChecksumAlgorithm]) -> Self {
+        // Always in the same order, whatever order they were asked for in
+        let hashers = [
+            ChecksumAlgorithm::Sha1,
+            ChecksumAlgorithm::Sha256,
+            ChecksumAlgorithm::Crc32c,
+        ]
+        .into_iter()
+        .filter(|a| algorithms.contains(a))
+        .map(|a| (a, a.hasher()))
+        .collect();
+        Self { hashers }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        for (_, hasher) in &mut self.hashers {
+            hasher.update(data);
+        }
+    }
+
+    /// The hex-encoded checksums for every algorithm that was requested
+    pub fn finish(self) -> Vec<(ChecksumAlgorithm, String)> {
+        self.hashers
+            .into_iter()
+            .map(|(a, mut hasher)| (a, hasher.finish_hex()))
+            .collect()
+    }
+}
+
+/// Compute the checksums of a local file, skipping sha1 since b2 already handles it
+pub fn hash_file(
+    file: &Path,
+    algorithms: &[ChecksumAlgorithm],
+) -> anyhow::Result<Vec<(ChecksumAlgorithm, String)>> {
+    let algorithms: Vec<_> = algorithms
+        .iter()
+        .copied()
+        .filter(|a| *a != ChecksumAlgorithm::Sha1)
+        .collect();
+    hash_file_all(file, &algorithms)
+}
+
+/// Compute the checksums of a local file for all of `algorithms`, reading it 1 MiB at a time so
+/// that hashing several files at once has a bounded memory use
+pub fn hash_file_all(
+    file: &Path,
+    algorithms: &[ChecksumAlgorithm],
+) -> anyhow::Result<Vec<(ChecksumAlgorithm, String)>> {
+    if algorithms.is_empty() {
+        return Ok(Vec::new());
+    }
+    hash_reader(fs::File::open(file)?, algorithms)
+}
+
+/// Compute the checksums of everything left in `reader` for all of `algorithms`
+pub fn hash_reader(
+    mut reader: impl Read,
+    algorithms: &[ChecksumAlgorithm],
+) -> anyhow::Result<Vec<(ChecksumAlgorithm, String)>> {
+    let mut hasher = MultiHasher::new(algorithms);
+    let mut buf = vec![0u8; 1024 * 1024];
+    loop {
+        cancel::check()?;
+        match reader.read(&mut buf)? {
+            0 => break,
+            n => hasher.update(&buf[..n]),
+        }
+    }
+
+    Ok(hasher.finish())
+}
@@ -0,0 +1,195 @@
+use std::{
+    fs,
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+};
+
+use ::reqwest::header::HeaderMap;
+use flate2::{write::GzDecoder, write::GzEncoder, Compression as Level};
+
+/// The file info key holding the codec that a file was compressed with, which b2 also returns as
+/// the `Content-Encoding` of the file so that browsers decompress it too
+pub const INFO_KEY: &str = "b2-content-encoding";
+
+/// Extensions of formats that are already compressed, so gain nothing from compressing again
+const COMPRESSED_EXTENSIONS: &[&str] = &[
+    "7z", "aac", "apk", "avif", "br", "bz2", "deb", "docx", "epub", "flac", "gif", "gz", "heic",
+    "jar", "jpeg", "jpg", "lz", "lz4", "m4a", "m4v", "mkv", "mov", "mp3", "mp4", "ogg", "opus",
+    "png", "pptx", "rar", "rpm", "tgz", "webm", "webp", "woff", "woff2", "xlsx", "xz", "zip",
+    "zst",
+];
+
+/// The magic bytes at the start of formats that are already compressed, for files without a
+/// telling extension
+const COMPRESSED_MAGIC: &[&[u8]] = &[
+    b"\x1f\x8b",           // gzip
+    b"PK\x03\x04",         // zip (and docx, jar, ...)
+    b"\x28\xb5\x2f\xfd",   // zstd
+    b"BZh",                // bzip2
+    b"\xfd7zXZ\x00",       // xz
+    b"7z\xbc\xaf\x27\x1c", // 7z
+    b"Rar!\x1a\x07",       // rar
+    b"\xff\xd8\xff",       // jpeg
+    b"\x89PNG\r\n\x1a\n",  // png
+    b"GIF8",               // gif
+    b"OggS",               // ogg
+    b"fLaC",               // flac
+    b"\x1a\x45\xdf\xa3",   // mkv/webm
+];
+
+/// Whether, and how, files are compressed before they are uploaded
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum Compression {
+    #[default]
+    None,
+    Gzip,
+    /// Gzip, except for files that are already compressed, going by their extension or contents
+    Auto,
+}
+
+/// A codec that a file was compressed with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Codec {
+    Gzip,
+}
+
+impl Codec {
+    /// The name of the codec as used in `Content-Encoding`
+    pub fn name(self) -> &'static str {
+        match self {
+            Codec::Gzip => "gzip",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "gzip" => Some(Codec::Gzip),
+            _ => None,
+        }
+    }
+
+    /// The codec of a downloaded file, from the `Content-Encoding` that b2 serves it with, or
+    /// from the file info for files uploaded by older versions. Anything that can't be
+    /// decompressed is `None`, so is kept as it is.
+    pub fn of_response(headers: &HeaderMap) -> Option<Self> {
+        [
+            "Content-Encoding".to_string(),
+            format!("X-Bz-Info-{}", INFO_KEY),
+        ]
+        .iter()
+        .filter_map(|name| headers.get(name)?.to_str().ok())
+        .find(|h| *h != "none")
+        .and_then(Codec::from_name)
+    }
+}
+
+impl Compression {
+    /// The codec to compress `file` with, if any
+    pub fn codec(self, file: &Path) -> io::Result<Option<Codec>> {
+        match self {
+            Compression::None => Ok(None),
+            Compression::Gzip => Ok(Some(Codec::Gzip)),
+            Compression::Auto if is_compressed(file)? => Ok(None),
+            Compression::Auto => Ok(Some(Codec::Gzip)),
+        }
+    }
+}
+
+/// Whether `file` is already compressed, going by its extension and then its first few bytes
+fn is_compressed(file: &Path) -> io::Result<bool> {
+    let ext = file.extension().map(|e| e.to_string_lossy().to_lowercase());
+    if ext.is_some_and(|e| COMPRESSED_EXTENSIONS.contains(&e.as_str())) {
+        return Ok(true);
+    }
+
+    let mut magic = [0u8; 16];
+    let mut n = 0;
+    let mut f = fs::File::open(file)?;
+    while n < magic.len() {
+        match f.read(&mut magic[n..])? {
+            0 => break,
+            read => n += read,
+        }
+    }
+    let magic = &magic[..n];
+
+    // mp4/mov/heic have `ftyp` after the size of the first box
+    Ok(COMPRESSED_MAGIC.iter().any(|m| magic.starts_with(m))
+        || magic.get(4..8) == Some(b"ftyp")
+        || (magic.starts_with(b"RIFF") && magic.get(8..12) == Some(b"WEBP")))
+}
+
+/// A compressed copy of a file in the temporary directory, which is removed when dropped
+pub struct Compressed {
+    pub path: PathBuf,
+}
+
+impl Drop for Compressed {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Compress `file` with `codec` into a temporary file, keeping its modification time
+pub fn compress(file: &Path, codec: Codec) -> io::Result<Compressed> {
+    let out = Compressed {
+        path: std::env::temp_dir().join(format!(
+            "b2-{}-{}.{}",
+            std::process::id(),
+            chrono::Utc::now().timestamp_nanos_opt().unwrap_or_default(),
+            codec.name()
+        )),
+    };
+
+    let mut src = fs::File::open(file)?;
+    let dst = fs::File::create(&out.path)?;
+    let dst = match codec {
+        Codec::Gzip => {
+            let mut enc = GzEncoder::new(dst, Level::default());
+            io::copy(&mut src, &mut enc)?;
+            enc.finish()?
+        }
+    };
+    dst.set_modified(fs::metadata(file)?.modified()?)?;
+
+    Ok(out)
+}
+
+/// Wraps a [`Write`], decompressing everything written through it if the file was compressed
+pub enum Decoder<W: Write> {
+    Plain(W),
+    Gzip(GzDecoder<W>),
+}
+
+impl<W: Write> Decoder<W> {
+    pub fn new(inner: W, codec: Option<Codec>) -> Self {
+        match codec {
+            None => Decoder::Plain(inner),
+            Some(Codec::Gzip) => Decoder::Gzip(GzDecoder::new(inner)),
+        }
+    }
+
+    /// Write out anything that is left and return the inner writer
+    pub fn finish(self) -> io::Result<W> {
+        match self {
+            Decoder::Plain(w) => Ok(w),
+            Decoder::Gzip(d) => d.finish(),
+        }
+    }
+}
+
+impl<W: Write> Write for Decoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Decoder::Plain(w) => w.write(buf),
+            Decoder::Gzip(d) => d.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Decoder::Plain(w) => w.flush(),
+            Decoder::Gzip(d) => d.flush(),
+        }
+    }
+}
@@ -0,0 +1,116 @@
+use std::{fs, os::unix::fs::FileExt};
+
+use rayon::prelude::*;
+
+use super::{checksum, sparse, ChecksumAlgorithm};
+use crate::{config::Config, path::RemotePath};
+
+/// The file info key holding the size of each part of a large file
+pub(super) const PART_SIZE_KEY: &str = "part_size";
+
+/// The file info key holding the comma-separated sha1 of each part of a large file
+pub(super) const PART_SHA1S_KEY: &str = "part_sha1s";
+
+/// The longest list of part sha1s that is stored, since b2 limits the total size of the file
+/// info to 7000 bytes (this is about 100 parts)
+pub(super) const MAX_PART_SHA1S_LEN: usize = 4000;
+
+/// How much of a part is read at a time while hashing it
+const HASH_CHUNK_SIZE: u64 = 1024 * 1024;
+
+/// Compute the sha1 of each `part_size` part of the first `len` bytes of `file`.  The parts are
+/// hashed in parallel, each read a chunk at a time so that memory use doesn't grow with the
+/// part size.
+pub(super) fn part_sha1s(
+    file: &fs::File,
+    len: u64,
+    part_size: u64,
+) -> std::io::Result<Vec<String>> {
+    (0..len.div_ceil(part_size))
+        .into_par_iter()
+        .map(|part| {
+            let start = part * part_size;
+            let end = std::cmp::min(start + part_size, len);
+            let mut sha = ChecksumAlgorithm::Sha1.hasher();
+            let mut buf = vec![0u8; std::cmp::min(HASH_CHUNK_SIZE, end - start) as usize];
+            let mut offset = start;
+            while offset < end {
+                let n = read_part(file, &mut buf, offset, end)?;
+                sha.update(&buf[..n]);
+                offset += n as u64;
+            }
+            Ok(sha.finish_hex())
+        })
+        .collect()
+}
+
+/// The hex-encoded sha1 of `part`
+pub(super) fn sha1(part: &[u8]) -> String {
+    checksum::sha1_hex(part)
+}
+
+/// Read the part starting at `offset` into `buf`, returning its length
+pub(super) fn read_part(
+    file: &fs::File,
+    buf: &mut [u8],
+    offset: u64,
+    len: u64,
+) -> std::io::Result<usize> {
+    let n = std::cmp::min(buf.len() as u64, len - offset) as usize;
+    // Holes read as zeros anyway, so there's no need to touch the disk for them
+    if sparse::is_hole(file, offset, n as u64) {
+        buf[..n].fill(0);
+    } else {
+        file.read_exact_at(&mut buf[..n], offset)?;
+    }
+    Ok(n)
+}
+
+/// The id and part sha1s of the large file currently at `dest`, if it was uploaded with the same
+/// part size
+pub(super) fn remote_parts(
+    cfg: &mut Config,
+    dest: &RemotePath,
+    part_size: u64,
+) -> anyhow::Result<Option<(String, Vec<String>)>> {
+    let Some(file) = cfg.get_file_fresh(dest)? else {
+        return Ok(None);
+    };
+
+    let info = |key| file.file_info.get(key).and_then(|v| v.as_str());
+    if info(PART_SIZE_KEY) != Some(&part_size.to_string()) {
+        return Ok(None);
+    }
+    let Some(shas) = info(PART_SHA1S_KEY) else {
+        return Ok(None);
+    };
+
+    Ok(Some((
+        file.file_id,
+        shas.split(',').map(String::from).collect(),
+    )))
+}
+
+/// Copy the bytes from `start` up to (but not including) `end` of `source_id` into part
+/// `part_number` of the unfinished large file `file_id`
+pub(super) fn copy_part(
+    cfg: &mut Config,
+    source_id: &str,
+    file_id: &str,
+    part_number: usize,
+    start: u64,
+    end: u64,
+) -> anyhow::Result<()> {
+    let _: serde_json::Value = cfg.send_request_de(|cfg| {
+        Ok(cfg
+            .post("b2_copy_part")?
+            .json(&serde_json::json!({
+                "sourceFileId": source_id,
+                "largeFileId": file_id,
+                "partNumber": part_number,
+                "range": format!("bytes={}-{}", start, end - 1),
+            }))
+            .send()?)
+    })?;
+    Ok(())
+}
@@ -0,0 +1,386 @@
+use std::{fs, io::Write, os::unix::fs::FileExt, path::Path};
+
+use anyhow::bail;
+use colored::Colorize;
+use humanize_bytes::humanize_bytes_decimal;
+
+use super::{
+    checksum::{self, Checksum, ChecksumAlgorithm, MultiHasher},
+    compress::{Codec, Decoder},
+    delta,
+    sparse::{self, SparseWriter},
+    throttle::Throttled,
+    ChecksumPolicy, TransferJob,
+};
+use crate::{config::Config, http, path::RemotePath, progress, public};
+
+/// How many times a part that doesn't match its sha1 is downloaded again before giving up
+const PART_RETRIES: usize = 3;
+
+/// Wraps a [`Write`] and hashes everything that is written through it
+struct HashingWriter<W> {
+    inner: W,
+    hasher: MultiHasher,
+    parts: Option<PartVerifier>,
+}
+
+impl<W> Write for HashingWriter<W>
+where
+    W: Write,
+{
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.hasher.update(&buf[..n]);
+        if let Some(ref mut parts) = self.parts {
+            parts.update(&buf[..n]);
+        }
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Checks each part of a file uploaded in parts against the sha1 stored for it as it arrives,
+/// keeping track of the ones that don't match so that only they need to be downloaded again
+struct PartVerifier {
+    part_size: u64,
+    shas: Vec<String>,
+    /// The part being hashed, and how much of it has been
+    part: usize,
+    filled: u64,
+    sha: Box<dyn Checksum>,
+    bad: Vec<usize>,
+}
+
+impl PartVerifier {
+    fn update(&mut self, mut data: &[u8]) {
+        while !data.is_empty() {
+            let n = std::cmp::min(data.len() as u64, self.part_size - self.filled) as usize;
+            self.sha.update(&data[..n]);
+            self.filled += n as u64;
+            data = &data[n..];
+            if self.filled == self.part_size {
+                self.finish_part();
+            }
+        }
+    }
+
+    fn finish_part(&mut self) {
+        let sha = self.sha.finish_hex();
+        if self.shas.get(self.part) != Some(&sha) {
+            self.bad.push(self.part);
+        }
+        self.part += 1;
+        self.filled = 0;
+    }
+
+    /// The parts that didn't match, including any that never arrived
+    fn finish(mut self) -> Vec<usize> {
+        if self.filled > 0 {
+            self.finish_part();
+        }
+        self.bad.extend(self.part..self.shas.len());
+        self.bad
+    }
+}
+
+/// The length of the download, if it is known.  Chunked or compressed responses might not have a
+/// length, in which case we just read until the end.
+fn content_len(job: &TransferJob, res: &reqwest::blocking::Response) -> Option<u64> {
+    job.size.or(res.content_length())
+}
+
+pub(super) fn download(
+    cfg: &mut Config,
+    job: &TransferJob,
+    file: &RemotePath,
+    output: &Path,
+) -> anyhow::Result<u64> {
+    let url = if job.anonymous {
+        file.url(public::download_url(cfg)?)
+    } else {
+        cfg.confirm_auth()?;
+        file.url(&cfg.download_url)
+    };
+    let res = if job.anonymous {
+        http::client().get(&url).send()?.error_for_status()?
+    } else {
+        cfg.send_request_res(|cfg| {
+            Ok(http::client()
+                .get(&url)
+                .header("Authorization", &cfg.auth_token)
+                .send()?)
+        })?
+    };
+
+    let mut algorithms = job.checksums.clone();
+    if job.checksum == ChecksumPolicy::Verify && !algorithms.contains(&ChecksumAlgorithm::Sha1) {
+        algorithms.push(ChecksumAlgorithm::Sha1);
+    }
+
+    let header = |name: &str| {
+        res.headers()
+            .get(name)
+            .and_then(|h| h.to_str().ok())
+            .filter(|h| *h != "none")
+            .map(|h| h.trim_start_matches("unverified:").to_string())
+    };
+
+    let mut expected = Vec::with_capacity(algorithms.len());
+    for &algorithm in &algorithms {
+        let info = header(&format!("X-Bz-Info-{}", algorithm.info_key()));
+        let hash = match algorithm {
+            ChecksumAlgorithm::Sha1 => header("X-Bz-Content-Sha1").or(info),
+            _ => info,
+        };
+
+        match hash {
+            Some(hash) => expected.push((algorithm, hash)),
+            // Large files uploaded by other tools might not have a sha1, so only complain when
+            // it was explicitly asked for
+            None if job.checksums.contains(&algorithm) => {
+                bail!("{} has no {:?} checksum to verify against", file, algorithm)
+            }
+            None => {}
+        }
+    }
+
+    let len = content_len(job, &res);
+
+    let sparse = header(&format!("X-Bz-Info-{}", sparse::INFO_KEY)).as_deref() == Some("true");
+
+    let codec = if job.decompress {
+        Codec::of_response(res.headers())
+    } else {
+        None
+    };
+
+    // Files uploaded in parts by b2 with `--delta` have the sha1 of each part, so a corrupted
+    // part can be downloaded again on its own
+    let info = |key: &str| {
+        header(&format!("X-Bz-Info-{}", key))
+            .and_then(|h| urlencoding::decode(&h).ok().map(|h| h.into_owned()))
+    };
+    let parts = match (info(delta::PART_SIZE_KEY), info(delta::PART_SHA1S_KEY), len) {
+        (Some(part_size), Some(shas), Some(len)) => part_size
+            .parse::<u64>()
+            .ok()
+            .filter(|size| *size > 0)
+            .map(|part_size| PartVerifier {
+                part_size,
+                shas: shas.split(',').map(String::from).collect(),
+                part: 0,
+                filled: 0,
+                sha: ChecksumAlgorithm::Sha1.hasher(),
+                bad: Vec::new(),
+            })
+            .filter(|parts| parts.shas.len() as u64 == len.div_ceil(parts.part_size)),
+        _ => None,
+    };
+
+    // The checksums are of what was uploaded, so they're computed before decompressing
+    let mut writer = HashingWriter {
+        inner: progress::WriterProgress::new(
+            Decoder::new(SparseWriter::new(fs::File::create(output)?, sparse), codec),
+            len.map(|len| len as usize),
+            job.progress,
+        ),
+        hasher: MultiHasher::new(&algorithms),
+        parts,
+    };
+
+    let mut res = Throttled::new(res, cfg.bandwidth.clone());
+    let n = std::io::copy(&mut res, &mut writer)?;
+
+    job.progress.finish();
+
+    writer.inner.into_inner().finish()?.finish()?;
+
+    let mut actual = writer.hasher.finish();
+    if let Some(parts) = writer.parts {
+        let part_size = parts.part_size;
+        let shas = parts.shas.clone();
+        let bad = parts.finish();
+        if !bad.is_empty() {
+            // The parts are of the stored bytes, which are only where they were in the file if
+            // nothing was decompressed
+            if codec.is_some() {
+                bail!(
+                    "{} parts of {} didn't match their sha1, try downloading it again",
+                    bad.len(),
+                    output.display()
+                );
+            }
+            let len = len.unwrap();
+            for part in bad {
+                let start = part as u64 * part_size;
+                let end = std::cmp::min(start + part_size, len);
+                repair_part(cfg, job, &url, output, (start, end), &shas[part])?;
+            }
+            // Everything else was hashed as it arrived, including the bad parts
+            actual = checksum::hash_file_all(output, &algorithms)?;
+        }
+    }
+
+    for (algorithm, expected) in expected {
+        let Some((_, actual)) = actual.iter().find(|(a, _)| *a == algorithm) else {
+            continue;
+        };
+
+        if !actual.eq_ignore_ascii_case(&expected) {
+            bail!(
+                "{:?} mismatch for {}: expected {}, got {}",
+                algorithm,
+                output.display(),
+                expected,
+                actual
+            );
+        }
+    }
+
+    println!(
+        "{}",
+        format!(
+            "Downloaded {} to {}!",
+            humanize_bytes_decimal!(n),
+            output.display()
+        )
+        .green()
+    );
+
+    Ok(n)
+}
+
+/// Download the part of `output` from `start` up to (but not including) `end` again, until it
+/// matches `sha`, and write it into place
+fn repair_part(
+    cfg: &mut Config,
+    job: &TransferJob,
+    url: &str,
+    output: &Path,
+    (start, end): (u64, u64),
+    sha: &str,
+) -> anyhow::Result<()> {
+    let range = format!("bytes={}-{}", start, end - 1);
+    for attempt in 1..=PART_RETRIES {
+        eprintln!(
+            "{}",
+            format!(
+                "Bytes {}-{} of {} didn't match their sha1, downloading them again ({}/{})",
+                start,
+                end,
+                output.display(),
+                attempt,
+                PART_RETRIES
+            )
+            .yellow()
+        );
+
+        let mut res = if job.anonymous {
+            http::client()
+                .get(url)
+                .header("Range", &range)
+                .send()?
+                .error_for_status()?
+        } else {
+            cfg.send_request_res(|cfg| {
+                Ok(http::client()
+                    .get(url)
+                    .header("Authorization", &cfg.auth_token)
+                    .header("Range", &range)
+                    .send()?)
+            })?
+        };
+        let mut buf = Vec::with_capacity((end - start) as usize);
+        res.copy_to(&mut buf)?;
+
+        if checksum::sha1_hex(&buf) == sha {
+            fs::OpenOptions::new()
+                .write(true)
+                .open(output)?
+                .write_all_at(&buf, start)?;
+            return Ok(());
+        }
+    }
+    bail!(
+        "Bytes {}-{} of {} still didn't match their sha1 after downloading them {} times",
+        start,
+        end,
+        output.display(),
+        PART_RETRIES
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BufRead, BufReader, Write},
+        net::TcpListener,
+        path::PathBuf,
+    };
+
+    use super::{content_len, download};
+    use crate::transfer::TransferJob;
+    use crate::{config::Config, http, path::RemotePath, progress::ProgressSink};
+
+    /// Serve `chunks` with `Transfer-Encoding: chunked` and no `Content-Length` to the next
+    /// `requests` requests, returning the url of the server
+    fn serve_chunked(chunks: &'static [&'static [u8]], requests: usize) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            for stream in listener.incoming().take(requests) {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut line = String::new();
+                while reader.read_line(&mut line).unwrap() > 2 {
+                    line.clear();
+                }
+
+                write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/octet-stream\r\n\
+                     Transfer-Encoding: chunked\r\nConnection: close\r\n\r\n"
+                )
+                .unwrap();
+                for chunk in chunks {
+                    write!(stream, "{:x}\r\n", chunk.len()).unwrap();
+                    stream.write_all(chunk).unwrap();
+                    stream.write_all(b"\r\n").unwrap();
+                    stream.flush().unwrap();
+                }
+                stream.write_all(b"0\r\n\r\n").unwrap();
+            }
+        });
+        url
+    }
+
+    #[test]
+    fn chunked_without_length() {
+        const CHUNKS: &[&[u8]] = &[b"The first chunk, ", &[b'x'; 20_000], b" and the end"];
+        let url = serve_chunked(CHUNKS, 2);
+        let file = RemotePath::new("bucket", "photos/cat.jpg");
+        let output = std::env::temp_dir().join(format!("b2-download-test-{}", std::process::id()));
+
+        let mut job = TransferJob::download(file.clone(), PathBuf::from(&output));
+        // The spinner is what is shown when there is no length, so it must be what writes the file
+        job.progress = ProgressSink::Bar;
+
+        let res = http::client().get(file.url(&url)).send().unwrap();
+        assert_eq!(content_len(&job, &res), None);
+        drop(res);
+
+        let mut cfg = Config::default();
+        cfg.auth_token = "token".to_string();
+        cfg.download_url = url;
+        let n = download(&mut cfg, &job, &file, &output).unwrap();
+        let written = std::fs::read(&output).unwrap();
+        std::fs::remove_file(&output).unwrap();
+
+        let expected = CHUNKS.concat();
+        assert_eq!(n, expected.len() as u64);
+        assert_eq!(written, expected);
+    }
+}
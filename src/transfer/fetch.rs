@@ -0,0 +1,335 @@
+use std::{io::Read, sync::Arc};
+
+use ::reqwest::header;
+use colored::Colorize;
+use humanize_bytes::humanize_bytes_decimal;
+use reqwest::blocking as reqwest;
+
+use super::{
+    checksum::{self, Checksum, ChecksumAlgorithm},
+    throttle::Throttled,
+    upload::{
+        cancel_large_file, guess_content_type, upload_bytes, SharedPart, LARGE_FILE_SIZE,
+        LOW_MEMORY_PART_SIZE, MAX_PARTS,
+    },
+    TransferJob,
+};
+use crate::{api::File, cancel, config::Config, http, path::RemotePath, progress};
+
+/// The length of a hex-encoded sha1
+const SHA1_HEX_LEN: u64 = 40;
+
+/// Wraps a [`Read`], hashing everything read through it and then appending the hex-encoded
+/// sha1 once the inner reader is exhausted, for use with `X-Bz-Content-Sha1: hex_digits_at_end`
+struct HashAtEnd<R> {
+    inner: R,
+    sha: Box<dyn Checksum>,
+    /// The hex digits that still need to be written once the inner reader is finished
+    digits: Option<Vec<u8>>,
+}
+
+impl<R> Read for HashAtEnd<R>
+where
+    R: Read,
+{
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.digits.is_none() {
+            let n = self.inner.read(buf)?;
+            if n > 0 || buf.is_empty() {
+                self.sha.update(&buf[..n]);
+                return Ok(n);
+            }
+
+            self.digits = Some(self.sha.finish_hex().into_bytes());
+        }
+
+        let digits = self.digits.as_mut().unwrap();
+        let n = std::cmp::min(buf.len(), digits.len());
+        buf[..n].copy_from_slice(&digits[..n]);
+        digits.drain(..n);
+        Ok(n)
+    }
+}
+
+pub(super) fn fetch(
+    cfg: &mut Config,
+    job: &TransferJob,
+    url: &str,
+    dest: &RemotePath,
+) -> anyhow::Result<u64> {
+    let res = http::client().get(url).send()?.error_for_status()?;
+    stream(cfg, job, res, dest)
+}
+
+/// Upload the body of `res` to `dest` as it arrives, using the type and modification time that
+/// it was served with, and keeping the file info of files served by b2
+pub(super) fn stream(
+    cfg: &mut Config,
+    job: &TransferJob,
+    res: reqwest::Response,
+    dest: &RemotePath,
+) -> anyhow::Result<u64> {
+    let bucket_id = cfg.require_bucket_id(&dest.bucket)?;
+
+    let len = job.size.or(res.content_length());
+
+    let content_type = job
+        .content_type
+        .clone()
+        .or_else(|| {
+            res.headers()
+                .get(header::CONTENT_TYPE)
+                .and_then(|h| h.to_str().ok())
+                .map(String::from)
+        })
+        .unwrap_or_else(|| guess_content_type(cfg, &dest.key).to_string());
+
+    // b2 serves the file info (percent-encoded) as headers, which includes the original
+    // modification time, while anything else might have a Last-Modified
+    let mut info: Vec<(String, String)> = res
+        .headers()
+        .iter()
+        .filter_map(|(name, value)| {
+            let key = name.as_str().strip_prefix("x-bz-info-")?;
+            let value = urlencoding::decode(value.to_str().ok()?).ok()?;
+            Some((key.to_string(), value.into_owned()))
+        })
+        .collect();
+    if !info.iter().any(|(k, _)| k == "src_last_modified_millis") {
+        let modified = res
+            .headers()
+            .get(header::LAST_MODIFIED)
+            .and_then(|h| h.to_str().ok())
+            .and_then(|h| chrono::DateTime::parse_from_rfc2822(h).ok());
+        if let Some(modified) = modified {
+            info.push((
+                "src_last_modified_millis".into(),
+                modified.timestamp_millis().to_string(),
+            ));
+        }
+    }
+
+    let res = Throttled::new(res, cfg.bandwidth.clone());
+
+    let file = match len {
+        Some(len) if len < LARGE_FILE_SIZE && !job.parts => {
+            let res = progress::ReaderProgress::new(res, len as usize, "Fetching", job.progress);
+            let file = fetch_simple(cfg, &bucket_id, res, len, dest, &content_type, &info)?;
+            job.progress.finish();
+            file
+        }
+        _ => {
+            println!("Uploading as parts");
+            fetch_parts(cfg, job, &bucket_id, res, len, dest, &content_type, &info)?
+        }
+    };
+
+    println!(
+        "{}",
+        format!(
+            "Uploaded {} to {}!",
+            humanize_bytes_decimal!(file.content_length),
+            file.file_name
+        )
+        .green()
+    );
+
+    Ok(file.content_length)
+}
+
+/// Stream the response into a single upload, sending the sha1 at the end of the body
+fn fetch_simple<R>(
+    cfg: &mut Config,
+    bucket_id: &str,
+    res: R,
+    len: u64,
+    dest: &RemotePath,
+    content_type: &str,
+    info: &[(String, String)],
+) -> anyhow::Result<File>
+where
+    R: Read + Send + 'static,
+{
+    let upload: serde_json::Value = cfg.send_request_de(|cfg| {
+        Ok(cfg
+            .get("b2_get_upload_url")?
+            .query(&[("bucketId", bucket_id)])
+            .send()?)
+    })?;
+
+    let upload_url = upload["uploadUrl"].as_str().unwrap();
+    let auth = upload["authorizationToken"].as_str().unwrap();
+
+    let body = HashAtEnd {
+        inner: res,
+        sha: ChecksumAlgorithm::Sha1.hasher(),
+        digits: None,
+    };
+
+    let mut req = http::client()
+        .post(upload_url)
+        .header("Authorization", auth)
+        .header("X-Bz-File-Name", urlencoding::encode(&dest.key).to_string())
+        .header("Content-Type", content_type)
+        .header("Content-Length", len + SHA1_HEX_LEN)
+        .header("X-Bz-Content-Sha1", "hex_digits_at_end");
+    for (key, value) in info {
+        req = req.header(
+            format!("X-Bz-Info-{}", key),
+            urlencoding::encode(value).to_string(),
+        );
+    }
+
+    // The body can only be read once, so this can't be retried through `cfg.send_request`
+    let res = req
+        .body(reqwest::Body::sized(body, len + SHA1_HEX_LEN))
+        .send()?;
+
+    if res.status() != 200 {
+        let mut error: crate::api::ApiError = res.json()?;
+        error.url = upload_url.to_string();
+        return Err(error.into());
+    }
+
+    let file: File = res.json()?;
+    cfg.forget_lists(Some(bucket_id));
+    Ok(file)
+}
+
+/// Stream the response into a large file one part at a time, so that the size doesn't need to
+/// be known ahead of time
+#[allow(clippy::too_many_arguments)]
+fn fetch_parts<R>(
+    cfg: &mut Config,
+    job: &TransferJob,
+    bucket_id: &str,
+    mut res: R,
+    len: Option<u64>,
+    dest: &RemotePath,
+    content_type: &str,
+    info: &[(String, String)],
+) -> anyhow::Result<File>
+where
+    R: Read,
+{
+    let mut part_size = if cfg.low_memory {
+        LOW_MEMORY_PART_SIZE
+    } else {
+        cfg.part_size()?
+    };
+    if let Some(len) = len {
+        part_size = std::cmp::max(part_size, len.div_ceil(MAX_PARTS));
+    }
+    // The buffer is shared with the body of each attempt at sending it, rather than copied
+    let mut buf = Arc::new(vec![0u8; part_size as usize]);
+
+    let mut n = read_full(&mut res, Arc::make_mut(&mut buf).as_mut_slice())?;
+    if n < buf.len() {
+        // It all fit in one part, and large files need at least two
+        return upload_bytes(cfg, dest, &buf[..n], content_type);
+    }
+
+    let mut file_info = serde_json::Map::new();
+    for (key, value) in info {
+        file_info.insert(key.clone(), value.clone().into());
+    }
+
+    let start: serde_json::Value = cfg.send_request_de(|cfg| {
+        Ok(cfg
+            .post("b2_start_large_file")?
+            .json(&serde_json::json!({
+                "bucketId": bucket_id,
+                "fileName": dest.key,
+                "contentType": content_type,
+                "fileInfo": file_info,
+            }))
+            .send()?)
+    })?;
+    cfg.forget_lists(Some(bucket_id));
+
+    let file_id = start["fileId"].as_str().unwrap();
+
+    let part_url: serde_json::Value = cfg.send_request_de(|cfg| {
+        Ok(cfg
+            .get("b2_get_upload_part_url")?
+            .query(&[("fileId", file_id)])
+            .send()?)
+    })?;
+
+    let upload_url = part_url["uploadUrl"].as_str().unwrap();
+    let auth = part_url["authorizationToken"].as_str().unwrap();
+
+    job.progress.start(len.map(|len| len as usize), "Fetching");
+    let mut shas = Vec::new();
+    let mut total = 0;
+    let mut sent = || -> anyhow::Result<()> {
+        while n > 0 {
+            shas.push(checksum::sha1_hex(&buf[..n]));
+
+            let _: serde_json::Value = cfg.send_request_de(|_| {
+                let body = std::io::Cursor::new(SharedPart(buf.clone(), n));
+                Ok(http::client()
+                    .post(upload_url)
+                    .header("Authorization", auth)
+                    .header("X-Bz-Part-Number", shas.len())
+                    .header("Content-Length", n)
+                    .header("X-Bz-Content-Sha1", shas.last().unwrap())
+                    .body(reqwest::Body::sized(body, n as u64))
+                    .send()?)
+            })?;
+
+            total += n;
+            job.progress.set(total);
+
+            // Without a length, the parts are made bigger as the stream goes on so that it
+            // doesn't run out of parts, doubling every so often up to the most that b2 takes
+            if len.is_none() && (shas.len() as u64).is_multiple_of(PARTS_PER_GROWTH) {
+                let grown = std::cmp::min(buf.len() as u64 * 2, MAX_PART_SIZE) as usize;
+                Arc::make_mut(&mut buf).resize(grown, 0);
+            }
+            n = read_full(&mut res, Arc::make_mut(&mut buf).as_mut_slice())?;
+        }
+        Ok(())
+    };
+    let sent = sent();
+
+    job.progress.finish();
+
+    if let Err(e) = sent {
+        if cancel::is_cancelled() {
+            cancel_large_file(cfg, file_id)?;
+        }
+        return Err(e);
+    }
+
+    let file: File = cfg.send_request_de(|cfg| {
+        Ok(cfg
+            .post("b2_finish_large_file")?
+            .json(&serde_json::json!({
+                "fileId": file_id,
+                "partSha1Array": shas,
+            }))
+            .send()?)
+    })?;
+    cfg.forget_lists(Some(bucket_id));
+    Ok(file)
+}
+
+/// How many parts of a fetch of unknown length are sent before the part size doubles, which
+/// lets the 10,000 parts of a large file hold about 5 TB even when starting from 5 MB parts
+const PARTS_PER_GROWTH: u64 = 1000;
+
+/// The largest part that b2 accepts
+const MAX_PART_SIZE: u64 = 5_000_000_000;
+
+/// Read from `r` until `buf` is full or the end is reached, returning the number of bytes read
+fn read_full<R: Read>(r: &mut R, buf: &mut [u8]) -> std::io::Result<usize> {
+    let mut total = 0;
+    while total < buf.len() {
+        match r.read(&mut buf[total..])? {
+            0 => break,
+            n => total += n,
+        }
+    }
+    Ok(total)
+}
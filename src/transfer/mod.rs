@@ -0,0 +1,237 @@
+use std::path::PathBuf;
+
+use anyhow::bail;
+use chrono::{DateTime, Utc};
+
+use crate::{config::Config, http, path::RemotePath, progress::ProgressSink};
+
+mod checksum;
+mod compress;
+mod delta;
+mod download;
+mod fetch;
+mod sparse;
+mod throttle;
+mod upload;
+
+pub use checksum::{hash_file_all, sha1_hex, ChecksumAlgorithm};
+pub use compress::{Codec, Compression, Decoder};
+pub use upload::{create_placeholder, hash_ahead, upload_bytes};
+
+/// One end of a [`TransferJob`]
+#[derive(Debug, Clone)]
+pub enum Location {
+    /// A path on the local filesystem
+    Local(PathBuf),
+    /// A file within a bucket
+    Remote(RemotePath),
+    /// An HTTP(S) url, which can only be used as a source
+    Url(String),
+}
+
+/// How checksums are handled during a [`TransferJob`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChecksumPolicy {
+    /// Hash the content and check it against the hash known by the other end
+    Verify,
+    /// Don't compute or check any hashes
+    Skip,
+}
+
+/// What happens to the local file once it has been uploaded
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AfterUpload {
+    Keep,
+    Delete,
+    /// Move the file to this path, creating its parent directories
+    Move(PathBuf),
+}
+
+/// A single upload or download between the local filesystem and b2
+#[derive(Debug, Clone)]
+pub struct TransferJob {
+    pub source: Location,
+    pub destination: Location,
+    /// The size of the content, if it is known before the transfer starts
+    pub size: Option<u64>,
+    pub checksum: ChecksumPolicy,
+    /// Extra checksums to store in the file info when uploading, or to verify when downloading
+    pub checksums: Vec<ChecksumAlgorithm>,
+    /// Checksums of the source that were computed ahead of time (see [`hash_ahead`]), so they
+    /// don't need to be computed again during the transfer
+    pub hashes: Vec<(ChecksumAlgorithm, String)>,
+    pub progress: ProgressSink,
+    /// Manually override the Content Type rather than guessing it from the file extension
+    pub content_type: Option<String>,
+    /// Upload the file using the "parts" api, regardless of its size
+    pub parts: bool,
+    /// When uploading in parts, copy the parts that are unchanged from the existing file at the
+    /// destination rather than uploading them again
+    pub delta: bool,
+    /// After uploading, read back the size and sha1 of the file (or of each part of a large
+    /// file) and check them against what was read from it
+    pub verify_after: bool,
+    /// Compress the file before uploading it
+    pub compress: Compression,
+    /// Download from a public bucket without authorising
+    pub anonymous: bool,
+    /// Decompress downloads that are served with a `Content-Encoding`, rather than saving the
+    /// bytes as they are stored
+    pub decompress: bool,
+    /// What to do with the local file after uploading it, anything other than keeping it only
+    /// happens once the upload has been read back and both its size and a checksum matched: the
+    /// sha1 of a simple upload, or of every part of a large file
+    pub after: AfterUpload,
+    /// When the uploaded file should be deleted by `b2 expire`
+    pub expires: Option<DateTime<Utc>>,
+    /// Extra file info to store with the uploaded file
+    pub info: Vec<(String, String)>,
+}
+
+impl TransferJob {
+    /// Create a job which uploads the local `file` to `dest`
+    pub fn upload(file: PathBuf, dest: RemotePath) -> Self {
+        Self {
+            source: Location::Local(file),
+            destination: Location::Remote(dest),
+            size: None,
+            checksum: ChecksumPolicy::Verify,
+            checksums: Vec::new(),
+            hashes: Vec::new(),
+            progress: ProgressSink::auto(),
+            content_type: None,
+            parts: false,
+            delta: false,
+            verify_after: false,
+            compress: Compression::None,
+            anonymous: false,
+            decompress: true,
+            after: AfterUpload::Keep,
+            expires: None,
+            info: Vec::new(),
+        }
+    }
+
+    /// Create a job which downloads `file` into the local `output`
+    pub fn download(file: RemotePath, output: PathBuf) -> Self {
+        Self {
+            source: Location::Remote(file),
+            destination: Location::Local(output),
+            size: None,
+            checksum: ChecksumPolicy::Skip,
+            checksums: Vec::new(),
+            hashes: Vec::new(),
+            progress: ProgressSink::auto(),
+            content_type: None,
+            parts: false,
+            delta: false,
+            verify_after: false,
+            compress: Compression::None,
+            anonymous: false,
+            decompress: true,
+            after: AfterUpload::Keep,
+            expires: None,
+            info: Vec::new(),
+        }
+    }
+
+    /// Create a job which streams the contents of `url` into `dest`
+    pub fn fetch(url: String, dest: RemotePath) -> Self {
+        Self {
+            source: Location::Url(url),
+            destination: Location::Remote(dest),
+            size: None,
+            checksum: ChecksumPolicy::Verify,
+            checksums: Vec::new(),
+            hashes: Vec::new(),
+            progress: ProgressSink::auto(),
+            content_type: None,
+            parts: false,
+            delta: false,
+            verify_after: false,
+            compress: Compression::None,
+            anonymous: false,
+            decompress: true,
+            after: AfterUpload::Keep,
+            expires: None,
+            info: Vec::new(),
+        }
+    }
+
+    /// Create a job which copies `file` into `dest`, which may be in another account
+    pub fn copy(file: RemotePath, dest: RemotePath) -> Self {
+        Self {
+            source: Location::Remote(file),
+            destination: Location::Remote(dest),
+            size: None,
+            checksum: ChecksumPolicy::Verify,
+            checksums: Vec::new(),
+            hashes: Vec::new(),
+            progress: ProgressSink::auto(),
+            content_type: None,
+            parts: false,
+            delta: false,
+            verify_after: false,
+            compress: Compression::None,
+            anonymous: false,
+            decompress: true,
+            after: AfterUpload::Keep,
+            expires: None,
+            info: Vec::new(),
+        }
+    }
+
+    /// The checksum of the source that was computed ahead of time, if there is one
+    fn hash(&self, algorithm: ChecksumAlgorithm) -> Option<&str> {
+        self.hashes
+            .iter()
+            .find(|(a, _)| *a == algorithm)
+            .map(|(_, h)| h.as_str())
+    }
+
+    /// Run the transfer, returning the number of bytes that were transferred
+    pub fn run(self, cfg: &mut Config) -> anyhow::Result<u64> {
+        match (&self.source, &self.destination) {
+            (Location::Local(file), Location::Remote(dest)) => {
+                let n = upload::upload(cfg, &self, file, dest)?;
+                upload::after_upload(file, &self.after)?;
+                Ok(n)
+            }
+            (Location::Remote(file), Location::Local(output)) => {
+                download::download(cfg, &self, file, output)
+            }
+            (Location::Url(url), Location::Remote(dest)) => fetch::fetch(cfg, &self, url, dest),
+            (_, Location::Url(_)) => bail!("Urls can only be the source of a transfer"),
+            (Location::Url(_), Location::Local(_)) => {
+                bail!("Transfers from a url to a local path are not supported")
+            }
+            (Location::Local(_), Location::Local(_)) => {
+                bail!("Transfers between two local paths are not supported")
+            }
+            (Location::Remote(_), Location::Remote(_)) => {
+                bail!("Transfers between two remote paths need a config for each end")
+            }
+        }
+    }
+
+    /// Run a transfer between two remote paths, downloading the source with `from` and
+    /// streaming it straight into an upload with `to`, so the two can be in different accounts
+    pub fn run_between(self, from: &mut Config, to: &mut Config) -> anyhow::Result<u64> {
+        let (Location::Remote(src), Location::Remote(dest)) = (&self.source, &self.destination)
+        else {
+            bail!("Only transfers between two remote paths can use two configs");
+        };
+
+        from.confirm_auth()?;
+        let url = src.url(&from.download_url);
+        let res = from.send_request_res(|cfg| {
+            Ok(http::client()
+                .get(&url)
+                .header("Authorization", &cfg.auth_token)
+                // Keep the bytes as they are stored, along with their Content-Encoding
+                .header("Accept-Encoding", "identity")
+                .send()?)
+        })?;
+        fetch::stream(to, &self, res, dest)
+    }
+}
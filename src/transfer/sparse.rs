@@ -0,0 +1,94 @@
+use std::{
+    fs,
+    io::{Seek, SeekFrom, Write},
+};
+
+/// The file info key which marks a file as having been sparse when it was uploaded, so that it
+/// can be written back out sparsely when downloaded
+pub(super) const INFO_KEY: &str = "src_sparse";
+
+/// Whether `file` has any holes in its first `len` bytes
+#[cfg(target_os = "linux")]
+pub(super) fn is_sparse(file: &fs::File, len: u64) -> bool {
+    use std::os::fd::AsRawFd;
+
+    // SAFETY: the fd is valid for as long as `file` is borrowed, and the offset is put back at the
+    // start afterwards so that reading the file isn't affected
+    let hole = unsafe {
+        let hole = libc::lseek(file.as_raw_fd(), 0, libc::SEEK_HOLE);
+        libc::lseek(file.as_raw_fd(), 0, libc::SEEK_SET);
+        hole
+    };
+    hole >= 0 && (hole as u64) < len
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(super) fn is_sparse(_file: &fs::File, _len: u64) -> bool {
+    false
+}
+
+/// Whether the `len` bytes starting at `start` are all within a hole, so can be treated as zeros
+/// without reading them
+#[cfg(target_os = "linux")]
+pub(super) fn is_hole(file: &fs::File, start: u64, len: u64) -> bool {
+    use std::os::fd::AsRawFd;
+
+    // SAFETY: the fd is valid for as long as `file` is borrowed.  This moves the file offset, but
+    // files uploaded in parts are only read using `read_at`.
+    let data = unsafe { libc::lseek(file.as_raw_fd(), start as libc::off_t, libc::SEEK_DATA) };
+    if data < 0 {
+        // ENXIO means there is no more data after `start`
+        return std::io::Error::last_os_error().raw_os_error() == Some(libc::ENXIO);
+    }
+    data as u64 >= start + len
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(super) fn is_hole(_file: &fs::File, _start: u64, _len: u64) -> bool {
+    false
+}
+
+/// Writes to a file, seeking over runs of zeros instead of writing them when `sparse` is set so
+/// that they become holes
+pub(super) struct SparseWriter {
+    file: fs::File,
+    sparse: bool,
+    /// The length of the file once everything has been written
+    len: u64,
+}
+
+impl SparseWriter {
+    pub fn new(file: fs::File, sparse: bool) -> Self {
+        Self {
+            file,
+            sparse,
+            len: 0,
+        }
+    }
+
+    /// Set the length of the file, since seeking past the end doesn't extend it when the file
+    /// ends in a hole
+    pub fn finish(self) -> std::io::Result<()> {
+        if self.sparse {
+            self.file.set_len(self.len)?;
+        }
+        Ok(())
+    }
+}
+
+impl Write for SparseWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let n = if self.sparse && buf.iter().all(|b| *b == 0) {
+            self.file.seek(SeekFrom::Current(buf.len() as i64))?;
+            buf.len()
+        } else {
+            self.file.write(buf)?
+        };
+        self.len += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.file.flush()
+    }
+}
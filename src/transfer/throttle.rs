@@ -0,0 +1,77 @@
+use std::{
+    io::Read,
+    time::{Duration, Instant},
+};
+
+use chrono::Local;
+
+use crate::{cancel, config::BandwidthWindow};
+
+/// Wraps a [`Read`] and limits how fast it can be read from according to the configured
+/// [`BandwidthWindow`]s
+pub struct Throttled<R> {
+    inner: R,
+    windows: Vec<BandwidthWindow>,
+    second_start: Instant,
+    second_bytes: u64,
+}
+
+impl<R> Throttled<R> {
+    pub fn new(inner: R, windows: Vec<BandwidthWindow>) -> Self {
+        Self {
+            inner,
+            windows,
+            second_start: Instant::now(),
+            second_bytes: 0,
+        }
+    }
+
+    /// The limit of the first window that is active right now, if any
+    fn current_limit(&self) -> Option<u64> {
+        let now = Local::now().time();
+        self.windows
+            .iter()
+            .find(|w| w.contains(now))
+            .map(|w| w.limit)
+    }
+}
+
+impl<R> Read for Throttled<R>
+where
+    R: Read,
+{
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        loop {
+            // Every transfer reads through here, so this is where they're stopped part way
+            if cancel::is_cancelled() {
+                return Err(std::io::Error::other("cancelled"));
+            }
+
+            let Some(limit) = self.current_limit() else {
+                return self.inner.read(buf);
+            };
+
+            if limit == 0 {
+                // Transfers are paused, so check again in a little while
+                std::thread::sleep(Duration::from_secs(1));
+                continue;
+            }
+
+            let elapsed = self.second_start.elapsed();
+            if elapsed >= Duration::from_secs(1) {
+                self.second_start = Instant::now();
+                self.second_bytes = 0;
+            }
+
+            if self.second_bytes >= limit {
+                std::thread::sleep(Duration::from_secs(1).saturating_sub(elapsed));
+                continue;
+            }
+
+            let max = std::cmp::min(buf.len() as u64, limit - self.second_bytes) as usize;
+            let n = self.inner.read(&mut buf[..max])?;
+            self.second_bytes += n as u64;
+            return Ok(n);
+        }
+    }
+}
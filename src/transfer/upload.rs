@@ -0,0 +1,955 @@
+use std::{
+    borrow::Cow,
+    fs,
+    io::{Cursor, Read, Seek, SeekFrom},
+    ops::Range,
+    path::Path,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc, Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use anyhow::bail;
+use colored::Colorize;
+use humanize_bytes::humanize_bytes_decimal;
+use rayon::prelude::*;
+use reqwest::blocking as reqwest;
+
+use super::{
+    checksum::{self, ChecksumAlgorithm},
+    compress, delta, sparse,
+    throttle::Throttled,
+    AfterUpload, ChecksumPolicy, Location, TransferJob,
+};
+use crate::{api::File, cancel, config::Config, expire, http, path::RemotePath, progress};
+
+/// How many part-sized buffers a large file upload uses, one being sent while the others are
+/// read ahead
+const PART_BUFFERS: usize = 2;
+
+/// The largest part that [`PartSizer`] will choose, which is well under b2's limit of 5 GB since
+/// [`PART_BUFFERS`] parts are held in memory at once
+const MAX_PART_SIZE: u64 = 500_000_000;
+
+/// The size of each part of a large file with `--low-memory`, which is the smallest that b2
+/// accepts
+pub(super) const LOW_MEMORY_PART_SIZE: u64 = 5_000_000;
+
+/// The most parts that b2 accepts in a large file
+pub(super) const MAX_PARTS: u64 = 10_000;
+
+/// How long [`PartSizer`] aims for each part to take to send
+const TARGET_PART_TIME: Duration = Duration::from_secs(20);
+
+/// How many times a part is sent again after failing before the upload is abandoned
+const PART_RETRIES: usize = 3;
+
+/// Files at least this big are uploaded using the large file api
+pub(super) const LARGE_FILE_SIZE: u64 = 1024 * 1024 * 1024; // 1 GiB
+
+/// How many seconds a simple upload can be estimated to take before it's switched over to
+/// parts, when `auto_parts_after` isn't set in the config
+const AUTO_PARTS_AFTER: u64 = 300;
+
+/// How long a simple upload runs before its speed is used to estimate how long all of it will
+/// take, see [`Probe`]
+const PROBE_TIME: Duration = Duration::from_secs(5);
+
+/// Files smaller than this are never switched over to parts, since the few parts they'd be split
+/// into aren't worth starting again for
+const MIN_AUTO_PARTS_SIZE: u64 = 100_000_000;
+
+/// The name of the file used to mark an empty directory
+const PLACEHOLDER_NAME: &str = ".bzEmpty";
+
+/// Wraps the body of a simple upload and, once it has run for [`PROBE_TIME`], gives up on it if
+/// the rest would take longer than `limit` at the speed so far, so it can be sent in parts
+struct Probe<R> {
+    inner: R,
+    start: Instant,
+    sent: u64,
+    len: u64,
+    limit: Duration,
+    checked: bool,
+    /// The speed that the upload was going at, in bytes per second, if it was given up on
+    abandoned: Arc<Mutex<Option<u64>>>,
+}
+
+impl<R> Read for Probe<R>
+where
+    R: Read,
+{
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        cancel::check().map_err(std::io::Error::other)?;
+
+        let elapsed = self.start.elapsed();
+        if !self.checked && elapsed >= PROBE_TIME {
+            self.checked = true;
+            let speed = (self.sent as f64 / elapsed.as_secs_f64()) as u64;
+            if speed == 0 || self.len / speed > self.limit.as_secs() {
+                *self.abandoned.lock().unwrap() = Some(speed);
+                return Err(std::io::Error::other("switching to a large file upload"));
+            }
+        }
+
+        let n = self.inner.read(buf)?;
+        self.sent += n as u64;
+        Ok(n)
+    }
+}
+
+/// Bytes per second of `bytes` sent in `elapsed`
+fn speed(bytes: u64, elapsed: Duration) -> u64 {
+    (bytes as f64 / elapsed.as_secs_f64().max(0.001)) as u64
+}
+
+/// The first `len` bytes of a part buffer, shared with the request body so that the buffer can
+/// be sent again if the part fails without copying it for every attempt
+pub(super) struct SharedPart(pub(super) Arc<Vec<u8>>, pub(super) usize);
+impl AsRef<[u8]> for SharedPart {
+    fn as_ref(&self) -> &[u8] {
+        &self.0[..self.1]
+    }
+}
+
+pub(super) fn upload(
+    cfg: &mut Config,
+    job: &TransferJob,
+    file: &Path,
+    dest: &RemotePath,
+) -> anyhow::Result<u64> {
+    if !file.is_file() {
+        eprintln!(
+            "{} {}",
+            file.display().to_string().red(),
+            "is not a file.".red()
+        );
+    }
+
+    if job.after != AfterUpload::Keep && job.checksum == ChecksumPolicy::Skip {
+        bail!(
+            "{} can't be removed after uploading without checking its sha1",
+            file.display()
+        );
+    }
+
+    let bucket_id = cfg.require_bucket_id(&dest.bucket)?;
+
+    let mut len = match job.size {
+        Some(len) => len,
+        None => fs::metadata(file)?.len(),
+    };
+
+    let mut info: Vec<_> = job
+        .info
+        .iter()
+        .map(|(k, v)| (k.as_str(), v.clone()))
+        .collect();
+    if let Some(expires) = job.expires {
+        info.push((expire::INFO_KEY, expires.timestamp_millis().to_string()));
+    }
+
+    // The compressed copy is uploaded in place of the file, so anything that was worked out
+    // about the original no longer applies
+    let compressed;
+    let (job, file) = match job.compress.codec(file)? {
+        Some(codec) => {
+            compressed = compress::compress(file, codec)?;
+            let compressed_len = fs::metadata(&compressed.path)?.len();
+            println!(
+                "Compressed {} to {}",
+                humanize_bytes_decimal!(len),
+                humanize_bytes_decimal!(compressed_len)
+            );
+            len = compressed_len;
+            info.push((compress::INFO_KEY, codec.name().to_string()));
+
+            let mut job = job.clone();
+            job.size = Some(len);
+            job.hashes.clear();
+            job.delta = false;
+            (Cow::Owned(job), compressed.path.as_path())
+        }
+        None => (Cow::Borrowed(job), file),
+    };
+
+    let simple = if uses_parts(&job, len) {
+        println!("Uploading as parts");
+        None
+    } else {
+        upload_file_non_parts(cfg, &job, &bucket_id, file, len, &dest.key, &info)?
+    };
+    // Large files have no sha1 of the whole file, their parts are checked before finishing instead
+    let (file, sha1, parts) = match simple {
+        Some((file, sha1)) => (file, sha1, false),
+        None => (
+            upload_file_parts(cfg, &job, &bucket_id, file, len, dest, &info)?,
+            None,
+            true,
+        ),
+    };
+
+    // The local file is only removed once we know that b2 has all of it
+    let verified = if verifies(&job) {
+        verify_upload(cfg, dest, len, sha1.as_deref())?;
+        // The size matching isn't enough to remove the only other copy of the file
+        if job.after != AfterUpload::Keep && sha1.is_none() && !parts {
+            bail!(
+                "Not removing the local copy of {}, since there was no checksum to check the \
+                 upload against",
+                dest
+            );
+        }
+        " (verified)"
+    } else {
+        ""
+    };
+
+    println!(
+        "{}",
+        format!(
+            "Uploaded {} to {}{}!",
+            humanize_bytes_decimal!(len),
+            file.file_name,
+            verified
+        )
+        .green()
+    );
+
+    Ok(len)
+}
+
+/// Delete or move the local `file` after it has been uploaded
+pub(super) fn after_upload(file: &Path, after: &AfterUpload) -> anyhow::Result<()> {
+    match after {
+        AfterUpload::Keep => {}
+        AfterUpload::Delete => {
+            fs::remove_file(file)?;
+            println!("Deleted {}", file.display());
+        }
+        AfterUpload::Move(to) => {
+            if let Some(parent) = to.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            // Renaming fails across filesystems, in which case it has to be copied
+            if fs::rename(file, to).is_err() {
+                fs::copy(file, to)?;
+                fs::remove_file(file)?;
+            }
+            println!("Moved {} to {}", file.display(), to.display());
+        }
+    }
+    Ok(())
+}
+
+/// Whether the upload is read back and checked once it is done
+fn verifies(job: &TransferJob) -> bool {
+    job.verify_after || job.after != AfterUpload::Keep
+}
+
+/// Read back the headers of the uploaded file and check that its size matches what was sent, and
+/// its sha1 matches `sha1`, which was computed from the bytes that were read from the local file
+fn verify_upload(
+    cfg: &mut Config,
+    dest: &RemotePath,
+    len: u64,
+    sha1: Option<&str>,
+) -> anyhow::Result<()> {
+    let url = dest.url(&cfg.download_url);
+    let res = cfg.send_request_res(|cfg| {
+        Ok(http::client()
+            .head(&url)
+            .header("Authorization", &cfg.auth_token)
+            .send()?)
+    })?;
+
+    let header = |name: &str| {
+        res.headers()
+            .get(name)
+            .and_then(|h| h.to_str().ok())
+            .map(String::from)
+    };
+
+    let remote_len = header("Content-Length").and_then(|l| l.parse::<u64>().ok());
+    if remote_len != Some(len) {
+        bail!(
+            "Verification of {} failed: sent {} bytes, but b2 has {}",
+            dest,
+            len,
+            remote_len.map_or("an unknown number of".into(), |l| l.to_string())
+        );
+    }
+
+    if let Some(sha1) = sha1 {
+        let remote = header("X-Bz-Content-Sha1");
+        if !remote
+            .as_ref()
+            .is_some_and(|r| r.eq_ignore_ascii_case(sha1))
+        {
+            bail!(
+                "Verification of {} failed: sent sha1 {}, but b2 has {}",
+                dest,
+                sha1,
+                remote.as_deref().unwrap_or("none")
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Compute the checksums that each upload in `jobs` will need, hashing several files at once
+/// across all cores, rather than one at a time as each file is uploaded.  Returns whether each
+/// job could be hashed, so that one unreadable file doesn't stop the others.
+pub fn hash_ahead(jobs: &mut [TransferJob]) -> Vec<anyhow::Result<()>> {
+    jobs.par_iter_mut()
+        .map(|job| {
+            let Location::Local(ref file) = job.source else {
+                return Ok(());
+            };
+
+            let len = match job.size {
+                Some(len) => len,
+                None => fs::metadata(file)?.len(),
+            };
+            job.size = Some(len);
+
+            let mut algorithms: Vec<_> = job
+                .checksums
+                .iter()
+                .copied()
+                .filter(|a| *a != ChecksumAlgorithm::Sha1)
+                .collect();
+            // The sha1 of the whole file is only sent for simple uploads
+            if job.checksum == ChecksumPolicy::Verify && !uses_parts(job, len) {
+                algorithms.push(ChecksumAlgorithm::Sha1);
+            }
+
+            job.hashes = checksum::hash_file_all(file, &algorithms)?;
+            Ok(())
+        })
+        .collect()
+}
+
+/// Whether a file of `len` bytes will be uploaded using the large file api
+fn uses_parts(job: &TransferJob, len: u64) -> bool {
+    // Empty files can't be split into parts, so they always use the simple upload
+    len > 0 && (job.parts || len >= LARGE_FILE_SIZE)
+}
+
+/// The extra checksums to store with the file, using the ones from [`hash_ahead`] if they exist
+fn extra_checksums(
+    job: &TransferJob,
+    file: &Path,
+) -> anyhow::Result<Vec<(ChecksumAlgorithm, String)>> {
+    if job.hashes.is_empty() {
+        return checksum::hash_file(file, &job.checksums);
+    }
+
+    Ok(job
+        .hashes
+        .iter()
+        .filter(|(a, _)| *a != ChecksumAlgorithm::Sha1)
+        .cloned()
+        .collect())
+}
+
+/// Upload an empty placeholder file into `dir` so that it shows up as a directory, the same way
+/// the Backblaze web UI does
+pub fn create_placeholder(cfg: &mut Config, dir: &RemotePath) -> anyhow::Result<()> {
+    let dest = dir.join(PLACEHOLDER_NAME);
+    upload_bytes(cfg, &dest, &[], "application/x-bz-empty")?;
+
+    println!("{}", format!("Created {}!", dest.key).green());
+
+    Ok(())
+}
+
+/// Upload a small in-memory buffer to `dest` using the simple upload api
+pub fn upload_bytes(
+    cfg: &mut Config,
+    dest: &RemotePath,
+    data: &[u8],
+    content_type: &str,
+) -> anyhow::Result<File> {
+    let bucket_id = cfg.require_bucket_id(&dest.bucket)?;
+
+    let res: serde_json::Value = cfg.send_request_de(|cfg| {
+        Ok(cfg
+            .get("b2_get_upload_url")?
+            .query(&[("bucketId", &bucket_id)])
+            .send()?)
+    })?;
+
+    let upload_url = res["uploadUrl"].as_str().unwrap();
+    let auth = res["authorizationToken"].as_str().unwrap();
+
+    let hash = checksum::sha1_hex(data);
+
+    let file: File = cfg.send_request_de(|_| {
+        Ok(http::client()
+            .post(upload_url)
+            .header("Authorization", auth)
+            .header("X-Bz-File-Name", urlencoding::encode(&dest.key).to_string())
+            .header("Content-Type", content_type)
+            .header("Content-Length", data.len())
+            .header("X-Bz-Content-Sha1", &hash)
+            .body(data.to_vec())
+            .send()?)
+    })?;
+    cfg.forget_lists(Some(&bucket_id));
+    Ok(file)
+}
+
+/// Upload the file in one request, returning it along with the sha1 that was computed locally
+/// and sent, or `None` if it was abandoned because it would take longer than `auto_parts_after`
+/// and should be sent in parts instead
+fn upload_file_non_parts(
+    cfg: &mut Config,
+    job: &TransferJob,
+    bucket_id: &str,
+    file: &Path,
+    len: u64,
+    dest: &str,
+    info: &[(&str, String)],
+) -> anyhow::Result<Option<(File, Option<String>)>> {
+    let res: serde_json::Value = cfg.send_request_de(|cfg| {
+        Ok(cfg
+            .get("b2_get_upload_url")?
+            .query(&[("bucketId", bucket_id)])
+            .send()?)
+    })?;
+
+    let upload_url = res["uploadUrl"].as_str().unwrap();
+    let auth = res["authorizationToken"].as_str().unwrap();
+
+    let modified = src_last_modified_millis(file)?;
+    let extra_checksums = extra_checksums(job, file)?;
+
+    let mut file = fs::File::open(file)?;
+    let is_sparse = sparse::is_sparse(&file, len);
+
+    let hash = match (job.checksum, job.hash(ChecksumAlgorithm::Sha1)) {
+        (ChecksumPolicy::Verify, Some(hash)) => hash.to_string(),
+        (ChecksumPolicy::Verify, None) => {
+            let mut hashes = checksum::hash_reader(&mut file, &[ChecksumAlgorithm::Sha1])?;
+            file.seek(SeekFrom::Start(0))?;
+            hashes.remove(0).1
+        }
+        (ChecksumPolicy::Skip, _) => "do_not_verify".to_string(),
+    };
+
+    let limit = cfg.auto_parts_after.unwrap_or(AUTO_PARTS_AFTER);
+    let abandoned = Arc::new(Mutex::new(None));
+    let file = Probe {
+        inner: Throttled::new(file, cfg.bandwidth.clone()),
+        start: Instant::now(),
+        sent: 0,
+        len,
+        limit: Duration::from_secs(limit),
+        // Small files are never switched over
+        checked: limit == 0 || len < MIN_AUTO_PARTS_SIZE,
+        abandoned: abandoned.clone(),
+    };
+    let file = progress::ReaderProgress::new(file, len as usize, "Uploading", job.progress);
+
+    // TODO: make this work with `cfg.send_request`
+    let mut req = http::client()
+        .post(upload_url)
+        .header("Authorization", auth)
+        .header("X-Bz-File-Name", urlencoding::encode(dest).to_string())
+        .header("Content-Type", content_type(job, cfg, dest))
+        .header("Content-Length", len)
+        .header("X-Bz-Content-Sha1", &hash)
+        .header("X-Bz-Info-src_last_modified_millis", modified);
+    for (algorithm, hash) in extra_checksums {
+        req = req.header(format!("X-Bz-Info-{}", algorithm.info_key()), hash);
+    }
+    if is_sparse {
+        req = req.header(format!("X-Bz-Info-{}", sparse::INFO_KEY), "true");
+    }
+    for (key, value) in info {
+        req = req.header(
+            format!("X-Bz-Info-{}", key),
+            urlencoding::encode(value).to_string(),
+        );
+    }
+
+    let res = req.body(reqwest::Body::new(file)).send();
+
+    job.progress.finish();
+    // The body failing to read shows up as a request error, so say why it really stopped
+    cancel::check()?;
+
+    if let Some(speed) = *abandoned.lock().unwrap() {
+        println!(
+            "Uploading as parts, since this would take more than {}s at {}/s",
+            limit,
+            humanize_bytes_decimal!(speed)
+        );
+        return Ok(None);
+    }
+
+    let file: File = res?.json()?;
+    cfg.forget_lists(Some(bucket_id));
+    let sha1 = (job.checksum == ChecksumPolicy::Verify).then_some(hash);
+    Ok(Some((file, sha1)))
+}
+
+fn upload_file_parts(
+    cfg: &mut Config,
+    job: &TransferJob,
+    bucket_id: &str,
+    file: &Path,
+    len: u64,
+    dest: &RemotePath,
+    info: &[(&str, String)],
+) -> anyhow::Result<File> {
+    let mut file_info = serde_json::Map::new();
+    for (key, value) in info {
+        file_info.insert(key.to_string(), value.clone().into());
+    }
+    file_info.insert(
+        "src_last_modified_millis".into(),
+        src_last_modified_millis(file)?.to_string().into(),
+    );
+    for (algorithm, hash) in extra_checksums(job, file)? {
+        file_info.insert(algorithm.info_key().into(), hash.into());
+    }
+
+    let file = fs::File::open(file)?;
+    if sparse::is_sparse(&file, len) {
+        file_info.insert(sparse::INFO_KEY.into(), "true".into());
+    }
+
+    let chunk_size = if cfg.low_memory {
+        LOW_MEMORY_PART_SIZE
+    } else {
+        cfg.part_size()?
+    };
+    // Each block may be a part of its own, so there can't be more blocks than b2 takes parts
+    let mut chunk_size = std::cmp::max(chunk_size, len.div_ceil(MAX_PARTS));
+    if len / chunk_size < 2 {
+        // split it into two chunks or chunks of the minimum part size if that's bigger
+        chunk_size = std::cmp::max(len / 2 + 100, cfg.min_part_size());
+    }
+    let chunks = len.div_ceil(chunk_size);
+
+    if chunks < 2 {
+        bail!("Not enough data to upload by parts");
+    }
+
+    // With `--delta`, the part sha1s are needed up front to compare with the existing file, and
+    // are stored in the file info so that later uploads can reuse the parts that haven't changed.
+    // Otherwise the file is only read once, hashing each part as it is sent.
+    let shas = if job.delta {
+        delta::part_sha1s(&file, len, chunk_size)?
+    } else {
+        Vec::new()
+    };
+    let joined = shas.join(",");
+    if !shas.is_empty() && joined.len() <= delta::MAX_PART_SHA1S_LEN {
+        file_info.insert(delta::PART_SIZE_KEY.into(), chunk_size.to_string().into());
+        file_info.insert(delta::PART_SHA1S_KEY.into(), joined.into());
+    }
+
+    let existing = if job.delta {
+        delta::remote_parts(cfg, dest, chunk_size)?
+    } else {
+        None
+    };
+
+    let res: serde_json::Value = cfg.send_request_de(|cfg| {
+        Ok(cfg
+            .post("b2_start_large_file")?
+            .json(&serde_json::json!({
+                "bucketId": bucket_id,
+                "fileName": dest.key,
+                "contentType": content_type(job, cfg, &dest.key),
+                "fileInfo": file_info,
+            }))
+            .send()?)
+    })?;
+    cfg.forget_lists(Some(bucket_id));
+
+    let file_id = res["fileId"].as_str().unwrap();
+
+    // TODO: Parallelise this stuff
+
+    let res: serde_json::Value = cfg.send_request_de(|cfg| {
+        Ok(cfg
+            .get("b2_get_upload_part_url")?
+            .query(&[("fileId", file_id)])
+            .send()?)
+    })?;
+
+    let upload_url = res["uploadUrl"].as_str().unwrap();
+    let auth = res["authorizationToken"].as_str().unwrap();
+
+    job.progress.start(Some(len as usize), "Uploading");
+    let mut total = 0;
+    let mut copied = 0;
+    // Only what is actually sent counts towards the speed, not parts copied server-side
+    let mut uploaded = 0;
+    let upload_start = Instant::now();
+    let mut part_shas = Vec::with_capacity(chunks as usize);
+    let mut part_lens = Vec::with_capacity(chunks as usize);
+
+    let unchanged = |block: usize| {
+        existing
+            .as_ref()
+            .is_some_and(|(_, remote)| remote.get(block) == Some(&shas[block]))
+    };
+    // With `--low-memory`, only one part is held at a time and parts never grow past one block
+    let (buffers, max_part_size) = if cfg.low_memory {
+        (1, chunk_size)
+    } else {
+        (PART_BUFFERS, MAX_PART_SIZE)
+    };
+    let sizer = PartSizer::new(chunk_size, max_part_size);
+
+    let sent = std::thread::scope(|s| -> anyhow::Result<()> {
+        let (full_tx, full_rx) = mpsc::sync_channel(buffers);
+        let (empty_tx, empty_rx) = mpsc::channel();
+        for _ in 0..buffers {
+            empty_tx.send(Vec::new())?;
+        }
+
+        // Read and hash the next parts while the current one is being sent, so that the upload
+        // doesn't stall on the disk. Once the sending below stops, for whatever reason, the
+        // channels are dropped and this stops too.
+        let (file, shas, sizer, unchanged) = (&file, &shas, &sizer, &unchanged);
+        let blocks = chunks as usize;
+        s.spawn(move || {
+            let mut block = 0;
+            while block < blocks {
+                if unchanged(block) {
+                    if full_tx.send(Ok(Part::Copy { block })).is_err() {
+                        break;
+                    }
+                    block += 1;
+                    continue;
+                }
+
+                let Ok(buf) = empty_rx.recv() else {
+                    break;
+                };
+                let mut end = block + 1;
+                while end < blocks && end - block < sizer.blocks() && !unchanged(end) {
+                    end += 1;
+                }
+                let part = read_blocks(file, buf, block..end, chunk_size, len, shas);
+                if full_tx.send(part).is_err() {
+                    break;
+                }
+                block = end;
+            }
+        });
+
+        for part in full_rx {
+            cancel::check()?;
+            match part? {
+                Part::Copy { block } => {
+                    let (source_id, _) = existing.as_ref().unwrap();
+                    let offset = chunk_size * block as u64;
+                    let end = std::cmp::min(offset + chunk_size, len);
+                    delta::copy_part(cfg, source_id, file_id, part_shas.len() + 1, offset, end)?;
+                    part_shas.push(shas[block].clone());
+                    part_lens.push(end - offset);
+
+                    copied += end - offset;
+                    total += (end - offset) as usize;
+                }
+                Part::Upload { buf, len, sha } => {
+                    let buf = Arc::new(buf);
+                    let mut failures = 0;
+                    let start = Instant::now();
+                    loop {
+                        let res: anyhow::Result<serde_json::Value> = cfg.send_request_de(|cfg| {
+                            let body = Throttled::new(
+                                Cursor::new(SharedPart(buf.clone(), len)),
+                                cfg.bandwidth.clone(),
+                            );
+                            Ok(http::client()
+                                .post(upload_url)
+                                .header("Authorization", auth)
+                                .header("X-Bz-Part-Number", part_shas.len() + 1)
+                                .header("Content-Length", len)
+                                .header("X-Bz-Content-Sha1", &sha)
+                                .body(reqwest::Body::sized(body, len as u64))
+                                .send()?)
+                        });
+                        match res {
+                            Ok(_) => break,
+                            Err(_) if failures < PART_RETRIES && !cancel::is_cancelled() => {
+                                failures += 1
+                            }
+                            Err(e) => return Err(e),
+                        }
+                    }
+                    let elapsed = start.elapsed();
+                    sizer.record(len as u64, elapsed, failures);
+                    part_shas.push(sha);
+                    part_lens.push(len as u64);
+
+                    total += len;
+                    uploaded += len as u64;
+                    let overall = speed(uploaded, upload_start.elapsed());
+                    job.progress.info(
+                        &format!("Part {}", part_shas.len()),
+                        &format!(
+                            "{} at {}/s, {}/s overall",
+                            humanize_bytes_decimal!(len),
+                            humanize_bytes_decimal!(speed(len as u64, elapsed)),
+                            humanize_bytes_decimal!(overall)
+                        ),
+                    );
+                    job.progress
+                        .label(&format!("{}/s", humanize_bytes_decimal!(overall)));
+                    // The reader may have finished already, in which case the buffer isn't needed.
+                    // If the client is somehow still holding on to the body, the reader gets a new
+                    // buffer instead.
+                    let _ = empty_tx.send(Arc::try_unwrap(buf).unwrap_or_default());
+                }
+            }
+            job.progress.set(total);
+        }
+
+        Ok(())
+    });
+
+    job.progress.finish();
+
+    if let Err(e) = sent {
+        if cancel::is_cancelled() {
+            cancel_large_file(cfg, file_id)?;
+        }
+        return Err(e);
+    }
+
+    if copied > 0 {
+        println!(
+            "Reused {} from the existing file",
+            humanize_bytes_decimal!(copied)
+        );
+    }
+
+    if verifies(job) {
+        if let Err(e) = verify_parts(cfg, file_id, dest, &part_shas, &part_lens) {
+            cancel_large_file(cfg, file_id)?;
+            return Err(e);
+        }
+    }
+
+    let file: File = cfg.send_request_de(|cfg| {
+        Ok(cfg
+            .post("b2_finish_large_file")?
+            .json(&serde_json::json!({
+                "fileId": file_id,
+                "partSha1Array": part_shas,
+            }))
+            .send()?)
+    })?;
+    cfg.forget_lists(Some(bucket_id));
+    Ok(file)
+}
+
+/// Check the parts that b2 has of the unfinished large file `file_id` against the sha1 and
+/// length of each part that was read from the local file.  This has to happen before the file is
+/// finished, since b2 only lists the parts until then.
+fn verify_parts(
+    cfg: &mut Config,
+    file_id: &str,
+    dest: &RemotePath,
+    shas: &[String],
+    lens: &[u64],
+) -> anyhow::Result<()> {
+    let mut remote = Vec::with_capacity(shas.len());
+    let mut start = None;
+    loop {
+        let mut query = vec![
+            ("fileId", file_id.to_string()),
+            ("maxPartCount", "1000".into()),
+        ];
+        if let Some(start) = start {
+            query.push(("startPartNumber", start));
+        }
+        let mut res: serde_json::Value =
+            cfg.send_request_de(|cfg| Ok(cfg.get("b2_list_parts")?.query(&query).send()?))?;
+        if let serde_json::Value::Array(parts) = res["parts"].take() {
+            remote.extend(parts);
+        }
+        match res["nextPartNumber"].as_u64() {
+            Some(next) => start = Some(next.to_string()),
+            None => break,
+        }
+    }
+
+    if remote.len() != shas.len() {
+        bail!(
+            "Verification of {} failed: sent {} parts, but b2 has {}",
+            dest,
+            shas.len(),
+            remote.len()
+        );
+    }
+    for (i, part) in remote.iter().enumerate() {
+        let len = part["contentLength"].as_u64();
+        let sha = part["contentSha1"].as_str().unwrap_or("none");
+        if len != Some(lens[i]) || !sha.eq_ignore_ascii_case(&shas[i]) {
+            bail!(
+                "Verification of {} failed: part {} was sent as {} bytes with sha1 {}, but b2 has \
+                 {} bytes with sha1 {}",
+                dest,
+                i + 1,
+                lens[i],
+                shas[i],
+                len.map_or("an unknown number of".into(), |l| l.to_string()),
+                sha
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Cancel an unfinished large file, deleting the parts that have been uploaded so far
+pub(super) fn cancel_large_file(cfg: &mut Config, file_id: &str) -> anyhow::Result<()> {
+    let res: serde_json::Value = cfg.send_request_de(|cfg| {
+        Ok(cfg
+            .post("b2_cancel_large_file")?
+            .json(&serde_json::json!({ "fileId": file_id }))
+            .send()?)
+    })?;
+    cfg.forget_lists(res["bucketId"].as_str());
+    Ok(())
+}
+
+/// One part of a large file upload, in the order that the parts are numbered
+enum Part {
+    /// A block which is unchanged from the existing file, so is copied rather than uploaded
+    Copy { block: usize },
+    /// One or more consecutive blocks read from the local file
+    Upload {
+        buf: Vec<u8>,
+        len: usize,
+        sha: String,
+    },
+}
+
+/// Read `blocks` into `buf` and hash them.  If the sha1 of each block was computed before the
+/// upload started (`shas`), they're checked against it, since the file might have changed since
+/// then.
+fn read_blocks(
+    file: &fs::File,
+    mut buf: Vec<u8>,
+    blocks: Range<usize>,
+    block_size: u64,
+    len: u64,
+    shas: &[String],
+) -> anyhow::Result<Part> {
+    let start = block_size * blocks.start as u64;
+    let end = std::cmp::min(block_size * blocks.end as u64, len);
+    buf.resize((end - start) as usize, 0);
+
+    for block in blocks.clone() {
+        let offset = block_size * block as u64;
+        let from = (offset - start) as usize;
+        let to = (std::cmp::min(offset + block_size, end) - start) as usize;
+        delta::read_part(file, &mut buf[from..to], offset, end)?;
+        if shas
+            .get(block)
+            .is_some_and(|sha| delta::sha1(&buf[from..to]) != *sha)
+        {
+            bail!("The file changed while it was being uploaded");
+        }
+    }
+
+    let sha = match shas.get(blocks.start) {
+        Some(sha) if blocks.len() == 1 => sha.clone(),
+        _ => delta::sha1(&buf),
+    };
+    Ok(Part::Upload {
+        len: buf.len(),
+        buf,
+        sha,
+    })
+}
+
+/// Chooses how many blocks go into each part of a large file, starting with one block (the
+/// recommended part size) and growing up to `max_part_size` so that each part takes about
+/// [`TARGET_PART_TIME`] to send at the measured speed, which means fewer requests on fast
+/// connections. Failed parts shrink it again, since less has to be sent again after a failure,
+/// and it won't grow past that size again during the upload.
+struct PartSizer {
+    blocks: AtomicUsize,
+    block_size: u64,
+    /// The most blocks in a part, which shrinks when parts fail
+    max: AtomicUsize,
+}
+
+impl PartSizer {
+    fn new(block_size: u64, max_part_size: u64) -> Self {
+        Self {
+            blocks: AtomicUsize::new(1),
+            block_size,
+            max: AtomicUsize::new(std::cmp::max(1, (max_part_size / block_size) as usize)),
+        }
+    }
+
+    /// How many blocks should go into the next part
+    fn blocks(&self) -> usize {
+        self.blocks.load(Ordering::Relaxed)
+    }
+
+    /// Adjust the size of the next parts after a part of `len` bytes took `elapsed` to send and
+    /// failed `failures` times first
+    fn record(&self, len: u64, elapsed: Duration, failures: usize) {
+        let mut max = self.max.load(Ordering::Relaxed);
+        let blocks = if failures > 0 {
+            max = std::cmp::max(1, self.blocks() / 2);
+            self.max.store(max, Ordering::Relaxed);
+            max
+        } else {
+            let speed = len as f64 / elapsed.as_secs_f64().max(0.001);
+            let want = speed * TARGET_PART_TIME.as_secs_f64() / self.block_size as f64;
+            // Grow at most twofold at a time, since one fast part might be a fluke
+            (want as usize).clamp(1, self.blocks() * 2)
+        };
+        self.blocks
+            .store(std::cmp::min(blocks, max), Ordering::Relaxed);
+    }
+}
+
+/// The Content-Type of the upload, either from the job or guessed from the destination
+fn content_type<'a>(job: &'a TransferJob, cfg: &'a Config, dest: &str) -> &'a str {
+    job.content_type
+        .as_deref()
+        .unwrap_or_else(|| guess_content_type(cfg, dest))
+}
+
+/// Guess the Content-Type of a file from its extension, using the `content_types` in the config
+/// before the usual guesses
+pub(super) fn guess_content_type<'a>(cfg: &'a Config, dest: &str) -> &'a str {
+    let extension = Path::new(dest).extension().and_then(|e| e.to_str());
+    let configured = extension.and_then(|extension| {
+        cfg.content_types
+            .iter()
+            .find(|(k, _)| k.trim_start_matches('.').eq_ignore_ascii_case(extension))
+    });
+    if let Some((_, content_type)) = configured {
+        return content_type;
+    }
+
+    mime_guess::from_path(dest)
+        .first_raw()
+        .unwrap_or("text/plain")
+}
+
+/// Get the modification time of a local file in the format expected by
+/// `src_last_modified_millis`
+fn src_last_modified_millis(file: &Path) -> anyhow::Result<i64> {
+    let modified: chrono::DateTime<chrono::Utc> = fs::metadata(file)?.modified()?.into();
+    Ok(modified.timestamp_millis())
+}
@@ -0,0 +1,41 @@
+use std::time::{Duration, Instant};
+
+use anyhow::bail;
+use colored::Colorize;
+
+use crate::{cancel, config::Config, path::RemotePath};
+
+/// Poll until `path` exists, or until a version other than the current one is uploaded if `new`
+/// is set, giving up after `timeout`
+pub fn wait(
+    cfg: &mut Config,
+    path: &RemotePath,
+    new: bool,
+    interval: Duration,
+    timeout: Duration,
+) -> anyhow::Result<()> {
+    let current = if new {
+        cfg.get_file_fresh(path)?.map(|f| f.file_id)
+    } else {
+        None
+    };
+
+    let start = Instant::now();
+    loop {
+        if let Some(file) = cfg.get_file_fresh(path)? {
+            if current.as_ref() != Some(&file.file_id) {
+                println!("{}", format!("{} is available", path).green());
+                return Ok(());
+            }
+        }
+
+        if start.elapsed() + interval > timeout {
+            bail!(
+                "Timed out after {}s waiting for {}",
+                timeout.as_secs(),
+                path
+            );
+        }
+        cancel::sleep(interval)?;
+    }
+}